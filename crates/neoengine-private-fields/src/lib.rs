@@ -0,0 +1,295 @@
+//! Encryption format for NeoEngine private profile fields.
+//!
+//! A field is encrypted once under a random 32-byte content key, and that
+//! key is wrapped separately for every recipient. Only the outputs of this
+//! crate go on-chain, through `set_private_field` and
+//! `add_private_field_recipient` in `neoengine-profile`.
+//!
+//! - Field: ChaCha20-Poly1305 with a random 12-byte nonce. The associated
+//!   data is the owner's wallet key followed by the field label, so a
+//!   ciphertext cannot be replayed under another field or owner.
+//! - Wrapped key: an ephemeral x25519 key agrees a secret with the
+//!   recipient's published key. SHA-256 over [`WRAP_DOMAIN`], the shared
+//!   secret, the ephemeral key and the recipient key gives a one-time key
+//!   that seals the content key under a zero nonce (48 bytes with the tag).
+//!
+//! ```
+//! use neoengine_private_fields::*;
+//! use rand_core::OsRng;
+//!
+//! let owner = [7u8; 32];
+//! let recipient = StaticSecret::new(OsRng);
+//! let recipient_pubkey = PublicKey::from(&recipient);
+//!
+//! let key = FieldKey::generate(OsRng);
+//! let field = encrypt_field(&key, &owner, "email", b"me@example.com", OsRng).unwrap();
+//! let wrapped = wrap_key(&key, &recipient_pubkey, OsRng);
+//!
+//! let unwrapped = unwrap_key(&wrapped, &recipient).unwrap();
+//! let plaintext = decrypt_field(&unwrapped, &owner, "email", &field).unwrap();
+//! assert_eq!(plaintext, b"me@example.com");
+//! ```
+
+use std::fmt;
+
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use x25519_dalek::EphemeralSecret;
+use zeroize::Zeroize;
+
+pub use x25519_dalek::{PublicKey, StaticSecret};
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+/// Length of a sealed content key, matching `WrappedKey::wrapped_key`
+pub const WRAPPED_KEY_LEN: usize = KEY_LEN + 16;
+/// Domain separator for key wrapping
+pub const WRAP_DOMAIN: &[u8] = b"neoengine-private-field-v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Encryption,
+    Decryption,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Encryption => write!(f, "encryption failed"),
+            Error::Decryption => write!(f, "decryption failed or data was tampered with"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Content key of one private field; zeroed on drop
+pub struct FieldKey([u8; KEY_LEN]);
+
+impl FieldKey {
+    pub fn generate(mut rng: impl RngCore + CryptoRng) -> Self {
+        let mut key = [0u8; KEY_LEN];
+        rng.fill_bytes(&mut key);
+        FieldKey(key)
+    }
+
+    pub fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        FieldKey(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+impl Drop for FieldKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// `nonce` and `ciphertext` of a `PrivateField` account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedField {
+    pub nonce: [u8; NONCE_LEN],
+    pub ciphertext: Vec<u8>,
+}
+
+/// `ephemeral_pubkey` and `wrapped_key` of a `WrappedKey` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub ephemeral_pubkey: [u8; 32],
+    pub wrapped_key: [u8; WRAPPED_KEY_LEN],
+}
+
+/// Encrypt a field value. `owner` is the profile owner's wallet key.
+pub fn encrypt_field(
+    key: &FieldKey,
+    owner: &[u8; 32],
+    label: &str,
+    plaintext: &[u8],
+    mut rng: impl RngCore + CryptoRng,
+) -> Result<EncryptedField, Error> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let aad = field_aad(owner, label);
+    let ciphertext = key
+        .cipher()
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+        .map_err(|_| Error::Encryption)?;
+
+    Ok(EncryptedField { nonce, ciphertext })
+}
+
+pub fn decrypt_field(
+    key: &FieldKey,
+    owner: &[u8; 32],
+    label: &str,
+    field: &EncryptedField,
+) -> Result<Vec<u8>, Error> {
+    let aad = field_aad(owner, label);
+    key.cipher()
+        .decrypt(
+            Nonce::from_slice(&field.nonce),
+            Payload { msg: &field.ciphertext, aad: &aad },
+        )
+        .map_err(|_| Error::Decryption)
+}
+
+/// Wrap a content key for a recipient's published x25519 key
+pub fn wrap_key(
+    key: &FieldKey,
+    recipient: &PublicKey,
+    mut rng: impl RngCore + CryptoRng,
+) -> WrappedKey {
+    let ephemeral = EphemeralSecret::new(&mut rng);
+    let ephemeral_pubkey = PublicKey::from(&ephemeral);
+    let wrapping_key = derive_wrapping_key(
+        ephemeral.diffie_hellman(recipient).as_bytes(),
+        &ephemeral_pubkey,
+        recipient,
+    );
+
+    let sealed = wrapping_key
+        .cipher()
+        .encrypt(Nonce::from_slice(&[0u8; NONCE_LEN]), key.as_bytes().as_ref())
+        .expect("sealing 32 bytes cannot fail");
+    let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+    wrapped_key.copy_from_slice(&sealed);
+
+    WrappedKey {
+        ephemeral_pubkey: ephemeral_pubkey.to_bytes(),
+        wrapped_key,
+    }
+}
+
+/// Recover a content key with the recipient's x25519 secret
+pub fn unwrap_key(wrapped: &WrappedKey, secret: &StaticSecret) -> Result<FieldKey, Error> {
+    let ephemeral_pubkey = PublicKey::from(wrapped.ephemeral_pubkey);
+    let wrapping_key = derive_wrapping_key(
+        secret.diffie_hellman(&ephemeral_pubkey).as_bytes(),
+        &ephemeral_pubkey,
+        &PublicKey::from(secret),
+    );
+
+    let mut opened = wrapping_key
+        .cipher()
+        .decrypt(Nonce::from_slice(&[0u8; NONCE_LEN]), wrapped.wrapped_key.as_ref())
+        .map_err(|_| Error::Decryption)?;
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(&opened);
+    opened.zeroize();
+
+    Ok(FieldKey(key))
+}
+
+fn field_aad(owner: &[u8; 32], label: &str) -> Vec<u8> {
+    [owner.as_ref(), label.as_bytes()].concat()
+}
+
+fn derive_wrapping_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> FieldKey {
+    let digest = Sha256::new()
+        .chain_update(WRAP_DOMAIN)
+        .chain_update(shared)
+        .chain_update(ephemeral.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    FieldKey(digest.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    const OWNER: [u8; 32] = [7u8; 32];
+
+    fn sealed_field() -> (FieldKey, EncryptedField) {
+        let key = FieldKey::generate(OsRng);
+        let field = encrypt_field(&key, &OWNER, "email", b"me@example.com", OsRng).unwrap();
+        (key, field)
+    }
+
+    #[test]
+    fn wrapped_key_is_exactly_wrapped_key_len() {
+        let recipient = StaticSecret::new(OsRng);
+        let key = FieldKey::generate(OsRng);
+        let wrapped = wrap_key(&key, &PublicKey::from(&recipient), OsRng);
+
+        // Must fit `WrappedKey::wrapped_key: [u8; 48]` in neoengine-profile
+        assert_eq!(WRAPPED_KEY_LEN, 48);
+        assert_eq!(wrapped.wrapped_key.len(), WRAPPED_KEY_LEN);
+        let unwrapped = unwrap_key(&wrapped, &recipient).unwrap();
+        assert_eq!(unwrapped.as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn wrong_recipient_cannot_unwrap() {
+        let recipient = StaticSecret::new(OsRng);
+        let other = StaticSecret::new(OsRng);
+        let key = FieldKey::generate(OsRng);
+        let wrapped = wrap_key(&key, &PublicKey::from(&recipient), OsRng);
+
+        assert_eq!(unwrap_key(&wrapped, &other).err(), Some(Error::Decryption));
+    }
+
+    #[test]
+    fn tampered_wrapped_key_is_rejected() {
+        let recipient = StaticSecret::new(OsRng);
+        let key = FieldKey::generate(OsRng);
+        let wrapped = wrap_key(&key, &PublicKey::from(&recipient), OsRng);
+
+        let mut tampered = wrapped.clone();
+        tampered.wrapped_key[0] ^= 1;
+        assert_eq!(unwrap_key(&tampered, &recipient).err(), Some(Error::Decryption));
+
+        let mut tampered = wrapped;
+        tampered.ephemeral_pubkey[0] ^= 1;
+        assert_eq!(unwrap_key(&tampered, &recipient).err(), Some(Error::Decryption));
+    }
+
+    #[test]
+    fn tampered_ciphertext_or_tag_is_rejected() {
+        let (key, field) = sealed_field();
+        assert_eq!(field.ciphertext.len(), b"me@example.com".len() + 16);
+
+        let mut tampered = field.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(decrypt_field(&key, &OWNER, "email", &tampered), Err(Error::Decryption));
+
+        let mut tampered = field.clone();
+        *tampered.ciphertext.last_mut().unwrap() ^= 1;
+        assert_eq!(decrypt_field(&key, &OWNER, "email", &tampered), Err(Error::Decryption));
+
+        let mut tampered = field;
+        tampered.nonce[0] ^= 1;
+        assert_eq!(decrypt_field(&key, &OWNER, "email", &tampered), Err(Error::Decryption));
+    }
+
+    #[test]
+    fn field_is_bound_to_owner_and_label() {
+        let (key, field) = sealed_field();
+
+        assert_eq!(
+            decrypt_field(&key, &OWNER, "email", &field).unwrap(),
+            b"me@example.com"
+        );
+        assert_eq!(decrypt_field(&key, &[8u8; 32], "email", &field), Err(Error::Decryption));
+        assert_eq!(decrypt_field(&key, &OWNER, "phone", &field), Err(Error::Decryption));
+    }
+
+    #[test]
+    fn wrong_field_key_is_rejected() {
+        let (_, field) = sealed_field();
+        let other = FieldKey::generate(OsRng);
+
+        assert_eq!(decrypt_field(&other, &OWNER, "email", &field), Err(Error::Decryption));
+    }
+}
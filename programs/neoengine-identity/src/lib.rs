@@ -1,7 +1,32 @@
+// The generated CPI client mirrors the arity of the instruction handlers
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    token::{Mint, Token, TokenAccount, MintTo, mint_to, FreezeAccount, freeze_account},
-    associated_token::AssociatedToken,
+    token::{
+        Mint, Token, TokenAccount, MintTo, mint_to, FreezeAccount, freeze_account,
+        ThawAccount, thaw_account, Transfer, transfer, CloseAccount, close_account,
+    },
+    token_interface::{
+        self, Mint as InterfaceMint, TokenAccount as InterfaceTokenAccount, TokenInterface,
+    },
+    associated_token::{self, AssociatedToken},
+    token_2022::{spl_token_2022, Token2022},
+    token_interface::spl_token_metadata_interface::state::{Field, TokenMetadata},
+    token_interface::spl_pod::optional_keys::OptionalNonZeroPubkey,
+};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::system_program::{self, Transfer as SystemTransfer};
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as sysvar_instructions, load_current_index_checked, load_instruction_at_checked,
+};
+use mpl_bubblegum::{
+    accounts::TreeConfig,
+    instructions::{CreateTreeConfigCpiBuilder, MintV1CpiBuilder, VerifyLeafCpiBuilder},
+    programs::{SPL_ACCOUNT_COMPRESSION_ID, SPL_NOOP_ID},
+    types::{Creator, LeafSchema, MetadataArgs, TokenProgramVersion, TokenStandard},
+    utils::get_asset_id,
 };
 
 declare_id!("Ao3kUW9s6cQQNEfANM1XAzPYf2EEaCVURzgPGVxFc1eL");
@@ -15,12 +40,30 @@ pub mod neoengine_identity {
         ctx: Context<CreateUsername>, 
         username: String
     ) -> Result<()> {
-        // Validate username
-        require!(username.len() >= 3, ErrorCode::UsernameTooShort);
-        require!(username.len() <= 20, ErrorCode::UsernameTooLong);
+        // Validate against the handle grammar; seeds are derived from the canonical form
+        let username = canonical_username(&username);
+        validate_username(&username)?;
+        let skeleton = username_skeleton(&username);
+        ensure_unclaimed(
+            &ctx.accounts.username_account,
+            &ctx.accounts.handle_skeleton,
+            &ctx.accounts.identity_account,
+        )?;
+
+        ctx.accounts.fee.registry.ensure_open(username.len())?;
+        let fee_paid = ctx.accounts.fee.charge(
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            &ctx.accounts.token_program,
+            username.len(),
+        )?;
+        ctx.accounts.fee.registry.total_usernames += 1;
+        let handle_id = ctx.accounts.fee.registry.assign_handle_id();
 
-        let username_account = &mut ctx.accounts.username_account;
         let clock = Clock::get()?;
+        ensure_not_quarantined(&ctx.accounts.tombstone, clock.unix_timestamp)?;
+
+        let username_account = &mut ctx.accounts.username_account;
 
         // Store the bump before borrowing
         let bump = ctx.bumps.username_account;
@@ -31,50 +74,226 @@ pub mod neoengine_identity {
         username_account.mint = ctx.accounts.mint.key();
         username_account.created_at = clock.unix_timestamp;
         username_account.bump = bump;
+        username_account.last_renamed_at = 0;
+        username_account.previous_usernames = Vec::new();
+        username_account.standard = SoulboundStandard::FrozenSpl;
+        username_account.compressed_leaf = None;
+        username_account.suspended = false;
+        username_account.handle_id = handle_id;
 
-        // Mint 1 token to user
-        let mint_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            MintTo {
-                mint: ctx.accounts.mint.to_account_info(),
-                to: ctx.accounts.token_account.to_account_info(),
-                authority: ctx.accounts.username_account.to_account_info(),
-            },
-        );
+        // Reserve the confusable skeleton so look-alike handles collide
+        let handle_skeleton = &mut ctx.accounts.handle_skeleton;
+        handle_skeleton.handle_account = username_account.key();
+        handle_skeleton.skeleton = skeleton;
+        handle_skeleton.bump = ctx.bumps.handle_skeleton;
+
+        // Reverse record: one identity per wallet
+        let identity_account = &mut ctx.accounts.identity_account;
+        identity_account.wallet = ctx.accounts.user.key();
+        identity_account.username_account = username_account.key();
+        identity_account.username = username.clone();
+        identity_account.active_username_nft = None;
+        identity_account.created_at = clock.unix_timestamp;
+        identity_account.bump = ctx.bumps.identity_account;
+        identity_account.last_challenge_nonce = 0;
 
+        // Mint 1 frozen token to user
         let username_seeds = &[
             b"username",
             username.as_bytes(),
             &[bump],
         ];
-        let signer_seeds = &[&username_seeds[..]];
+        mint_soulbound_token(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_account,
+            &ctx.accounts.user,
+            &ctx.accounts.username_account,
+            &[&username_seeds[..]],
+        )?;
+
+        emit!(UsernameCreated {
+            owner: ctx.accounts.user.key(),
+            username: username.clone(),
+            mint: ctx.accounts.mint.key(),
+            fee_paid,
+            timestamp: clock.unix_timestamp,
+        });
 
-        mint_to(mint_ctx.with_signer(signer_seeds), 1)?;
+        Ok(())
+    }
 
-        // Freeze the token account to make it soulbound
-        let freeze_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            FreezeAccount {
-                account: ctx.accounts.token_account.to_account_info(),
+    /// Create a soulbound username on a Token-2022 mint with the
+    /// NonTransferable extension. The handle is stored in on-mint token
+    /// metadata (metadata pointer to the mint itself) so wallets show it
+    /// natively. NonTransferable keeps the token in place, so it is neither
+    /// frozen nor delegated; the username PDA is the mint's permanent
+    /// delegate so recovery can still burn it. A reused mint gets its
+    /// metadata name and uri rewritten.
+    pub fn create_username_token_2022(
+        ctx: Context<CreateUsernameToken2022>,
+        username: String,
+        uri: String,
+    ) -> Result<()> {
+        let username = canonical_username(&username);
+        validate_username(&username)?;
+        require!(uri.len() <= MAX_METADATA_URI_LEN, ErrorCode::InvalidMetadataUri);
+        let skeleton = username_skeleton(&username);
+        ensure_unclaimed(
+            &ctx.accounts.username_account,
+            &ctx.accounts.handle_skeleton,
+            &ctx.accounts.identity_account,
+        )?;
+
+        let clock = Clock::get()?;
+        ensure_not_quarantined(&ctx.accounts.tombstone, clock.unix_timestamp)?;
+
+        ctx.accounts.fee.registry.ensure_open(username.len())?;
+        let fee_paid = ctx.accounts.fee.charge(
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            &ctx.accounts.token_program,
+            username.len(),
+        )?;
+        ctx.accounts.fee.registry.total_usernames += 1;
+        let handle_id = ctx.accounts.fee.registry.assign_handle_id();
+
+        let bump = ctx.bumps.username_account;
+        let username_key = ctx.accounts.username_account.key();
+        let username_seeds = &[
+            b"username",
+            username.as_bytes(),
+            &[bump],
+        ];
+
+        // A released handle keeps its Token-2022 mint; reuse it
+        if ctx.accounts.mint.data_is_empty() {
+            initialize_non_transferable_mint(&ctx, &username, &uri, username_key, &[&username_seeds[..]])?;
+        } else {
+            update_handle_metadata(&ctx, &username, &uri, &[&username_seeds[..]])?;
+        }
+
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.user.to_account_info(),
+                associated_token: ctx.accounts.token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
                 mint: ctx.accounts.mint.to_account_info(),
-                authority: ctx.accounts.username_account.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_2022_program.to_account_info(),
             },
-        );
+        ))?;
+
+        let username_account = &mut ctx.accounts.username_account;
+        username_account.owner = ctx.accounts.user.key();
+        username_account.username = username.clone();
+        username_account.mint = ctx.accounts.mint.key();
+        username_account.created_at = clock.unix_timestamp;
+        username_account.bump = bump;
+        username_account.last_renamed_at = 0;
+        username_account.previous_usernames = Vec::new();
+        username_account.standard = SoulboundStandard::NonTransferable2022;
+        username_account.compressed_leaf = None;
+        username_account.suspended = false;
+        username_account.handle_id = handle_id;
+
+        let handle_skeleton = &mut ctx.accounts.handle_skeleton;
+        handle_skeleton.handle_account = username_key;
+        handle_skeleton.skeleton = skeleton;
+        handle_skeleton.bump = ctx.bumps.handle_skeleton;
+
+        let identity_account = &mut ctx.accounts.identity_account;
+        identity_account.wallet = ctx.accounts.user.key();
+        identity_account.username_account = username_key;
+        identity_account.username = username.clone();
+        identity_account.active_username_nft = None;
+        identity_account.created_at = clock.unix_timestamp;
+        identity_account.bump = ctx.bumps.identity_account;
+        identity_account.last_challenge_nonce = 0;
 
-        freeze_account(freeze_ctx.with_signer(signer_seeds))?;
+        mint_non_transferable_token(
+            &ctx.accounts.token_2022_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_account,
+            ctx.accounts.username_account.as_ref(),
+            &[&username_seeds[..]],
+        )?;
 
         emit!(UsernameCreated {
             owner: ctx.accounts.user.key(),
-            username: username.clone(),
+            username,
             mint: ctx.accounts.mint.key(),
+            fee_paid,
             timestamp: clock.unix_timestamp,
         });
 
         Ok(())
     }
 
+    /// Initialize the global username registry (program upgrade authority only)
+    pub fn initialize_registry(
+        ctx: Context<InitializeRegistry>,
+        config: RegistryConfig,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.username_registry;
+        registry.apply_config(config)?;
+        registry.total_usernames = 0;
+        registry.total_username_nfts = 0;
+        registry.next_handle_id = 1;
+        registry.bump = ctx.bumps.username_registry;
+
+        emit!(RegistryConfigUpdated {
+            authority: registry.authority,
+            treasury: registry.treasury,
+            fee_currency: registry.fee_currency,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Update registry admin, treasury and pricing (admin only)
+    pub fn update_registry_config(
+        ctx: Context<UpdateRegistryConfig>,
+        config: RegistryConfig,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.username_registry;
+        registry.apply_config(config)?;
+
+        emit!(RegistryConfigUpdated {
+            authority: registry.authority,
+            treasury: registry.treasury,
+            fee_currency: registry.fee_currency,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Check a username against the handle grammar without creating it.
+    /// Returns the canonical handle, the skeleton used for collision checks
+    /// and whether the skeleton is free and out of quarantine.
+    pub fn check_username(ctx: Context<CheckUsername>, username: String) -> Result<UsernameCheck> {
+        let canonical = canonical_username(&username);
+        validate_username(&canonical)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let quarantine_until = active_quarantine(&ctx.accounts.tombstone, now)?;
+
+        Ok(UsernameCheck {
+            skeleton: username_skeleton(&canonical),
+            canonical,
+            available: ctx.accounts.handle_skeleton.data_is_empty() && quarantine_until.is_none(),
+            quarantine_until,
+        })
+    }
+
     /// Get username info
-    pub fn get_username_info(ctx: Context<GetUsernameInfo>) -> Result<UsernameInfo> {
+    pub fn get_username_info(
+        ctx: Context<GetUsernameInfo>,
+        _username: String,
+    ) -> Result<UsernameInfo> {
         let username_account = &ctx.accounts.username_account;
         
         Ok(UsernameInfo {
@@ -83,113 +302,5092 @@ pub mod neoengine_identity {
             created_at: username_account.created_at,
         })
     }
-}
 
-// ============================================================================
-// Account Structs
-// ============================================================================
+    /// Look up the identity owned by a wallet
+    pub fn get_identity_by_owner(
+        ctx: Context<GetIdentityByOwner>,
+        _owner: Pubkey,
+    ) -> Result<UsernameInfo> {
+        let identity_account = &ctx.accounts.identity_account;
 
-#[account]
-pub struct UsernameAccount {
-    pub owner: Pubkey,        // 32 bytes
-    pub username: String,     // 4 + max 20 bytes = 24 bytes
-    pub mint: Pubkey,         // 32 bytes
-    pub created_at: i64,      // 8 bytes
-    pub bump: u8,             // 1 byte
-}
+        Ok(UsernameInfo {
+            owner: identity_account.wallet,
+            username: identity_account.username.clone(),
+            created_at: identity_account.created_at,
+        })
+    }
 
-impl UsernameAccount {
-    pub const MAX_SIZE: usize = 32 + 24 + 32 + 8 + 1 + 50; // 147 bytes with buffer
-}
+    /// Claim a tradable username NFT. Shares the handle namespace with
+    /// soulbound usernames but the token is not frozen until activated.
+    pub fn claim_username_nft(
+        ctx: Context<ClaimUsernameNft>,
+        username: String,
+    ) -> Result<()> {
+        let username = canonical_username(&username);
+        validate_username(&username)?;
+        let skeleton = username_skeleton(&username);
 
-// ============================================================================
-// Context Structs
-// ============================================================================
+        ctx.accounts.fee.registry.ensure_open(username.len())?;
+        let fee_paid = ctx.accounts.fee.charge(
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            &ctx.accounts.token_program,
+            username.len(),
+        )?;
+        ctx.accounts.fee.registry.total_username_nfts += 1;
 
-#[derive(Accounts)]
-#[instruction(username: String)]
-pub struct CreateUsername<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+        let clock = Clock::get()?;
+        ensure_not_quarantined(&ctx.accounts.tombstone, clock.unix_timestamp)?;
 
-    #[account(
-        init,
-        payer = user,
-        space = 8 + UsernameAccount::MAX_SIZE,
-        seeds = [b"username", username.as_bytes()],
-        bump,
-    )]
-    pub username_account: Account<'info, UsernameAccount>,
+        let username_nft = &mut ctx.accounts.username_nft;
+        let bump = ctx.bumps.username_nft;
 
-    #[account(
-        init,
-        payer = user,
-        mint::decimals = 0,
-        mint::authority = username_account,
-        mint::freeze_authority = username_account,
-        seeds = [b"mint", username.as_bytes()],
-        bump,
-    )]
-    pub mint: Account<'info, Mint>,
+        username_nft.username = username.clone();
+        username_nft.mint = ctx.accounts.mint.key();
+        username_nft.claimed_by = ctx.accounts.user.key();
+        username_nft.active = false;
+        username_nft.assigned_identity = None;
+        username_nft.created_at = clock.unix_timestamp;
+        username_nft.bump = bump;
 
-    #[account(
-        init,
-        payer = user,
-        associated_token::mint = mint,
-        associated_token::authority = user,
-    )]
-    pub token_account: Account<'info, TokenAccount>,
+        let handle_skeleton = &mut ctx.accounts.handle_skeleton;
+        handle_skeleton.handle_account = username_nft.key();
+        handle_skeleton.skeleton = skeleton;
+        handle_skeleton.bump = ctx.bumps.handle_skeleton;
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
+        let nft_seeds = &[
+            b"username_nft",
+            username.as_bytes(),
+            &[bump],
+        ];
+        let signer_seeds = &[&nft_seeds[..]];
 
-#[derive(Accounts)]
-#[instruction(username: String)]
-pub struct GetUsernameInfo<'info> {
-    #[account(
-        seeds = [b"username", username.as_bytes()],
-        bump = username_account.bump
-    )]
-    pub username_account: Account<'info, UsernameAccount>,
-}
+        mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.token_account.to_account_info(),
+                    authority: ctx.accounts.username_nft.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
 
-// ============================================================================
-// Return Types
-// ============================================================================
+        emit!(UsernameNftClaimed {
+            owner: ctx.accounts.user.key(),
+            username,
+            mint: ctx.accounts.mint.key(),
+            fee_paid,
+            timestamp: clock.unix_timestamp,
+        });
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct UsernameInfo {
-    pub owner: Pubkey,
-    pub username: String,
-    pub created_at: i64,
-}
+        Ok(())
+    }
 
-// ============================================================================
-// Events
-// ============================================================================
+    /// Activate a held username NFT and assign it to the holder's identity.
+    /// The token is frozen while active so an active name cannot move.
+    pub fn activate_username_nft(ctx: Context<ActivateUsernameNft>) -> Result<()> {
+        let username_nft = &mut ctx.accounts.username_nft;
+        let identity_account = &mut ctx.accounts.identity_account;
+        let clock = Clock::get()?;
 
-#[event]
-pub struct UsernameCreated {
-    pub owner: Pubkey,
-    pub username: String,
-    pub mint: Pubkey,
-    pub timestamp: i64,
-}
+        username_nft.active = true;
+        username_nft.assigned_identity = Some(identity_account.key());
+        identity_account.active_username_nft = Some(username_nft.key());
 
-// ============================================================================
-// Error Codes
-// ============================================================================
+        let nft_seeds = &[
+            b"username_nft",
+            username_nft.username.as_bytes(),
+            &[username_nft.bump],
+        ];
+        let signer_seeds = &[&nft_seeds[..]];
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Username already exists")]
-    UsernameAlreadyExists,
-    #[msg("Username too short (minimum 3 characters)")]
-    UsernameTooShort,
-    #[msg("Username too long (maximum 20 characters)")]
-    UsernameTooLong,
-    #[msg("Invalid username format")]
-    InvalidUsernameFormat,
-}
\ No newline at end of file
+        freeze_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            FreezeAccount {
+                account: ctx.accounts.token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                authority: username_nft.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        emit!(UsernameNftActivated {
+            owner: ctx.accounts.user.key(),
+            identity: identity_account.key(),
+            username: username_nft.username.clone(),
+            mint: username_nft.mint,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Deactivate a username NFT, unassigning it and thawing the token
+    pub fn deactivate_username_nft(ctx: Context<DeactivateUsernameNft>) -> Result<()> {
+        let username_nft = &mut ctx.accounts.username_nft;
+        let identity_account = &mut ctx.accounts.identity_account;
+        let clock = Clock::get()?;
+
+        username_nft.active = false;
+        username_nft.assigned_identity = None;
+        identity_account.active_username_nft = None;
+
+        let nft_seeds = &[
+            b"username_nft",
+            username_nft.username.as_bytes(),
+            &[username_nft.bump],
+        ];
+        let signer_seeds = &[&nft_seeds[..]];
+
+        thaw_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            ThawAccount {
+                account: ctx.accounts.token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                authority: username_nft.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        emit!(UsernameNftDeactivated {
+            owner: ctx.accounts.user.key(),
+            identity: identity_account.key(),
+            username: username_nft.username.clone(),
+            mint: username_nft.mint,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Transfer an inactive username NFT to another wallet
+    pub fn transfer_username_nft(ctx: Context<TransferUsernameNft>) -> Result<()> {
+        transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.from_token_account.to_account_info(),
+                    to: ctx.accounts.to_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        emit!(UsernameNftTransferred {
+            from: ctx.accounts.user.key(),
+            to: ctx.accounts.recipient.key(),
+            username: ctx.accounts.username_nft.username.clone(),
+            mint: ctx.accounts.username_nft.mint,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+    /// Register recovery guardians and the approval threshold for a handle
+    pub fn configure_recovery(
+        ctx: Context<ConfigureRecovery>,
+        guardians: Vec<Pubkey>,
+        threshold: u8,
+        timelock_seconds: i64,
+    ) -> Result<()> {
+        require!(!guardians.is_empty(), ErrorCode::InvalidGuardianSet);
+        require!(guardians.len() <= MAX_GUARDIANS, ErrorCode::InvalidGuardianSet);
+        require!(
+            threshold >= 1 && threshold as usize <= guardians.len(),
+            ErrorCode::InvalidRecoveryThreshold
+        );
+        require!(
+            timelock_seconds >= MIN_RECOVERY_TIMELOCK,
+            ErrorCode::RecoveryTimelockTooShort
+        );
+        for (i, guardian) in guardians.iter().enumerate() {
+            require!(!guardians[..i].contains(guardian), ErrorCode::InvalidGuardianSet);
+            require!(*guardian != ctx.accounts.owner.key(), ErrorCode::InvalidGuardianSet);
+        }
+
+        let recovery_config = &mut ctx.accounts.recovery_config;
+        recovery_config.handle_id = ctx.accounts.username_account.handle_id;
+        recovery_config.guardians = guardians.clone();
+        recovery_config.threshold = threshold;
+        recovery_config.timelock_seconds = timelock_seconds;
+        recovery_config.bump = ctx.bumps.recovery_config;
+
+        emit!(RecoveryConfigured {
+            username: ctx.accounts.username_account.username.clone(),
+            guardians,
+            threshold,
+            timelock_seconds,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Open a recovery request moving a handle to a new wallet (guardian only)
+    pub fn initiate_recovery(
+        ctx: Context<InitiateRecovery>,
+        new_wallet: Pubkey,
+    ) -> Result<()> {
+        require!(
+            new_wallet != ctx.accounts.username_account.owner,
+            ErrorCode::InvalidRecoveryWallet
+        );
+
+        let recovery_config = &ctx.accounts.recovery_config;
+        let recovery_request = &mut ctx.accounts.recovery_request;
+        let clock = Clock::get()?;
+
+        recovery_request.handle_id = ctx.accounts.username_account.handle_id;
+        recovery_request.new_wallet = new_wallet;
+        recovery_request.initiator = ctx.accounts.guardian.key();
+        recovery_request.approvals = vec![ctx.accounts.guardian.key()];
+        recovery_request.initiated_at = clock.unix_timestamp;
+        recovery_request.threshold_reached_at = None;
+        recovery_request.bump = ctx.bumps.recovery_request;
+        recovery_request.record_threshold(recovery_config, clock.unix_timestamp);
+
+        emit!(RecoveryInitiated {
+            username: ctx.accounts.username_account.username.clone(),
+            guardian: ctx.accounts.guardian.key(),
+            new_wallet,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Approve a pending recovery request (guardian only)
+    pub fn approve_recovery(ctx: Context<ApproveRecovery>) -> Result<()> {
+        let guardian = ctx.accounts.guardian.key();
+        let recovery_config = &ctx.accounts.recovery_config;
+        let recovery_request = &mut ctx.accounts.recovery_request;
+        let clock = Clock::get()?;
+
+        require!(
+            !recovery_request.approvals.contains(&guardian),
+            ErrorCode::RecoveryAlreadyApproved
+        );
+        recovery_request.approvals.push(guardian);
+        recovery_request.record_threshold(recovery_config, clock.unix_timestamp);
+
+        emit!(RecoveryApproved {
+            username: ctx.accounts.username_account.username.clone(),
+            guardian,
+            new_wallet: recovery_request.new_wallet,
+            approvals: recovery_request.approvals.len() as u8,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel a pending recovery request (current owner only)
+    pub fn cancel_recovery(ctx: Context<CancelRecovery>) -> Result<()> {
+        emit!(RecoveryCancelled {
+            username: ctx.accounts.username_account.username.clone(),
+            new_wallet: ctx.accounts.recovery_request.new_wallet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Execute an approved recovery once the timelock has passed. Burns the
+    /// soulbound token in the lost wallet, re-mints it to the new wallet and
+    /// moves the identity record. A tradable username NFT that was active on
+    /// the old identity stays with the old wallet. A suspended handle cannot
+    /// be recovered until the suspension is lifted.
+    pub fn execute_recovery(ctx: Context<ExecuteRecovery>) -> Result<()> {
+        let clock = Clock::get()?;
+        let recovery_request = &ctx.accounts.recovery_request;
+
+        require!(
+            recovery_request.approval_count(&ctx.accounts.recovery_config)
+                >= ctx.accounts.recovery_config.threshold as usize,
+            ErrorCode::RecoveryThresholdNotMet
+        );
+        let threshold_reached_at = recovery_request
+            .threshold_reached_at
+            .ok_or(ErrorCode::RecoveryThresholdNotMet)?;
+        require!(
+            clock.unix_timestamp >= threshold_reached_at + ctx.accounts.recovery_config.timelock_seconds,
+            ErrorCode::RecoveryTimelockActive
+        );
+
+        let old_wallet = ctx.accounts.username_account.owner;
+        let new_wallet = ctx.accounts.new_wallet.key();
+        let username_key = ctx.accounts.username_account.key();
+        let username = ctx.accounts.username_account.username.clone();
+        let username_seeds = &[
+            b"username",
+            username.as_bytes(),
+            &[ctx.accounts.username_account.bump],
+        ];
+        let signer_seeds = &[&username_seeds[..]];
+
+        // Pull the old token back out of the lost wallet
+        let standard = ctx.accounts.username_account.standard;
+        retire_soulbound_token(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.old_token_account,
+            ctx.accounts.old_token_account.delegate,
+            standard,
+            &ctx.accounts.username_account,
+            signer_seeds,
+        )?;
+        ctx.accounts.mint.reload()?;
+        require!(ctx.accounts.mint.supply == 0, ErrorCode::HandleTokenNotBurned);
+
+        if standard == SoulboundStandard::NonTransferable2022 {
+            mint_non_transferable_token(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.new_token_account,
+                &ctx.accounts.username_account,
+                signer_seeds,
+            )?;
+        } else {
+            mint_soulbound_token(
+                &ctx.accounts.token_program,
+                &ctx.accounts.mint,
+                &ctx.accounts.new_token_account,
+                &ctx.accounts.new_wallet,
+                &ctx.accounts.username_account,
+                signer_seeds,
+            )?;
+        }
+
+        let old_identity = &ctx.accounts.old_identity;
+        let new_identity = &mut ctx.accounts.new_identity;
+        new_identity.wallet = new_wallet;
+        new_identity.username_account = username_key;
+        new_identity.username = username.clone();
+        new_identity.active_username_nft = None;
+        new_identity.created_at = old_identity.created_at;
+        new_identity.bump = ctx.bumps.new_identity;
+        new_identity.last_challenge_nonce = 0;
+
+        ctx.accounts.username_account.owner = new_wallet;
+
+        emit!(RecoveryExecuted {
+            username,
+            old_wallet,
+            new_wallet,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Rename a soulbound handle. The identity moves to the new handle PDA,
+    /// the old handle is tombstoned for the registry quarantine period and
+    /// the old name is appended to the handle history. The handle id moves
+    /// with it, so guardians, records, sub-handles and attestations follow.
+    /// A new name with the same skeleton keeps the skeleton claim and leaves
+    /// no tombstone. Only classic frozen SPL handles can be renamed;
+    /// Token-2022 and compressed handles fail with `RenameUnsupported` and
+    /// have to be burned and registered again.
+    pub fn update_handle(ctx: Context<UpdateHandle>, new_handle: String) -> Result<()> {
+        let new_handle = canonical_username(&new_handle);
+        validate_username(&new_handle)?;
+        let new_skeleton = username_skeleton(&new_handle);
+
+        let clock = Clock::get()?;
+        let old_account = &ctx.accounts.old_username_account;
+        require!(new_handle != old_account.username, ErrorCode::UsernameAlreadyExists);
+        require!(
+            old_account.last_renamed_at == 0
+                || clock.unix_timestamp
+                    >= old_account.last_renamed_at + ctx.accounts.fee.registry.rename_cooldown_seconds,
+            ErrorCode::RenameCooldownActive
+        );
+        ensure_not_quarantined(&ctx.accounts.new_tombstone, clock.unix_timestamp)?;
+
+        ctx.accounts.fee.registry.ensure_open(new_handle.len())?;
+        let fee_paid = ctx.accounts.fee.charge(
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &ctx.accounts.token_program,
+            new_handle.len(),
+        )?;
+
+        let old_handle = old_account.username.clone();
+        let old_seeds = &[
+            b"username",
+            old_handle.as_bytes(),
+            &[old_account.bump],
+        ];
+        retire_soulbound_token(
+            &ctx.accounts.token_program,
+            ctx.accounts.old_mint.as_ref(),
+            ctx.accounts.old_token_account.as_ref(),
+            ctx.accounts.old_token_account.delegate,
+            old_account.standard,
+            ctx.accounts.old_username_account.as_ref(),
+            &[&old_seeds[..]],
+        )?;
+
+        let mut previous_usernames = old_account.previous_usernames.clone();
+        if previous_usernames.len() == MAX_USERNAME_HISTORY {
+            previous_usernames.remove(0);
+        }
+        previous_usernames.push(old_handle.clone());
+
+        let new_account = &mut ctx.accounts.new_username_account;
+        let new_bump = ctx.bumps.new_username_account;
+        new_account.owner = ctx.accounts.owner.key();
+        new_account.username = new_handle.clone();
+        new_account.mint = ctx.accounts.new_mint.key();
+        new_account.created_at = old_account.created_at;
+        new_account.bump = new_bump;
+        new_account.last_renamed_at = clock.unix_timestamp;
+        new_account.previous_usernames = previous_usernames;
+        new_account.standard = SoulboundStandard::FrozenSpl;
+        new_account.compressed_leaf = None;
+        new_account.suspended = false;
+        new_account.handle_id = old_account.handle_id;
+        let new_account_key = new_account.key();
+
+        // A confusable rename keeps the skeleton; anything else releases it
+        let quarantine_until = if new_skeleton == ctx.accounts.old_skeleton.skeleton {
+            ctx.accounts.old_skeleton.handle_account = new_account_key;
+            None
+        } else {
+            let new_skeleton_account = ctx
+                .accounts
+                .new_skeleton
+                .as_mut()
+                .ok_or(ErrorCode::MissingHandleSkeleton)?;
+            new_skeleton_account.handle_account = new_account_key;
+            new_skeleton_account.skeleton = new_skeleton;
+            new_skeleton_account.bump = ctx.bumps.new_skeleton.ok_or(ErrorCode::MissingHandleSkeleton)?;
+
+            let tombstone = ctx
+                .accounts
+                .old_tombstone
+                .as_mut()
+                .ok_or(ErrorCode::MissingTombstone)?;
+            tombstone.skeleton = ctx.accounts.old_skeleton.skeleton.clone();
+            tombstone.username = old_handle.clone();
+            tombstone.former_owner = ctx.accounts.owner.key();
+            tombstone.released_at = clock.unix_timestamp;
+            tombstone.quarantine_until = clock.unix_timestamp + ctx.accounts.fee.registry.quarantine_seconds;
+            tombstone.bump = ctx.bumps.old_tombstone.ok_or(ErrorCode::MissingTombstone)?;
+            let quarantine_until = tombstone.quarantine_until;
+
+            ctx.accounts
+                .old_skeleton
+                .close(ctx.accounts.owner.to_account_info())?;
+            Some(quarantine_until)
+        };
+
+        let identity_account = &mut ctx.accounts.identity_account;
+        identity_account.username_account = new_account_key;
+        identity_account.username = new_handle.clone();
+
+        let new_seeds = &[
+            b"username",
+            new_handle.as_bytes(),
+            &[new_bump],
+        ];
+        mint_soulbound_token(
+            &ctx.accounts.token_program,
+            ctx.accounts.new_mint.as_ref(),
+            ctx.accounts.new_token_account.as_ref(),
+            &ctx.accounts.owner,
+            ctx.accounts.new_username_account.as_ref(),
+            &[&new_seeds[..]],
+        )?;
+
+        emit!(UsernameRenamed {
+            owner: ctx.accounts.owner.key(),
+            old_username: old_handle,
+            new_username: new_handle,
+            mint: ctx.accounts.new_mint.key(),
+            quarantine_until,
+            fee_paid,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// List a reserved handle for auction (registry admin only). The handle's
+    /// skeleton is reserved by the auction until it settles or is cancelled.
+    pub fn create_auction(
+        ctx: Context<CreateAuction>,
+        handle: String,
+        currency: FeeCurrency,
+        reserve_price: u64,
+        min_increment: u64,
+        duration_seconds: i64,
+        extension_window: i64,
+        extension_seconds: i64,
+    ) -> Result<()> {
+        let handle = canonical_username(&handle);
+        validate_username(&handle)?;
+        require!(duration_seconds > 0, ErrorCode::InvalidAuctionParams);
+        require!(min_increment > 0, ErrorCode::InvalidAuctionParams);
+        require!(extension_window >= 0 && extension_seconds >= 0, ErrorCode::InvalidAuctionParams);
+        require!(
+            currency == FeeCurrency::Lamports || ctx.accounts.vault.is_some(),
+            ErrorCode::MissingDsxAccount
+        );
+
+        let clock = Clock::get()?;
+        ensure_not_quarantined(&ctx.accounts.tombstone, clock.unix_timestamp)?;
+
+        let auction = &mut ctx.accounts.auction;
+        auction.handle = handle.clone();
+        auction.creator = ctx.accounts.authority.key();
+        auction.currency = currency;
+        auction.reserve_price = reserve_price;
+        auction.min_increment = min_increment;
+        auction.start_time = clock.unix_timestamp;
+        auction.end_time = clock.unix_timestamp + duration_seconds;
+        auction.extension_window = extension_window;
+        auction.extension_seconds = extension_seconds;
+        auction.highest_bidder = None;
+        auction.highest_bid = 0;
+        auction.bid_count = 0;
+        auction.bump = ctx.bumps.auction;
+
+        let handle_skeleton = &mut ctx.accounts.handle_skeleton;
+        handle_skeleton.handle_account = auction.key();
+        handle_skeleton.skeleton = username_skeleton(&handle);
+        handle_skeleton.bump = ctx.bumps.handle_skeleton;
+
+        emit!(AuctionCreated {
+            handle,
+            currency,
+            reserve_price,
+            min_increment,
+            end_time: auction.end_time,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Place an escrowed bid. The previous highest bidder is refunded and
+    /// late bids extend the auction end. Lamport refunds are paid out
+    /// directly; a DSX refund is moved into the outbid bidder's refund
+    /// escrow, which they withdraw with `claim_bid_refund`, so closing their
+    /// token account cannot block later bids.
+    pub fn place_bid(ctx: Context<PlaceBid>, amount: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let auction = &ctx.accounts.auction;
+
+        require!(clock.unix_timestamp < auction.end_time, ErrorCode::AuctionEnded);
+        require!(
+            ctx.accounts.bidder_identity.data_is_empty(),
+            ErrorCode::IdentityAlreadyExists
+        );
+        match auction.highest_bidder {
+            None => require!(amount >= auction.reserve_price, ErrorCode::BidTooLow),
+            Some(_) => require!(
+                amount >= auction.highest_bid.saturating_add(auction.min_increment),
+                ErrorCode::BidTooLow
+            ),
+        }
+
+        let handle = auction.handle.clone();
+        let auction_seeds = &[
+            b"auction",
+            handle.as_bytes(),
+            &[auction.bump],
+        ];
+        let signer_seeds = &[&auction_seeds[..]];
+
+        match auction.currency {
+            FeeCurrency::Lamports => {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.bidder.to_account_info(),
+                            to: ctx.accounts.auction.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+
+                if ctx.accounts.auction.highest_bidder.is_some() {
+                    let previous_bidder = ctx
+                        .accounts
+                        .previous_bidder
+                        .as_ref()
+                        .ok_or(ErrorCode::InvalidPreviousBidder)?;
+                    let refund = ctx.accounts.auction.highest_bid;
+                    **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()? -= refund;
+                    **previous_bidder.to_account_info().try_borrow_mut_lamports()? += refund;
+                }
+            }
+            FeeCurrency::Dsx => {
+                let vault = ctx.accounts.vault.as_ref().ok_or(ErrorCode::MissingDsxAccount)?;
+                let from = ctx.accounts.bidder_dsx_account.as_ref().ok_or(ErrorCode::MissingDsxAccount)?;
+
+                transfer(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: from.to_account_info(),
+                            to: vault.to_account_info(),
+                            authority: ctx.accounts.bidder.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+
+                if ctx.accounts.auction.highest_bidder.is_some() {
+                    let refund_to = ctx
+                        .accounts
+                        .bid_refund
+                        .as_ref()
+                        .ok_or(ErrorCode::InvalidPreviousBidder)?;
+                    transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            Transfer {
+                                from: vault.to_account_info(),
+                                to: refund_to.to_account_info(),
+                                authority: ctx.accounts.auction.to_account_info(),
+                            },
+                            signer_seeds,
+                        ),
+                        ctx.accounts.auction.highest_bid,
+                    )?;
+                }
+            }
+        }
+
+        let auction = &mut ctx.accounts.auction;
+        auction.highest_bidder = Some(ctx.accounts.bidder.key());
+        auction.highest_bid = amount;
+        auction.bid_count += 1;
+
+        // Anti-sniping: a bid inside the closing window pushes the end back
+        if auction.end_time - clock.unix_timestamp <= auction.extension_window {
+            auction.end_time += auction.extension_seconds;
+        }
+
+        emit!(BidPlaced {
+            handle,
+            bidder: ctx.accounts.bidder.key(),
+            amount,
+            end_time: auction.end_time,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw a DSX bid refund escrowed when the bidder was outbid. The
+    /// escrow is closed to the bidder; it outlives the auction, so the
+    /// auction is named by key.
+    pub fn claim_bid_refund(ctx: Context<ClaimBidRefund>, auction: Pubkey) -> Result<()> {
+        let bidder_key = ctx.accounts.bidder.key();
+        let amount = ctx.accounts.bid_refund.amount;
+        let refund_seeds = &[
+            b"bid_refund",
+            auction.as_ref(),
+            bidder_key.as_ref(),
+            &[ctx.bumps.bid_refund],
+        ];
+        let signer_seeds = &[&refund_seeds[..]];
+
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.bid_refund.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.bid_refund.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.bid_refund.to_account_info(),
+                destination: ctx.accounts.bidder.to_account_info(),
+                authority: ctx.accounts.bid_refund.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        emit!(BidRefundClaimed {
+            auction,
+            bidder: bidder_key,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Settle an ended auction; anyone may call it once the auction ends.
+    /// The escrowed bid goes to the treasury and the handle is minted to the
+    /// winner. A winner who picked up another identity since bidding cannot
+    /// hold the handle, so the bid is refunded and the handle released
+    /// instead. When the winner does not sign, the token is left undelegated
+    /// until they run `migrate_username`.
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= ctx.accounts.auction.end_time,
+            ErrorCode::AuctionNotEnded
+        );
+
+        let handle = ctx.accounts.auction.handle.clone();
+        let winning_bid = ctx.accounts.auction.highest_bid;
+        let auction_seeds = &[
+            b"auction",
+            handle.as_bytes(),
+            &[ctx.accounts.auction.bump],
+        ];
+        let deliver = ctx.accounts.identity_account.wallet == Pubkey::default();
+        // An initialized but unfilled username account would block the handle
+        require!(
+            deliver || (ctx.accounts.username_account.is_none() && ctx.accounts.mint.is_none()),
+            ErrorCode::UnexpectedHandleAccounts
+        );
+
+        match ctx.accounts.auction.currency {
+            FeeCurrency::Lamports => {
+                let to = if deliver {
+                    ctx.accounts.treasury.to_account_info()
+                } else {
+                    ctx.accounts.winner.to_account_info()
+                };
+                **ctx.accounts.auction.to_account_info().try_borrow_mut_lamports()? -= winning_bid;
+                **to.try_borrow_mut_lamports()? += winning_bid;
+            }
+            FeeCurrency::Dsx => {
+                let vault = ctx.accounts.vault.as_ref().ok_or(ErrorCode::MissingDsxAccount)?;
+                let to = if deliver {
+                    ctx.accounts.treasury_dsx_account.as_ref()
+                } else {
+                    ctx.accounts.winner_dsx_account.as_ref()
+                }
+                .ok_or(ErrorCode::MissingDsxAccount)?;
+
+                transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: vault.to_account_info(),
+                            to: to.to_account_info(),
+                            authority: ctx.accounts.auction.to_account_info(),
+                        },
+                        &[&auction_seeds[..]],
+                    ),
+                    winning_bid,
+                )?;
+
+                close_account(CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    CloseAccount {
+                        account: vault.to_account_info(),
+                        destination: ctx.accounts.creator.to_account_info(),
+                        authority: ctx.accounts.auction.to_account_info(),
+                    },
+                    &[&auction_seeds[..]],
+                ))?;
+            }
+        }
+
+        if !deliver {
+            ctx.accounts
+                .handle_skeleton
+                .close(ctx.accounts.creator.to_account_info())?;
+
+            emit!(AuctionRefunded {
+                handle,
+                winner: ctx.accounts.winner.key(),
+                amount: winning_bid,
+                timestamp: clock.unix_timestamp,
+            });
+
+            return Ok(());
+        }
+
+        ctx.accounts.registry.total_usernames += 1;
+        let handle_id = ctx.accounts.registry.assign_handle_id();
+
+        let bump = ctx.bumps.username_account.ok_or(ErrorCode::MissingHandleToken)?;
+        let mint_key = ctx.accounts.mint.as_ref().ok_or(ErrorCode::MissingHandleToken)?.key();
+        let username_account = ctx
+            .accounts
+            .username_account
+            .as_mut()
+            .ok_or(ErrorCode::MissingHandleToken)?;
+        username_account.owner = ctx.accounts.winner.key();
+        username_account.username = handle.clone();
+        username_account.mint = mint_key;
+        username_account.created_at = clock.unix_timestamp;
+        username_account.bump = bump;
+        username_account.last_renamed_at = 0;
+        username_account.previous_usernames = Vec::new();
+        username_account.standard = SoulboundStandard::FrozenSpl;
+        username_account.compressed_leaf = None;
+        username_account.suspended = false;
+        username_account.handle_id = handle_id;
+        let username_key = username_account.key();
+
+        ctx.accounts.handle_skeleton.handle_account = username_key;
+
+        let identity_account = &mut ctx.accounts.identity_account;
+        identity_account.wallet = ctx.accounts.winner.key();
+        identity_account.username_account = username_key;
+        identity_account.username = handle.clone();
+        identity_account.active_username_nft = None;
+        identity_account.created_at = clock.unix_timestamp;
+        identity_account.bump = ctx.bumps.identity_account;
+        identity_account.last_challenge_nonce = 0;
+
+        let (Some(username_account), Some(mint), Some(token_account)) = (
+            ctx.accounts.username_account.as_deref(),
+            ctx.accounts.mint.as_deref(),
+            ctx.accounts.token_account.as_deref(),
+        ) else {
+            return err!(ErrorCode::MissingHandleToken);
+        };
+        let username_seeds = &[
+            b"username",
+            handle.as_bytes(),
+            &[bump],
+        ];
+        mint_soulbound_token(
+            &ctx.accounts.token_program,
+            mint,
+            token_account,
+            &ctx.accounts.winner,
+            username_account,
+            &[&username_seeds[..]],
+        )?;
+
+        emit!(AuctionSettled {
+            handle,
+            winner: ctx.accounts.winner.key(),
+            amount: winning_bid,
+            mint: mint_key,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel an auction that has no bids and release its handle (admin only)
+    pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
+        if let Some(vault) = &ctx.accounts.vault {
+            let auction = &ctx.accounts.auction;
+            let auction_seeds = &[
+                b"auction",
+                auction.handle.as_bytes(),
+                &[auction.bump],
+            ];
+            close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.creator.to_account_info(),
+                    authority: auction.to_account_info(),
+                },
+                &[&auction_seeds[..]],
+            ))?;
+        }
+
+        emit!(AuctionCancelled {
+            handle: ctx.accounts.auction.handle.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+
+    /// Create a program-owned merkle tree for compressed username SBTs.
+    /// The merkle tree account must already be allocated to the account
+    /// compression program with room for `max_depth` and `max_buffer_size`.
+    pub fn initialize_handle_tree(
+        ctx: Context<InitializeHandleTree>,
+        max_depth: u32,
+        max_buffer_size: u32,
+    ) -> Result<()> {
+        let merkle_tree_key = ctx.accounts.merkle_tree.key();
+        let bump = ctx.bumps.handle_tree;
+        let tree_seeds = &[
+            b"handle_tree",
+            merkle_tree_key.as_ref(),
+            &[bump],
+        ];
+
+        // The handle tree PDA is the tree creator, so only this program can mint
+        CreateTreeConfigCpiBuilder::new(&ctx.accounts.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.tree_config.to_account_info())
+            .merkle_tree(&ctx.accounts.merkle_tree.to_account_info())
+            .payer(&ctx.accounts.authority.to_account_info())
+            .tree_creator(&ctx.accounts.handle_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .max_depth(max_depth)
+            .max_buffer_size(max_buffer_size)
+            .public(false)
+            .invoke_signed(&[&tree_seeds[..]])?;
+
+        let clock = Clock::get()?;
+        let handle_tree = &mut ctx.accounts.handle_tree;
+        handle_tree.merkle_tree = merkle_tree_key;
+        handle_tree.total_minted = 0;
+        handle_tree.created_at = clock.unix_timestamp;
+        handle_tree.bump = bump;
+
+        emit!(HandleTreeCreated {
+            merkle_tree: merkle_tree_key,
+            max_depth,
+            max_buffer_size,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create a username backed by a compressed NFT instead of a mint and
+    /// token account. The leaf is owned and delegated to the username PDA,
+    /// which never signs a Bubblegum transfer, so the leaf cannot move; the
+    /// user is recorded as the leaf's (unverified) creator so indexers can
+    /// attribute it. Compressed handles cannot be renamed or recovered and
+    /// fail with `RenameUnsupported` and `UnsupportedTokenStandard`.
+    pub fn create_compressed_username(
+        ctx: Context<CreateCompressedUsername>,
+        username: String,
+        uri: String,
+    ) -> Result<()> {
+        let username = canonical_username(&username);
+        validate_username(&username)?;
+        require!(uri.len() <= MAX_METADATA_URI_LEN, ErrorCode::InvalidMetadataUri);
+        let skeleton = username_skeleton(&username);
+
+        let clock = Clock::get()?;
+        ensure_not_quarantined(&ctx.accounts.tombstone, clock.unix_timestamp)?;
+
+        ctx.accounts.fee.registry.ensure_open(username.len())?;
+        let fee_paid = ctx.accounts.fee.charge(
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            &ctx.accounts.token_program,
+            username.len(),
+        )?;
+        ctx.accounts.fee.registry.total_usernames += 1;
+        let handle_id = ctx.accounts.fee.registry.assign_handle_id();
+
+        // The next leaf's nonce determines the asset id
+        let merkle_tree_key = ctx.accounts.merkle_tree.key();
+        let nonce = TreeConfig::from_bytes(&ctx.accounts.tree_config.try_borrow_data()?)
+            .map_err(|_| ErrorCode::InvalidHandleTree)?
+            .num_minted;
+        let asset_id = get_asset_id(&merkle_tree_key, nonce);
+
+        let handle_tree = &ctx.accounts.handle_tree;
+        let tree_seeds = &[
+            b"handle_tree",
+            merkle_tree_key.as_ref(),
+            &[handle_tree.bump],
+        ];
+        let username_info = ctx.accounts.username_account.to_account_info();
+
+        MintV1CpiBuilder::new(&ctx.accounts.bubblegum_program.to_account_info())
+            .tree_config(&ctx.accounts.tree_config.to_account_info())
+            .leaf_owner(&username_info)
+            .leaf_delegate(&username_info)
+            .merkle_tree(&ctx.accounts.merkle_tree.to_account_info())
+            .payer(&ctx.accounts.user.to_account_info())
+            .tree_creator_or_delegate(&handle_tree.to_account_info())
+            .log_wrapper(&ctx.accounts.log_wrapper.to_account_info())
+            .compression_program(&ctx.accounts.compression_program.to_account_info())
+            .system_program(&ctx.accounts.system_program.to_account_info())
+            .metadata(compressed_username_metadata(&username, uri, ctx.accounts.user.key()))
+            .invoke_signed(&[&tree_seeds[..]])?;
+
+        ctx.accounts.handle_tree.total_minted += 1;
+
+        let username_key = ctx.accounts.username_account.key();
+        let username_account = &mut ctx.accounts.username_account;
+        username_account.owner = ctx.accounts.user.key();
+        username_account.username = username.clone();
+        username_account.mint = asset_id;
+        username_account.created_at = clock.unix_timestamp;
+        username_account.bump = ctx.bumps.username_account;
+        username_account.last_renamed_at = 0;
+        username_account.previous_usernames = Vec::new();
+        username_account.standard = SoulboundStandard::Compressed;
+        username_account.compressed_leaf = Some(CompressedLeaf {
+            merkle_tree: merkle_tree_key,
+            nonce,
+        });
+        username_account.suspended = false;
+        username_account.handle_id = handle_id;
+
+        let handle_skeleton = &mut ctx.accounts.handle_skeleton;
+        handle_skeleton.handle_account = username_key;
+        handle_skeleton.skeleton = skeleton;
+        handle_skeleton.bump = ctx.bumps.handle_skeleton;
+
+        let identity_account = &mut ctx.accounts.identity_account;
+        identity_account.wallet = ctx.accounts.user.key();
+        identity_account.username_account = username_key;
+        identity_account.username = username.clone();
+        identity_account.active_username_nft = None;
+        identity_account.created_at = clock.unix_timestamp;
+        identity_account.bump = ctx.bumps.identity_account;
+        identity_account.last_challenge_nonce = 0;
+
+        emit!(UsernameCreated {
+            owner: ctx.accounts.user.key(),
+            username,
+            mint: asset_id,
+            fee_paid,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Verify that a compressed handle's leaf is in its merkle tree and
+    /// still held by the username PDA. The proof path is passed as remaining accounts; the data and creator
+    /// hashes come from the indexer. Fails if the proof does not match.
+    pub fn verify_compressed_username<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VerifyCompressedUsername<'info>>,
+        root: [u8; 32],
+        data_hash: [u8; 32],
+        creator_hash: [u8; 32],
+    ) -> Result<()> {
+        let username_account = &ctx.accounts.username_account;
+        let leaf_location = username_account
+            .compressed_leaf
+            .ok_or(ErrorCode::UnsupportedTokenStandard)?;
+        let index = u32::try_from(leaf_location.nonce).map_err(|_| ErrorCode::InvalidHandleTree)?;
+
+        let leaf = LeafSchema::V1 {
+            id: username_account.mint,
+            owner: username_account.key(),
+            delegate: username_account.key(),
+            nonce: leaf_location.nonce,
+            data_hash,
+            creator_hash,
+        };
+
+        let proof: Vec<_> = ctx
+            .remaining_accounts
+            .iter()
+            .map(|node| (node, false, false))
+            .collect();
+
+        VerifyLeafCpiBuilder::new(&ctx.accounts.compression_program.to_account_info())
+            .merkle_tree(&ctx.accounts.merkle_tree.to_account_info())
+            .root(root)
+            .leaf(leaf.hash())
+            .index(index)
+            .add_remaining_accounts(&proof)
+            .invoke()?;
+
+        Ok(())
+    }
+
+    /// Set a typed name-service record on a handle. One record PDA per
+    /// record key; setting an existing key overwrites it. Records are keyed
+    /// by the handle id and follow a rename.
+    pub fn set_record(ctx: Context<SetRecord>, value: RecordValue) -> Result<()> {
+        value.validate()?;
+
+        let clock = Clock::get()?;
+        let record_key = value.record_key();
+        let record = &mut ctx.accounts.record;
+        record.handle_id = ctx.accounts.username_account.handle_id;
+        record.key = record_key.clone();
+        record.value = value.clone();
+        record.updated_at = clock.unix_timestamp;
+        record.bump = ctx.bumps.record;
+
+        emit!(RecordSet {
+            username: ctx.accounts.username_account.username.clone(),
+            key: record_key,
+            value,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Delete a record and reclaim its rent
+    pub fn delete_record(ctx: Context<DeleteRecord>, record_key: String) -> Result<()> {
+        emit!(RecordDeleted {
+            username: ctx.accounts.username_account.username.clone(),
+            key: record_key,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Resolve a handle's record, SNS style
+    pub fn resolve_record(
+        ctx: Context<ResolveRecord>,
+        _username: String,
+        _record_key: String,
+    ) -> Result<ResolvedRecord> {
+        let username_account = &ctx.accounts.username_account;
+        let record = &ctx.accounts.record;
+
+        Ok(ResolvedRecord {
+            owner: username_account.owner,
+            username: username_account.username.clone(),
+            key: record.key.clone(),
+            value: record.value.clone(),
+            updated_at: record.updated_at,
+        })
+    }
+
+    /// Set who may claim sub-handles under a parent handle. The parent
+    /// owner can always issue sub-handles regardless of the policy.
+    pub fn configure_subdomains(
+        ctx: Context<ConfigureSubdomains>,
+        mode: SubdomainMode,
+        price_lamports: u64,
+        whitelist: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(whitelist.len() <= MAX_SUBDOMAIN_WHITELIST, ErrorCode::InvalidSubdomainPolicy);
+        require!(
+            mode != SubdomainMode::Paid || price_lamports > 0,
+            ErrorCode::InvalidSubdomainPolicy
+        );
+
+        let policy = &mut ctx.accounts.policy;
+        policy.parent_id = ctx.accounts.parent.handle_id;
+        policy.mode = mode;
+        policy.price_lamports = price_lamports;
+        policy.whitelist = whitelist;
+        policy.bump = ctx.bumps.policy;
+
+        emit!(SubdomainPolicyUpdated {
+            parent: ctx.accounts.parent.username.clone(),
+            mode,
+            price_lamports,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Issue a sub-handle `label.@parent`. The parent owner may issue to
+    /// any recipient; anyone else claims for themselves under the policy.
+    pub fn create_subdomain(ctx: Context<CreateSubdomain>, label: String) -> Result<()> {
+        let label = canonical_username(&label);
+        validate_username(&label)?;
+
+        let authority = ctx.accounts.authority.key();
+        let recipient = ctx.accounts.recipient.key();
+        let parent = &ctx.accounts.parent;
+
+        if authority != parent.owner {
+            require_keys_eq!(recipient, authority, ErrorCode::Unauthorized);
+            let policy = ctx
+                .accounts
+                .policy
+                .as_ref()
+                .ok_or(ErrorCode::SubdomainNotAllowed)?;
+
+            match policy.mode {
+                SubdomainMode::Open => {}
+                SubdomainMode::Whitelist => {
+                    require!(policy.whitelist.contains(&authority), ErrorCode::SubdomainNotAllowed);
+                }
+                SubdomainMode::Paid => {
+                    system_program::transfer(
+                        CpiContext::new(
+                            ctx.accounts.system_program.to_account_info(),
+                            SystemTransfer {
+                                from: ctx.accounts.authority.to_account_info(),
+                                to: ctx.accounts.parent_owner.to_account_info(),
+                            },
+                        ),
+                        policy.price_lamports,
+                    )?;
+                }
+            }
+        }
+
+        let clock = Clock::get()?;
+        let subdomain = &mut ctx.accounts.subdomain;
+        subdomain.parent_id = parent.handle_id;
+        subdomain.label = label.clone();
+        subdomain.owner = recipient;
+        subdomain.mint = None;
+        subdomain.transfer_locked = false;
+        subdomain.created_at = clock.unix_timestamp;
+        subdomain.bump = ctx.bumps.subdomain;
+
+        emit!(SubdomainCreated {
+            parent: parent.username.clone(),
+            label,
+            owner: recipient,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Mint a soulbound token for a sub-handle to its owner. Sub-handles
+    /// with a token can no longer be transferred.
+    pub fn mint_subdomain_token(ctx: Context<MintSubdomainToken>) -> Result<()> {
+        require!(ctx.accounts.mint.supply == 0, ErrorCode::HandleTokenNotBurned);
+
+        let subdomain = &ctx.accounts.subdomain;
+        let parent_id = subdomain.parent_id.to_le_bytes();
+        let subdomain_seeds = &[
+            b"subdomain",
+            parent_id.as_ref(),
+            subdomain.label.as_bytes(),
+            &[subdomain.bump],
+        ];
+
+        mint_soulbound_token(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            &ctx.accounts.token_account,
+            &ctx.accounts.owner,
+            &ctx.accounts.subdomain,
+            &[&subdomain_seeds[..]],
+        )?;
+
+        ctx.accounts.subdomain.mint = Some(ctx.accounts.mint.key());
+
+        Ok(())
+    }
+
+    /// Lock or unlock transfers of a sub-handle (parent owner only)
+    pub fn set_subdomain_transfer_lock(
+        ctx: Context<SetSubdomainTransferLock>,
+        locked: bool,
+    ) -> Result<()> {
+        let subdomain = &mut ctx.accounts.subdomain;
+        subdomain.transfer_locked = locked;
+
+        emit!(SubdomainLockUpdated {
+            parent: ctx.accounts.parent.username.clone(),
+            label: subdomain.label.clone(),
+            locked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Transfer a sub-handle that is neither transfer-locked nor tokenized
+    pub fn transfer_subdomain(ctx: Context<TransferSubdomain>, new_owner: Pubkey) -> Result<()> {
+        let subdomain = &mut ctx.accounts.subdomain;
+        require!(
+            !subdomain.transfer_locked && subdomain.mint.is_none(),
+            ErrorCode::SubdomainTransferLocked
+        );
+
+        let old_owner = subdomain.owner;
+        subdomain.owner = new_owner;
+
+        emit!(SubdomainTransferred {
+            parent: ctx.accounts.parent.username.clone(),
+            label: subdomain.label.clone(),
+            old_owner,
+            new_owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Revoke a sub-handle (parent owner only). A soulbound token, if
+    /// minted, is burned from the holder's account via the delegate.
+    pub fn revoke_subdomain(ctx: Context<RevokeSubdomain>) -> Result<()> {
+        let subdomain = &ctx.accounts.subdomain;
+
+        if subdomain.mint.is_some() {
+            let (Some(mint), Some(token_account)) =
+                (&ctx.accounts.mint, &ctx.accounts.token_account)
+            else {
+                return err!(ErrorCode::MissingSubdomainToken);
+            };
+            let parent_id = subdomain.parent_id.to_le_bytes();
+            let subdomain_seeds = &[
+                b"subdomain",
+                parent_id.as_ref(),
+                subdomain.label.as_bytes(),
+                &[subdomain.bump],
+            ];
+
+            retire_soulbound_token(
+                &ctx.accounts.token_program,
+                mint,
+                token_account,
+                token_account.delegate,
+                SoulboundStandard::FrozenSpl,
+                subdomain,
+                &[&subdomain_seeds[..]],
+            )?;
+        }
+
+        emit!(SubdomainRevoked {
+            parent: ctx.accounts.parent.username.clone(),
+            label: subdomain.label.clone(),
+            owner: subdomain.owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Check that the owner of `handle` signed the identity challenge.
+    /// The transaction must carry an ed25519 program instruction over
+    /// `identity_challenge_message(handle, challenge)`. Returns false if no
+    /// such signature by the current owner is present, the challenge has
+    /// expired or its nonce is not above the last one accepted for the
+    /// owner's identity. An accepted nonce is recorded, so a signature can
+    /// be used once; partner programs call this through CPI.
+    pub fn verify_identity(
+        ctx: Context<VerifyIdentity>,
+        handle: String,
+        challenge: IdentityChallenge,
+    ) -> Result<bool> {
+        require!(
+            !challenge.domain.is_empty() && challenge.domain.len() <= MAX_CHALLENGE_DOMAIN_LEN,
+            ErrorCode::InvalidIdentityChallenge
+        );
+        if Clock::get()?.unix_timestamp >= challenge.expires_at
+            || challenge.nonce <= ctx.accounts.identity_account.last_challenge_nonce
+        {
+            return Ok(false);
+        }
+
+        let owner = ctx.accounts.username_account.owner;
+        let message = identity_challenge_message(&canonical_username(&handle), &challenge);
+        let instructions = ctx.accounts.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)?;
+
+        for index in 0..current_index {
+            let ix = load_instruction_at_checked(index as usize, &instructions)?;
+            if ix.program_id == ed25519_program::ID && ed25519_signed_by(&ix.data, &owner, &message) {
+                ctx.accounts.identity_account.last_challenge_nonce = challenge.nonce;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Set the moderation authority and moderator set. The registry
+    /// authority bootstraps it; afterwards only the moderation authority
+    /// can change it.
+    pub fn configure_moderation(
+        ctx: Context<ConfigureModeration>,
+        authority: Pubkey,
+        moderators: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(moderators.len() <= MAX_MODERATORS, ErrorCode::InvalidModeratorSet);
+
+        let signer = ctx.accounts.signer.key();
+        let config = &mut ctx.accounts.moderation_config;
+        let current_authority = if config.authority == Pubkey::default() {
+            ctx.accounts.registry.authority
+        } else {
+            config.authority
+        };
+        require_keys_eq!(signer, current_authority, ErrorCode::Unauthorized);
+
+        config.authority = authority;
+        config.moderators = moderators.clone();
+        config.bump = ctx.bumps.moderation_config;
+
+        emit!(ModerationConfigUpdated {
+            authority,
+            moderators,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Suspend a handle. A suspended handle cannot change records, rename,
+    /// issue sub-handles or back a new profile until the suspension is lifted.
+    pub fn suspend_handle(ctx: Context<SuspendHandle>, reason_code: u16) -> Result<()> {
+        let clock = Clock::get()?;
+        let suspension = &mut ctx.accounts.suspension;
+        suspension.username_account = ctx.accounts.username_account.key();
+        suspension.reason_code = reason_code;
+        suspension.moderator = ctx.accounts.moderator.key();
+        suspension.suspended_at = clock.unix_timestamp;
+        suspension.status = SuspensionStatus::Suspended;
+        suspension.appeal_uri = String::new();
+        suspension.appealed_at = 0;
+        suspension.bump = ctx.bumps.suspension;
+
+        ctx.accounts.username_account.suspended = true;
+
+        emit!(HandleSuspended {
+            username: ctx.accounts.username_account.username.clone(),
+            reason_code,
+            moderator: ctx.accounts.moderator.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Appeal a suspension (handle owner, once per suspension)
+    pub fn appeal_suspension(ctx: Context<AppealSuspension>, appeal_uri: String) -> Result<()> {
+        require!(
+            !appeal_uri.is_empty() && appeal_uri.len() <= MAX_APPEAL_URI_LEN,
+            ErrorCode::InvalidAppeal
+        );
+
+        let clock = Clock::get()?;
+        let suspension = &mut ctx.accounts.suspension;
+        require!(suspension.status == SuspensionStatus::Suspended, ErrorCode::InvalidAppeal);
+
+        suspension.status = SuspensionStatus::Appealed;
+        suspension.appeal_uri = appeal_uri.clone();
+        suspension.appealed_at = clock.unix_timestamp;
+
+        emit!(SuspensionAppealed {
+            username: ctx.accounts.username_account.username.clone(),
+            appeal_uri,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Resolve a suspension. Lifting it closes the suspension record;
+    /// upholding it rejects any pending appeal.
+    pub fn resolve_suspension(ctx: Context<ResolveSuspension>, lift: bool) -> Result<()> {
+        let clock = Clock::get()?;
+
+        if lift {
+            ctx.accounts.username_account.suspended = false;
+            ctx.accounts
+                .suspension
+                .close(ctx.accounts.moderator.to_account_info())?;
+        } else {
+            ctx.accounts.suspension.status = SuspensionStatus::Upheld;
+        }
+
+        emit!(SuspensionResolved {
+            username: ctx.accounts.username_account.username.clone(),
+            lifted: lift,
+            moderator: ctx.accounts.moderator.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Register an attestation issuer (registry authority only)
+    pub fn register_issuer(ctx: Context<RegisterIssuer>, name: String) -> Result<()> {
+        require!(
+            !name.is_empty() && name.len() <= MAX_ISSUER_NAME_LEN,
+            ErrorCode::InvalidIssuerName
+        );
+
+        let clock = Clock::get()?;
+        let issuer = &mut ctx.accounts.issuer;
+        issuer.issuer = ctx.accounts.issuer_authority.key();
+        issuer.name = name.clone();
+        issuer.active = true;
+        issuer.registered_at = clock.unix_timestamp;
+        issuer.bump = ctx.bumps.issuer;
+
+        emit!(IssuerRegistered {
+            issuer: issuer.issuer,
+            name,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Enable or disable an issuer (registry authority only). Attestations
+    /// from a disabled issuer no longer count as valid.
+    pub fn set_issuer_active(ctx: Context<SetIssuerActive>, active: bool) -> Result<()> {
+        let issuer = &mut ctx.accounts.issuer;
+        issuer.active = active;
+
+        emit!(IssuerStatusUpdated {
+            issuer: issuer.issuer,
+            active,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Attach or refresh an attestation of `schema` on a handle. Re-issuing
+    /// overwrites the previous attestation and clears its revocation.
+    pub fn attest(
+        ctx: Context<Attest>,
+        schema: u32,
+        expires_at: Option<i64>,
+        data_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        if let Some(expires_at) = expires_at {
+            require!(expires_at > clock.unix_timestamp, ErrorCode::InvalidAttestationExpiry);
+        }
+
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.handle_id = ctx.accounts.username_account.handle_id;
+        attestation.issuer = ctx.accounts.issuer_authority.key();
+        attestation.schema = schema;
+        attestation.data_hash = data_hash;
+        attestation.issued_at = clock.unix_timestamp;
+        attestation.expires_at = expires_at;
+        attestation.revoked = false;
+        attestation.bump = ctx.bumps.attestation;
+
+        emit!(AttestationIssued {
+            username: ctx.accounts.username_account.username.clone(),
+            issuer: attestation.issuer,
+            schema,
+            expires_at,
+            data_hash,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Revoke an attestation (issuing wallet only)
+    pub fn revoke_attestation(ctx: Context<RevokeAttestation>) -> Result<()> {
+        let attestation = &mut ctx.accounts.attestation;
+        require!(!attestation.revoked, ErrorCode::AttestationRevoked);
+        attestation.revoked = true;
+
+        emit!(AttestationRevoked {
+            handle_id: attestation.handle_id,
+            issuer: attestation.issuer,
+            schema: attestation.schema,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Whether a handle holds a live attestation of `schema` from an active
+    /// issuer; callable through CPI
+    pub fn has_attestation(ctx: Context<HasAttestation>, _schema: u32) -> Result<bool> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(ctx.accounts.issuer.active && ctx.accounts.attestation.is_valid(now))
+    }
+
+    /// Burn a handle and reclaim its rent. The soulbound token is thawed,
+    /// burned and its token account closed; the username, skeleton and
+    /// identity accounts are closed to the owner. When the registry has a
+    /// quarantine period the tombstone is required and blocks the name
+    /// until it expires. A compressed leaf stays in its tree under the
+    /// username PDA; it no longer verifies once the username account is
+    /// gone, and a re-registration verifies only its own new leaf.
+    /// Guardians, records, sub-handles and attestations are keyed by the
+    /// burned handle id, so they never attach to whoever registers the name
+    /// next.
+    pub fn burn_username(ctx: Context<BurnUsername>) -> Result<()> {
+        let clock = Clock::get()?;
+        let username = ctx.accounts.username_account.username.clone();
+        let username_seeds = &[
+            b"username",
+            username.as_bytes(),
+            &[ctx.accounts.username_account.bump],
+        ];
+
+        if ctx.accounts.username_account.standard != SoulboundStandard::Compressed {
+            let (Some(mint), Some(token_account)) =
+                (&ctx.accounts.mint, &ctx.accounts.token_account)
+            else {
+                return err!(ErrorCode::MissingHandleToken);
+            };
+
+            if token_account.is_frozen() {
+                token_interface::thaw_account(CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::ThawAccount {
+                        account: token_account.to_account_info(),
+                        mint: mint.to_account_info(),
+                        authority: ctx.accounts.username_account.to_account_info(),
+                    },
+                    &[&username_seeds[..]],
+                ))?;
+            }
+
+            if token_account.amount > 0 {
+                token_interface::burn(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        token_interface::Burn {
+                            mint: mint.to_account_info(),
+                            from: token_account.to_account_info(),
+                            authority: ctx.accounts.owner.to_account_info(),
+                        },
+                    ),
+                    token_account.amount,
+                )?;
+            }
+
+            token_interface::close_account(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: token_account.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ))?;
+        }
+
+        let quarantine_seconds = ctx.accounts.registry.quarantine_seconds;
+        let quarantine_until = match ctx.accounts.tombstone.as_mut() {
+            Some(tombstone) => {
+                tombstone.skeleton = ctx.accounts.handle_skeleton.skeleton.clone();
+                tombstone.username = username.clone();
+                tombstone.former_owner = ctx.accounts.owner.key();
+                tombstone.released_at = clock.unix_timestamp;
+                tombstone.quarantine_until = clock.unix_timestamp + quarantine_seconds;
+                tombstone.bump = ctx.bumps.tombstone.ok_or(ErrorCode::MissingTombstone)?;
+                Some(tombstone.quarantine_until)
+            }
+            None => {
+                require!(quarantine_seconds == 0, ErrorCode::MissingTombstone);
+                None
+            }
+        };
+
+        emit!(UsernameBurned {
+            owner: ctx.accounts.owner.key(),
+            username,
+            quarantine_until,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Grow a handle created before the rename, standard and moderation
+    /// fields to the current layout and give it a handle id. A token minted
+    /// before delegation, or settled from an auction without the winner's
+    /// signature, is delegated to the handle PDA so recovery and rename can
+    /// retire it.
+    pub fn migrate_username(ctx: Context<MigrateUsername>) -> Result<()> {
+        let username_info = ctx.accounts.username_account.to_account_info();
+        let space = 8 + UsernameAccount::MAX_SIZE;
+        let needs_realloc = username_info.data_len() < space;
+        let needs_delegation = ctx.accounts.token_account.delegate != COption::Some(username_info.key());
+        require!(
+            needs_realloc || needs_delegation || ctx.accounts.username_account.handle_id == 0,
+            ErrorCode::UsernameAlreadyMigrated
+        );
+
+        if needs_realloc {
+            let rent = Rent::get()?.minimum_balance(space);
+            let top_up = rent.saturating_sub(username_info.lamports());
+            if top_up > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        SystemTransfer {
+                            from: ctx.accounts.owner.to_account_info(),
+                            to: username_info.clone(),
+                        },
+                    ),
+                    top_up,
+                )?;
+            }
+            // The legacy buffer is zeroed, so the new fields read as their defaults
+            username_info.realloc(space, false)?;
+        }
+
+        if ctx.accounts.username_account.handle_id == 0 {
+            ctx.accounts.username_account.handle_id = ctx.accounts.registry.assign_handle_id();
+        }
+
+        let token_account = &ctx.accounts.token_account;
+        if needs_delegation {
+            let username = ctx.accounts.username_account.username.clone();
+            let username_seeds = &[
+                b"username",
+                username.as_bytes(),
+                &[ctx.accounts.username_account.bump],
+            ];
+
+            if token_account.is_frozen() {
+                thaw_account(CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    ThawAccount {
+                        account: token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        authority: username_info.clone(),
+                    },
+                    &[&username_seeds[..]],
+                ))?;
+            }
+
+            token_interface::approve(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::Approve {
+                        to: token_account.to_account_info(),
+                        delegate: username_info.clone(),
+                        authority: ctx.accounts.owner.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+
+            freeze_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                FreezeAccount {
+                    account: token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    authority: username_info,
+                },
+                &[&username_seeds[..]],
+            ))?;
+        }
+
+        msg!("Username @{} migrated", ctx.accounts.username_account.username);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Mint the single soulbound token for a handle and freeze it. When the
+/// owner signs, the token is delegated to the username PDA first so recovery
+/// can burn it later; otherwise `migrate_username` delegates it.
+/// Works for both the classic token program and Token-2022.
+fn mint_soulbound_token<'info>(
+    token_program: &impl ToAccountInfo<'info>,
+    mint: &impl ToAccountInfo<'info>,
+    token_account: &impl ToAccountInfo<'info>,
+    owner: &impl ToAccountInfo<'info>,
+    username_account: &impl ToAccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token_interface::MintTo {
+                mint: mint.to_account_info(),
+                to: token_account.to_account_info(),
+                authority: username_account.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        1,
+    )?;
+
+    let owner = owner.to_account_info();
+    if owner.is_signer {
+        token_interface::approve(
+            CpiContext::new(
+                token_program.to_account_info(),
+                token_interface::Approve {
+                    to: token_account.to_account_info(),
+                    delegate: username_account.to_account_info(),
+                    authority: owner,
+                },
+            ),
+            1,
+        )?;
+    }
+
+    // Freeze the token account to make it soulbound
+    token_interface::freeze_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token_interface::FreezeAccount {
+            account: token_account.to_account_info(),
+            mint: mint.to_account_info(),
+            authority: username_account.to_account_info(),
+        },
+        signer_seeds,
+    ))
+}
+
+/// Mint the single Token-2022 handle token. The NonTransferable extension
+/// keeps it in place, so it is neither frozen nor delegated.
+fn mint_non_transferable_token<'info>(
+    token_program: &impl ToAccountInfo<'info>,
+    mint: &impl ToAccountInfo<'info>,
+    token_account: &impl ToAccountInfo<'info>,
+    username_account: &impl ToAccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token_interface::MintTo {
+                mint: mint.to_account_info(),
+                to: token_account.to_account_info(),
+                authority: username_account.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        1,
+    )
+}
+
+/// Create a Token-2022 mint at the `mint_2022` PDA with the NonTransferable,
+/// PermanentDelegate and MetadataPointer extensions, then write the handle
+/// into on-mint metadata.
+fn initialize_non_transferable_mint(
+    ctx: &Context<CreateUsernameToken2022>,
+    username: &str,
+    uri: &str,
+    username_key: Pubkey,
+    username_signer: &[&[&[u8]]],
+) -> Result<()> {
+    use spl_token_2022::extension::ExtensionType;
+
+    let token_program = ctx.accounts.token_2022_program.to_account_info();
+    let mint = ctx.accounts.mint.to_account_info();
+    let username_account = ctx.accounts.username_account.to_account_info();
+
+    let metadata = TokenMetadata {
+        update_authority: OptionalNonZeroPubkey::try_from(Some(username_key))?,
+        mint: mint.key(),
+        name: format!("@{}", username),
+        symbol: USERNAME_SYMBOL.to_string(),
+        uri: uri.to_string(),
+        additional_metadata: vec![],
+    };
+    let mint_len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&[
+        ExtensionType::NonTransferable,
+        ExtensionType::PermanentDelegate,
+        ExtensionType::MetadataPointer,
+    ])?;
+    // Token metadata is reallocated into the mint, so fund it up front
+    let lamports = Rent::get()?.minimum_balance(mint_len + metadata.tlv_size_of()?);
+
+    let mint_seeds = &[
+        b"mint_2022",
+        username.as_bytes(),
+        &[ctx.bumps.mint],
+    ];
+    system_program::create_account(
+        CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::CreateAccount {
+                from: ctx.accounts.user.to_account_info(),
+                to: mint.clone(),
+            },
+            &[&mint_seeds[..]],
+        ),
+        lamports,
+        mint_len as u64,
+        &token_program.key(),
+    )?;
+
+    token_interface::non_transferable_mint_initialize(CpiContext::new(
+        token_program.clone(),
+        token_interface::NonTransferableMintInitialize {
+            token_program_id: token_program.clone(),
+            mint: mint.clone(),
+        },
+    ))?;
+
+    token_interface::permanent_delegate_initialize(
+        CpiContext::new(
+            token_program.clone(),
+            token_interface::PermanentDelegateInitialize {
+                token_program_id: token_program.clone(),
+                mint: mint.clone(),
+            },
+        ),
+        &username_key,
+    )?;
+
+    token_interface::metadata_pointer_initialize(
+        CpiContext::new(
+            token_program.clone(),
+            token_interface::MetadataPointerInitialize {
+                token_program_id: token_program.clone(),
+                mint: mint.clone(),
+            },
+        ),
+        Some(username_key),
+        Some(mint.key()),
+    )?;
+
+    token_interface::initialize_mint2(
+        CpiContext::new(
+            token_program.clone(),
+            token_interface::InitializeMint2 { mint: mint.clone() },
+        ),
+        0,
+        &username_key,
+        Some(&username_key),
+    )?;
+
+    token_interface::token_metadata_initialize(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            token_interface::TokenMetadataInitialize {
+                token_program_id: token_program,
+                metadata: mint.clone(),
+                update_authority: username_account.clone(),
+                mint_authority: username_account,
+                mint,
+            },
+            username_signer,
+        ),
+        metadata.name,
+        metadata.symbol,
+        metadata.uri,
+    )
+}
+
+/// Rewrite the name and uri in the on-mint metadata of a reused Token-2022
+/// handle mint
+fn update_handle_metadata(
+    ctx: &Context<CreateUsernameToken2022>,
+    username: &str,
+    uri: &str,
+    username_signer: &[&[&[u8]]],
+) -> Result<()> {
+    let token_program = ctx.accounts.token_2022_program.to_account_info();
+    let mint = ctx.accounts.mint.to_account_info();
+    let username_account = ctx.accounts.username_account.to_account_info();
+
+    // Token-2022 reallocates the metadata in place; fund the longest case
+    let lamports = Rent::get()?.minimum_balance(mint.data_len() + uri.len());
+    let top_up = lamports.saturating_sub(mint.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                SystemTransfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: mint.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+
+    for (field, value) in [
+        (Field::Name, format!("@{}", username)),
+        (Field::Uri, uri.to_string()),
+    ] {
+        token_interface::token_metadata_update_field(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                token_interface::TokenMetadataUpdateField {
+                    token_program_id: token_program.clone(),
+                    metadata: mint.clone(),
+                    update_authority: username_account.clone(),
+                },
+                username_signer,
+            ),
+            field,
+            value,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Take a soulbound token out of circulation. A frozen SPL token must be
+/// delegated to the handle PDA; tokens minted before delegation go through
+/// `migrate_username` first. Token-2022 handles are burned by the PDA as
+/// the mint's permanent delegate.
+fn retire_soulbound_token<'info>(
+    token_program: &impl ToAccountInfo<'info>,
+    mint: &impl ToAccountInfo<'info>,
+    token_account: &impl ToAccountInfo<'info>,
+    delegate: COption<Pubkey>,
+    standard: SoulboundStandard,
+    username_account: &impl ToAccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let username_account = username_account.to_account_info();
+    match standard {
+        SoulboundStandard::FrozenSpl => {
+            require!(
+                delegate == COption::Some(username_account.key()),
+                ErrorCode::HandleTokenNotDelegated
+            );
+
+            token_interface::thaw_account(CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token_interface::ThawAccount {
+                    account: token_account.to_account_info(),
+                    mint: mint.to_account_info(),
+                    authority: username_account.clone(),
+                },
+                signer_seeds,
+            ))?;
+        }
+        SoulboundStandard::NonTransferable2022 => {}
+        SoulboundStandard::Compressed => return err!(ErrorCode::UnsupportedTokenStandard),
+    }
+
+    token_interface::burn(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token_interface::Burn {
+                mint: mint.to_account_info(),
+                from: token_account.to_account_info(),
+                authority: username_account,
+            },
+            signer_seeds,
+        ),
+        1,
+    )
+}
+
+/// Reject a registration whose name, look-alike skeleton or wallet identity
+/// is already taken. The records are `init_if_needed`, so a taken one
+/// arrives here populated rather than failing the system program's create.
+fn ensure_unclaimed(
+    username_account: &UsernameAccount,
+    handle_skeleton: &HandleSkeleton,
+    identity_account: &IdentityAccount,
+) -> Result<()> {
+    require!(
+        username_account.owner == Pubkey::default()
+            && handle_skeleton.handle_account == Pubkey::default()
+            && identity_account.wallet == Pubkey::default(),
+        ErrorCode::IdentityAlreadyExists
+    );
+
+    Ok(())
+}
+
+/// Reject a handle whose skeleton is still under a tombstone quarantine
+fn ensure_not_quarantined(tombstone: &AccountInfo, now: i64) -> Result<()> {
+    require!(
+        active_quarantine(tombstone, now)?.is_none(),
+        ErrorCode::UsernameQuarantined
+    );
+
+    Ok(())
+}
+
+/// End of the tombstone quarantine on a skeleton, if it has not passed yet
+fn active_quarantine(tombstone: &AccountInfo, now: i64) -> Result<Option<i64>> {
+    if tombstone.data_is_empty() {
+        return Ok(None);
+    }
+
+    let data = tombstone.try_borrow_data()?;
+    let tombstone = HandleTombstone::try_deserialize(&mut &data[..])?;
+
+    Ok((now < tombstone.quarantine_until).then_some(tombstone.quarantine_until))
+}
+
+/// Text a handle owner signs to prove control of the handle
+pub fn identity_challenge_message(handle: &str, challenge: &IdentityChallenge) -> Vec<u8> {
+    format!(
+        "neoengine-identity:verify\nhandle:@{}\ndomain:{}\nnonce:{}\nexpires_at:{}",
+        handle, challenge.domain, challenge.nonce, challenge.expires_at,
+    )
+    .into_bytes()
+}
+
+/// Whether ed25519 program instruction data carries a signature by `signer`
+/// over `message`. Only self-contained signatures (offsets pointing into the
+/// same instruction) are accepted.
+fn ed25519_signed_by(data: &[u8], signer: &Pubkey, message: &[u8]) -> bool {
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const PUBKEY_LEN: usize = 32;
+
+    let read_u16 = |at: usize| -> Option<usize> {
+        data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+
+    let Some(&count) = data.first() else {
+        return false;
+    };
+
+    (0..count as usize).any(|i| {
+        let base = OFFSETS_START + i * OFFSETS_LEN;
+        let offsets = (
+            read_u16(base + 2),
+            read_u16(base + 4),
+            read_u16(base + 6),
+            read_u16(base + 8),
+            read_u16(base + 10),
+            read_u16(base + 12),
+        );
+        let (
+            Some(signature_ix),
+            Some(pubkey_offset),
+            Some(pubkey_ix),
+            Some(message_offset),
+            Some(message_size),
+            Some(message_ix),
+        ) = offsets
+        else {
+            return false;
+        };
+
+        let this_ix = u16::MAX as usize;
+        if signature_ix != this_ix || pubkey_ix != this_ix || message_ix != this_ix {
+            return false;
+        }
+
+        data.get(pubkey_offset..pubkey_offset + PUBKEY_LEN) == Some(signer.as_ref())
+            && data.get(message_offset..message_offset + message_size) == Some(message)
+    })
+}
+
+/// Immutable, royalty-free Bubblegum metadata for a compressed handle. The
+/// leaf is owned by the username PDA, so the holder is listed as its only,
+/// unverified creator.
+fn compressed_username_metadata(username: &str, uri: String, holder: Pubkey) -> MetadataArgs {
+    MetadataArgs {
+        name: format!("@{}", username),
+        symbol: USERNAME_SYMBOL.to_string(),
+        uri,
+        seller_fee_basis_points: 0,
+        primary_sale_happened: false,
+        is_mutable: false,
+        edition_nonce: None,
+        token_standard: Some(TokenStandard::NonFungible),
+        collection: None,
+        uses: None,
+        token_program_version: TokenProgramVersion::Original,
+        creators: vec![Creator {
+            address: holder,
+            verified: false,
+            share: 100,
+        }],
+    }
+}
+
+// ============================================================================
+// Handle Grammar
+// ============================================================================
+
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 20;
+
+/// Canonical form of a handle: an optional leading `@` is dropped and ASCII
+/// letters are lowercased. This is the form used for PDA seeds and storage.
+pub fn canonical_username(username: &str) -> String {
+    username
+        .strip_prefix('@')
+        .unwrap_or(username)
+        .to_ascii_lowercase()
+}
+
+/// Canonical handle cut to at most one byte past `MAX_USERNAME_LEN`, for
+/// account seeds. Seeds are derived before the handler validates the name,
+/// and an overlong seed would abort the derivation; the cut keeps it in
+/// bounds and leaves the handler to reject the name as `UsernameTooLong`.
+pub fn username_seed(username: &str) -> String {
+    let mut seed = canonical_username(username);
+    let mut len = seed.len().min(MAX_USERNAME_LEN + 1);
+    while !seed.is_char_boundary(len) {
+        len -= 1;
+    }
+    seed.truncate(len);
+    seed
+}
+
+/// Validate a canonical handle: `[a-z0-9]` with single `.`, `_` or `-`
+/// separators, never leading, trailing or doubled.
+pub fn validate_username(username: &str) -> Result<()> {
+    require!(username.len() >= MIN_USERNAME_LEN, ErrorCode::UsernameTooShort);
+    require!(username.len() <= MAX_USERNAME_LEN, ErrorCode::UsernameTooLong);
+
+    let bytes = username.as_bytes();
+    require!(
+        bytes.iter().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || is_separator(*b)),
+        ErrorCode::InvalidUsernameFormat
+    );
+    require!(
+        !is_separator(bytes[0]) && !is_separator(bytes[bytes.len() - 1]),
+        ErrorCode::InvalidUsernameFormat
+    );
+    require!(
+        !bytes.windows(2).any(|w| is_separator(w[0]) && is_separator(w[1])),
+        ErrorCode::InvalidUsernameFormat
+    );
+
+    Ok(())
+}
+
+/// Confusable skeleton of a canonical handle. Handles that render alike
+/// (`0`/`o`, `1`/`i`/`l`, `rn`/`m`, `vv`/`w`, any separator) share a skeleton.
+pub fn username_skeleton(username: &str) -> String {
+    let folded: String = username
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '.' | '-' => '_',
+            other => other,
+        })
+        .collect();
+
+    folded.replace("rn", "m").replace("vv", "w")
+}
+
+fn is_separator(b: u8) -> bool {
+    matches!(b, b'.' | b'_' | b'-')
+}
+
+// ============================================================================
+// Account Structs
+// ============================================================================
+
+#[account]
+pub struct UsernameAccount {
+    pub owner: Pubkey,        // 32 bytes
+    pub username: String,     // 4 + max 20 bytes = 24 bytes
+    pub mint: Pubkey,         // 32 bytes
+    pub created_at: i64,      // 8 bytes
+    pub bump: u8,             // 1 byte
+    pub last_renamed_at: i64, // 8 bytes
+    pub previous_usernames: Vec<String>, // 4 + 24 * MAX_USERNAME_HISTORY bytes
+    pub standard: SoulboundStandard, // 1 byte
+    pub compressed_leaf: Option<CompressedLeaf>, // 1 + 40 bytes
+    pub suspended: bool,      // 1 byte
+    /// Registration id keying recovery, records, sub-handles and
+    /// attestations; kept across renames, never reused after a burn
+    pub handle_id: u64,       // 8 bytes
+}
+
+impl UsernameAccount {
+    pub const MAX_SIZE: usize = 32 + 24 + 32 + 8 + 1 + 8 + (4 + 24 * MAX_USERNAME_HISTORY) + 1 + (1 + 40) + 1 + 8 + 50; // with buffer
+}
+
+/// How a handle's soulbound token is kept in place
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SoulboundStandard {
+    /// Classic SPL token frozen by the username PDA
+    FrozenSpl,
+    /// Token-2022 NonTransferable mint carrying on-mint token metadata
+    NonTransferable2022,
+    /// Bubblegum compressed NFT owned by the username PDA
+    Compressed,
+}
+
+/// Location of a compressed handle's leaf
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedLeaf {
+    pub merkle_tree: Pubkey, // 32 bytes
+    pub nonce: u64,          // 8 bytes
+}
+
+pub const MAX_USERNAME_HISTORY: usize = 5;
+
+/// Quarantine marker left on a released handle's skeleton
+#[account]
+pub struct HandleTombstone {
+    pub skeleton: String,      // 4 + max 20 bytes = 24 bytes
+    pub username: String,      // 4 + max 20 bytes = 24 bytes
+    pub former_owner: Pubkey,  // 32 bytes
+    pub released_at: i64,      // 8 bytes
+    pub quarantine_until: i64, // 8 bytes
+    pub bump: u8,              // 1 byte
+}
+
+impl HandleTombstone {
+    pub const MAX_SIZE: usize = 24 + 24 + 32 + 8 + 8 + 1;
+}
+
+/// Reverse record from a wallet to its single identity
+#[account]
+pub struct IdentityAccount {
+    pub wallet: Pubkey,           // 32 bytes
+    pub username_account: Pubkey, // 32 bytes
+    pub username: String,         // 4 + max 20 bytes = 24 bytes
+    pub active_username_nft: Option<Pubkey>, // 1 + 32 bytes
+    pub created_at: i64,          // 8 bytes
+    pub bump: u8,                 // 1 byte
+    /// Highest identity challenge nonce accepted by `verify_identity`
+    pub last_challenge_nonce: u64, // 8 bytes
+}
+
+impl IdentityAccount {
+    pub const MAX_SIZE: usize = 32 + 32 + 24 + 33 + 8 + 1 + 8;
+}
+
+/// Claims a confusable skeleton for a single handle (soulbound or tradable)
+#[account]
+pub struct HandleSkeleton {
+    pub handle_account: Pubkey,   // 32 bytes
+    pub skeleton: String,         // 4 + max 20 bytes = 24 bytes
+    pub bump: u8,                 // 1 byte
+}
+
+impl HandleSkeleton {
+    pub const MAX_SIZE: usize = 32 + 24 + 1;
+}
+
+/// Tradable username NFT ("Username Trading" tier)
+#[account]
+pub struct UsernameNft {
+    pub username: String,                  // 4 + max 20 bytes = 24 bytes
+    pub mint: Pubkey,                      // 32 bytes
+    pub claimed_by: Pubkey,                // 32 bytes
+    pub active: bool,                      // 1 byte
+    pub assigned_identity: Option<Pubkey>, // 1 + 32 bytes
+    pub created_at: i64,                   // 8 bytes
+    pub bump: u8,                          // 1 byte
+}
+
+impl UsernameNft {
+    pub const MAX_SIZE: usize = 24 + 32 + 32 + 1 + 33 + 8 + 1;
+}
+
+/// Number of entries in the length price table (one per length 3..=20)
+pub const PRICE_TABLE_LEN: usize = MAX_USERNAME_LEN - MIN_USERNAME_LEN + 1;
+
+/// Global username registry: admin, pricing, treasury and counters
+#[account]
+pub struct UsernameRegistry {
+    pub authority: Pubkey,                     // 32 bytes
+    pub treasury: Pubkey,                      // 32 bytes
+    pub fee_currency: FeeCurrency,             // 1 byte
+    pub dsx_mint: Pubkey,                      // 32 bytes
+    pub length_prices: [u64; PRICE_TABLE_LEN], // 8 * 18 = 144 bytes
+    pub rename_cooldown_seconds: i64,          // 8 bytes
+    pub quarantine_seconds: i64,               // 8 bytes
+    pub auction_only_max_length: u8,           // 1 byte
+    pub total_usernames: u64,                  // 8 bytes
+    pub total_username_nfts: u64,              // 8 bytes
+    pub next_handle_id: u64,                   // 8 bytes
+    pub bump: u8,                              // 1 byte
+}
+
+impl UsernameRegistry {
+    pub const MAX_SIZE: usize = 32 + 32 + 1 + 32 + 8 * PRICE_TABLE_LEN + 8 + 8 + 1 + 8 + 8 + 8 + 1;
+
+    /// Fee for a canonical handle of the given length
+    pub fn price_for_length(&self, len: usize) -> u64 {
+        let index = len.clamp(MIN_USERNAME_LEN, MAX_USERNAME_LEN) - MIN_USERNAME_LEN;
+        self.length_prices[index]
+    }
+
+    /// Hand out the id of a new registration. Ids start at 1; 0 marks a
+    /// handle created before ids existed.
+    fn assign_handle_id(&mut self) -> u64 {
+        let id = self.next_handle_id;
+        self.next_handle_id += 1;
+        id
+    }
+
+    /// Reject handles short enough to be sold only through auctions
+    pub fn ensure_open(&self, len: usize) -> Result<()> {
+        require!(
+            len > self.auction_only_max_length as usize,
+            ErrorCode::UsernameReservedForAuction
+        );
+        Ok(())
+    }
+
+    fn apply_config(&mut self, config: RegistryConfig) -> Result<()> {
+        require!(
+            config.rename_cooldown_seconds >= 0 && config.quarantine_seconds >= 0,
+            ErrorCode::InvalidRegistryConfig
+        );
+        require!(
+            config.auction_only_max_length as usize <= MAX_USERNAME_LEN,
+            ErrorCode::InvalidRegistryConfig
+        );
+
+        self.authority = config.authority;
+        self.treasury = config.treasury;
+        self.fee_currency = config.fee_currency;
+        self.dsx_mint = config.dsx_mint;
+        self.length_prices = config.length_prices;
+        self.rename_cooldown_seconds = config.rename_cooldown_seconds;
+        self.quarantine_seconds = config.quarantine_seconds;
+        self.auction_only_max_length = config.auction_only_max_length;
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeeCurrency {
+    Lamports,
+    Dsx,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RegistryConfig {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_currency: FeeCurrency,
+    pub dsx_mint: Pubkey,
+    pub length_prices: [u64; PRICE_TABLE_LEN],
+    pub rename_cooldown_seconds: i64,
+    pub quarantine_seconds: i64,
+    pub auction_only_max_length: u8,
+}
+
+pub const MAX_GUARDIANS: usize = 10;
+pub const MIN_RECOVERY_TIMELOCK: i64 = 24 * 60 * 60; // 1 day
+
+/// Guardian set allowed to recover a handle to a new wallet
+#[account]
+pub struct RecoveryConfig {
+    pub handle_id: u64,           // 8 bytes
+    pub guardians: Vec<Pubkey>,   // 4 + 32 * MAX_GUARDIANS bytes
+    pub threshold: u8,            // 1 byte
+    pub timelock_seconds: i64,    // 8 bytes
+    pub bump: u8,                 // 1 byte
+}
+
+impl RecoveryConfig {
+    pub const MAX_SIZE: usize = 8 + (4 + 32 * MAX_GUARDIANS) + 1 + 8 + 1;
+}
+
+/// Pending guardian-approved migration of a handle to a new wallet
+#[account]
+pub struct RecoveryRequest {
+    pub handle_id: u64,                    // 8 bytes
+    pub new_wallet: Pubkey,                // 32 bytes
+    pub initiator: Pubkey,                 // 32 bytes
+    pub approvals: Vec<Pubkey>,            // 4 + 32 * MAX_GUARDIANS bytes
+    pub initiated_at: i64,                 // 8 bytes
+    pub threshold_reached_at: Option<i64>, // 1 + 8 bytes
+    pub bump: u8,                          // 1 byte
+}
+
+impl RecoveryRequest {
+    pub const MAX_SIZE: usize = 8 + 32 + 32 + (4 + 32 * MAX_GUARDIANS) + 8 + 9 + 1;
+
+    /// Approvals from wallets that are still guardians
+    pub fn approval_count(&self, config: &RecoveryConfig) -> usize {
+        self.approvals
+            .iter()
+            .filter(|approver| config.guardians.contains(approver))
+            .count()
+    }
+
+    /// Start the timelock the first time the threshold is met
+    fn record_threshold(&mut self, config: &RecoveryConfig, now: i64) {
+        if self.threshold_reached_at.is_none()
+            && self.approval_count(config) >= config.threshold as usize
+        {
+            self.threshold_reached_at = Some(now);
+        }
+    }
+}
+
+/// Auction for a reserved handle with escrowed bids
+#[account]
+pub struct HandleAuction {
+    pub handle: String,                 // 4 + max 20 bytes = 24 bytes
+    pub creator: Pubkey,                // 32 bytes
+    pub currency: FeeCurrency,          // 1 byte
+    pub reserve_price: u64,             // 8 bytes
+    pub min_increment: u64,             // 8 bytes
+    pub start_time: i64,                // 8 bytes
+    pub end_time: i64,                  // 8 bytes
+    pub extension_window: i64,          // 8 bytes
+    pub extension_seconds: i64,         // 8 bytes
+    pub highest_bidder: Option<Pubkey>, // 1 + 32 bytes
+    pub highest_bid: u64,               // 8 bytes
+    pub bid_count: u32,                 // 4 bytes
+    pub bump: u8,                       // 1 byte
+}
+
+impl HandleAuction {
+    pub const MAX_SIZE: usize = 24 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 33 + 8 + 4 + 1;
+}
+
+pub const MAX_METADATA_URI_LEN: usize = 200;
+pub const USERNAME_SYMBOL: &str = "NEOID";
+
+/// Program-owned Bubblegum tree that holds compressed handles
+#[account]
+pub struct HandleTree {
+    pub merkle_tree: Pubkey, // 32 bytes
+    pub total_minted: u64,   // 8 bytes
+    pub created_at: i64,     // 8 bytes
+    pub bump: u8,            // 1 byte
+}
+
+impl HandleTree {
+    pub const MAX_SIZE: usize = 32 + 8 + 8 + 1;
+}
+
+pub const MAX_RECORD_KEY_LEN: usize = 24;
+pub const MAX_RECORD_VALUE_LEN: usize = 200;
+
+/// Name-service record attached to a handle
+#[account]
+pub struct HandleRecord {
+    pub handle_id: u64,           // 8 bytes
+    pub key: String,              // 4 + max 29 bytes ("text." + key)
+    pub value: RecordValue,       // RecordValue::MAX_SIZE bytes
+    pub updated_at: i64,          // 8 bytes
+    pub bump: u8,                 // 1 byte
+}
+
+impl HandleRecord {
+    pub const MAX_SIZE: usize = 8 + (4 + 5 + MAX_RECORD_KEY_LEN) + RecordValue::MAX_SIZE + 8 + 1;
+}
+
+/// Typed record value; the variant determines the record key
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum RecordValue {
+    /// Wallet that should receive SOL and SPL payments
+    SolAddress(Pubkey),
+    /// 20-byte Ethereum address
+    EthAddress([u8; 20]),
+    /// IPFS CID of the handle's content
+    IpfsContentHash(String),
+    Url(String),
+    /// Mint of the NFT used as avatar
+    AvatarMint(Pubkey),
+    /// Free-form text under an app-defined key
+    Text { key: String, value: String },
+}
+
+impl RecordValue {
+    pub const MAX_SIZE: usize = 1 + (4 + MAX_RECORD_KEY_LEN) + (4 + MAX_RECORD_VALUE_LEN);
+
+    /// Seed and lookup key for the record
+    pub fn record_key(&self) -> String {
+        match self {
+            RecordValue::SolAddress(_) => "sol".to_string(),
+            RecordValue::EthAddress(_) => "eth".to_string(),
+            RecordValue::IpfsContentHash(_) => "ipfs".to_string(),
+            RecordValue::Url(_) => "url".to_string(),
+            RecordValue::AvatarMint(_) => "avatar".to_string(),
+            RecordValue::Text { key, .. } => format!("text.{}", key),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            RecordValue::IpfsContentHash(value) | RecordValue::Url(value) => {
+                require!(
+                    !value.is_empty() && value.len() <= MAX_RECORD_VALUE_LEN,
+                    ErrorCode::InvalidRecordValue
+                );
+            }
+            RecordValue::Text { key, value } => {
+                require!(
+                    !key.is_empty()
+                        && key.len() <= MAX_RECORD_KEY_LEN
+                        && key.bytes().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || is_separator(c)),
+                    ErrorCode::InvalidRecordKey
+                );
+                require!(value.len() <= MAX_RECORD_VALUE_LEN, ErrorCode::InvalidRecordValue);
+            }
+            RecordValue::SolAddress(_) | RecordValue::EthAddress(_) | RecordValue::AvatarMint(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+pub const MAX_SUBDOMAIN_WHITELIST: usize = 20;
+
+/// Who may claim sub-handles under a parent handle
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SubdomainMode {
+    /// Anyone may claim
+    Open,
+    /// Only whitelisted wallets may claim
+    Whitelist,
+    /// Anyone may claim by paying the parent owner
+    Paid,
+}
+
+/// Sub-handle issuance policy of a parent handle
+#[account]
+pub struct SubdomainPolicy {
+    pub parent_id: u64,          // 8 bytes
+    pub mode: SubdomainMode,     // 1 byte
+    pub price_lamports: u64,     // 8 bytes
+    pub whitelist: Vec<Pubkey>,  // 4 + 32 * MAX_SUBDOMAIN_WHITELIST bytes
+    pub bump: u8,                // 1 byte
+}
+
+impl SubdomainPolicy {
+    pub const MAX_SIZE: usize = 8 + 1 + 8 + (4 + 32 * MAX_SUBDOMAIN_WHITELIST) + 1;
+}
+
+/// Sub-handle `label.@parent`
+#[account]
+pub struct SubdomainAccount {
+    pub parent_id: u64,         // 8 bytes
+    pub label: String,          // 4 + max 20 bytes = 24 bytes
+    pub owner: Pubkey,          // 32 bytes
+    pub mint: Option<Pubkey>,   // 1 + 32 bytes
+    pub transfer_locked: bool,  // 1 byte
+    pub created_at: i64,        // 8 bytes
+    pub bump: u8,               // 1 byte
+}
+
+impl SubdomainAccount {
+    pub const MAX_SIZE: usize = 8 + 24 + 32 + (1 + 32) + 1 + 8 + 1;
+}
+
+pub const MAX_CHALLENGE_DOMAIN_LEN: usize = 64;
+
+pub const MAX_MODERATORS: usize = 10;
+pub const MAX_APPEAL_URI_LEN: usize = 200;
+
+/// Moderation authority and moderator set
+#[account]
+pub struct ModerationConfig {
+    pub authority: Pubkey,       // 32 bytes
+    pub moderators: Vec<Pubkey>, // 4 + 32 * MAX_MODERATORS bytes
+    pub bump: u8,                // 1 byte
+}
+
+impl ModerationConfig {
+    pub const MAX_SIZE: usize = 32 + (4 + 32 * MAX_MODERATORS) + 1;
+
+    pub fn is_moderator(&self, key: &Pubkey) -> bool {
+        *key == self.authority || self.moderators.contains(key)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SuspensionStatus {
+    Suspended,
+    /// The owner appealed and awaits a moderator decision
+    Appealed,
+    /// A moderator confirmed the suspension; no further appeal
+    Upheld,
+}
+
+/// Moderation record of a suspended handle, seeded by the username account
+/// and its handle id so a burned and re-registered name starts clean
+#[account]
+pub struct HandleSuspension {
+    pub username_account: Pubkey, // 32 bytes
+    pub reason_code: u16,         // 2 bytes
+    pub moderator: Pubkey,        // 32 bytes
+    pub suspended_at: i64,        // 8 bytes
+    pub status: SuspensionStatus, // 1 byte
+    pub appeal_uri: String,       // 4 + MAX_APPEAL_URI_LEN bytes
+    pub appealed_at: i64,         // 8 bytes
+    pub bump: u8,                 // 1 byte
+}
+
+impl HandleSuspension {
+    pub const MAX_SIZE: usize = 32 + 2 + 32 + 8 + 1 + (4 + MAX_APPEAL_URI_LEN) + 8 + 1;
+}
+
+pub const MAX_ISSUER_NAME_LEN: usize = 32;
+
+/// Wallet allowed to attest handles
+#[account]
+pub struct AttestationIssuer {
+    pub issuer: Pubkey,       // 32 bytes
+    pub name: String,         // 4 + max 32 bytes
+    pub active: bool,         // 1 byte
+    pub registered_at: i64,   // 8 bytes
+    pub bump: u8,             // 1 byte
+}
+
+impl AttestationIssuer {
+    pub const MAX_SIZE: usize = 32 + (4 + MAX_ISSUER_NAME_LEN) + 1 + 8 + 1;
+}
+
+/// Attestation by an issuer about a handle, keyed by (handle, issuer, schema)
+#[account]
+pub struct Attestation {
+    pub handle_id: u64,               // 8 bytes
+    pub issuer: Pubkey,               // 32 bytes
+    pub schema: u32,                  // 4 bytes
+    pub data_hash: Option<[u8; 32]>,  // 1 + 32 bytes
+    pub issued_at: i64,               // 8 bytes
+    pub expires_at: Option<i64>,      // 1 + 8 bytes
+    pub revoked: bool,                // 1 byte
+    pub bump: u8,                     // 1 byte
+}
+
+impl Attestation {
+    pub const MAX_SIZE: usize = 8 + 32 + 4 + (1 + 32) + 8 + (1 + 8) + 1 + 1;
+
+    /// Not revoked and not expired; the issuer's status is checked separately
+    pub fn is_valid(&self, now: i64) -> bool {
+        match self.expires_at {
+            Some(expires_at) => !self.revoked && now < expires_at,
+            None => !self.revoked,
+        }
+    }
+}
+
+// ============================================================================
+// Context Structs
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CreateUsername<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub fee: UsernameFee<'info>,
+
+    // init_if_needed so a taken name, look-alike or wallet reaches the
+    // handler and fails with IdentityAlreadyExists instead of inside the
+    // system program
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UsernameAccount::MAX_SIZE,
+        seeds = [b"username", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: Account<'info, HandleSkeleton>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + IdentityAccount::MAX_SIZE,
+        seeds = [b"identity", user.key().as_ref()],
+        bump,
+    )]
+    pub identity_account: Account<'info, IdentityAccount>,
+
+    // A handle released by rename or burn keeps its mint, whose authority is
+    // the same username PDA, so re-registration reuses it
+    #[account(
+        init_if_needed,
+        payer = user,
+        mint::decimals = 0,
+        mint::authority = username_account,
+        mint::freeze_authority = username_account,
+        seeds = [b"mint", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CheckUsername<'info> {
+    /// CHECK: Skeleton PDA of the handle; empty while the handle is available
+    #[account(
+        seeds = [b"skeleton", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: UncheckedAccount<'info>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct GetUsernameInfo<'info> {
+    #[account(
+        seeds = [b"username", username_seed(&username).as_bytes()],
+        bump = username_account.bump
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(owner: Pubkey)]
+pub struct GetIdentityByOwner<'info> {
+    #[account(
+        seeds = [b"identity", owner.as_ref()],
+        bump = identity_account.bump
+    )]
+    pub identity_account: Account<'info, IdentityAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct ClaimUsernameNft<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub fee: UsernameFee<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + UsernameNft::MAX_SIZE,
+        seeds = [b"username_nft", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub username_nft: Account<'info, UsernameNft>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: Account<'info, HandleSkeleton>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        mint::decimals = 0,
+        mint::authority = username_nft,
+        mint::freeze_authority = username_nft,
+        seeds = [b"username_nft_mint", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateUsernameNft<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", user.key().as_ref()],
+        bump = identity_account.bump,
+        constraint = identity_account.active_username_nft.is_none() @ ErrorCode::IdentityHasActiveUsernameNft,
+    )]
+    pub identity_account: Account<'info, IdentityAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"username_nft", username_nft.username.as_bytes()],
+        bump = username_nft.bump,
+        constraint = !username_nft.active @ ErrorCode::UsernameNftActive,
+    )]
+    pub username_nft: Account<'info, UsernameNft>,
+
+    #[account(address = username_nft.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+        constraint = token_account.amount == 1 @ ErrorCode::UsernameNftNotOwned,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateUsernameNft<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", user.key().as_ref()],
+        bump = identity_account.bump,
+    )]
+    pub identity_account: Account<'info, IdentityAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"username_nft", username_nft.username.as_bytes()],
+        bump = username_nft.bump,
+        constraint = username_nft.active @ ErrorCode::UsernameNftNotActive,
+        constraint = username_nft.assigned_identity == Some(identity_account.key()) @ ErrorCode::Unauthorized,
+    )]
+    pub username_nft: Account<'info, UsernameNft>,
+
+    #[account(address = username_nft.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct TransferUsernameNft<'info> {
+    pub user: Signer<'info>,
+
+    /// CHECK: Any wallet can receive an inactive username NFT
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"username_nft", username_nft.username.as_bytes()],
+        bump = username_nft.bump,
+        constraint = !username_nft.active @ ErrorCode::UsernameNftActive,
+    )]
+    pub username_nft: Account<'info, UsernameNft>,
+
+    #[account(address = username_nft.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = user,
+        constraint = from_token_account.amount == 1 @ ErrorCode::UsernameNftNotOwned,
+    )]
+    pub from_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = recipient,
+    )]
+    pub to_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRegistry<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + UsernameRegistry::MAX_SIZE,
+        seeds = [b"username_registry"],
+        bump,
+    )]
+    pub username_registry: Account<'info, UsernameRegistry>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::Unauthorized)]
+    pub program: Program<'info, crate::program::NeoengineIdentity>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ ErrorCode::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRegistryConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"username_registry"],
+        bump = username_registry.bump,
+        constraint = username_registry.authority == authority.key() @ ErrorCode::Unauthorized,
+    )]
+    pub username_registry: Account<'info, UsernameRegistry>,
+}
+
+/// Registry accounts needed to charge the length-based handle fee.
+/// The DSX token accounts are only required when the registry charges DSX.
+#[derive(Accounts)]
+pub struct UsernameFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    /// CHECK: Must match the registry treasury
+    #[account(mut, address = registry.treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = registry.dsx_mint)]
+    pub payer_dsx_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = registry.dsx_mint,
+        token::authority = registry.treasury,
+    )]
+    pub treasury_dsx_account: Option<Account<'info, TokenAccount>>,
+}
+
+impl<'info> UsernameFee<'info> {
+    /// Charge the registry price for a handle of `len` bytes. Returns the fee paid.
+    pub fn charge(
+        &self,
+        payer: &Signer<'info>,
+        system_program: &Program<'info, System>,
+        token_program: &Program<'info, Token>,
+        len: usize,
+    ) -> Result<u64> {
+        let fee = self.registry.price_for_length(len);
+        if fee == 0 {
+            return Ok(0);
+        }
+
+        match self.registry.fee_currency {
+            FeeCurrency::Lamports => system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    SystemTransfer {
+                        from: payer.to_account_info(),
+                        to: self.treasury.to_account_info(),
+                    },
+                ),
+                fee,
+            )?,
+            FeeCurrency::Dsx => {
+                let from = self.payer_dsx_account.as_ref().ok_or(ErrorCode::MissingDsxAccount)?;
+                let to = self.treasury_dsx_account.as_ref().ok_or(ErrorCode::MissingDsxAccount)?;
+                transfer(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        Transfer {
+                            from: from.to_account_info(),
+                            to: to.to_account_info(),
+                            authority: payer.to_account_info(),
+                        },
+                    ),
+                    fee,
+                )?
+            }
+        }
+
+        Ok(fee)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ConfigureRecovery<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = username_account.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+        constraint = username_account.standard != SoulboundStandard::Compressed @ ErrorCode::UnsupportedTokenStandard,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + RecoveryConfig::MAX_SIZE,
+        seeds = [b"recovery", username_account.handle_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub recovery_config: Account<'info, RecoveryConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitiateRecovery<'info> {
+    #[account(mut)]
+    pub guardian: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        seeds = [b"recovery", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_config.bump,
+        constraint = recovery_config.guardians.contains(&guardian.key()) @ ErrorCode::NotAGuardian,
+    )]
+    pub recovery_config: Account<'info, RecoveryConfig>,
+
+    #[account(
+        init,
+        payer = guardian,
+        space = 8 + RecoveryRequest::MAX_SIZE,
+        seeds = [b"recovery_request", username_account.handle_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveRecovery<'info> {
+    pub guardian: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        seeds = [b"recovery", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_config.bump,
+        constraint = recovery_config.guardians.contains(&guardian.key()) @ ErrorCode::NotAGuardian,
+    )]
+    pub recovery_config: Account<'info, RecoveryConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery_request", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_request.bump,
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRecovery<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    /// CHECK: Receives the request rent back; must be the guardian who opened it
+    #[account(mut, address = recovery_request.initiator)]
+    pub initiator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery_request", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_request.bump,
+        close = initiator,
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteRecovery<'info> {
+    #[account(mut)]
+    pub new_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.standard != SoulboundStandard::Compressed @ ErrorCode::UnsupportedTokenStandard,
+        constraint = !username_account.suspended @ ErrorCode::HandleSuspended,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        seeds = [b"recovery", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_config.bump,
+    )]
+    pub recovery_config: Account<'info, RecoveryConfig>,
+
+    /// CHECK: Receives the request rent back; must be the guardian who opened it
+    #[account(mut, address = recovery_request.initiator)]
+    pub initiator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery_request", username_account.handle_id.to_le_bytes().as_ref()],
+        bump = recovery_request.bump,
+        constraint = recovery_request.new_wallet == new_wallet.key() @ ErrorCode::InvalidRecoveryWallet,
+        close = initiator,
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", username_account.owner.as_ref()],
+        bump = old_identity.bump,
+        close = new_wallet,
+    )]
+    pub old_identity: Account<'info, IdentityAccount>,
+
+    #[account(
+        init,
+        payer = new_wallet,
+        space = 8 + IdentityAccount::MAX_SIZE,
+        seeds = [b"identity", new_wallet.key().as_ref()],
+        bump,
+    )]
+    pub new_identity: Account<'info, IdentityAccount>,
+
+    #[account(
+        mut,
+        address = username_account.mint,
+        mint::token_program = token_program,
+    )]
+    pub mint: InterfaceAccount<'info, InterfaceMint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = username_account.owner,
+        associated_token::token_program = token_program,
+    )]
+    pub old_token_account: InterfaceAccount<'info, InterfaceTokenAccount>,
+
+    #[account(
+        init,
+        payer = new_wallet,
+        associated_token::mint = mint,
+        associated_token::authority = new_wallet,
+        associated_token::token_program = token_program,
+    )]
+    pub new_token_account: InterfaceAccount<'info, InterfaceTokenAccount>,
+
+    /// Classic token program or Token-2022, matching the handle's mint
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_handle: String)]
+pub struct UpdateHandle<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub fee: UsernameFee<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"username", old_username_account.username.as_bytes()],
+        bump = old_username_account.bump,
+        constraint = old_username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = old_username_account.standard == SoulboundStandard::FrozenSpl @ ErrorCode::RenameUnsupported,
+        constraint = !old_username_account.suspended @ ErrorCode::HandleSuspended,
+        constraint = old_username_account.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+        close = owner,
+    )]
+    pub old_username_account: Box<Account<'info, UsernameAccount>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + UsernameAccount::MAX_SIZE,
+        seeds = [b"username", username_seed(&new_handle).as_bytes()],
+        bump,
+    )]
+    pub new_username_account: Box<Account<'info, UsernameAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"skeleton", old_skeleton.skeleton.as_bytes()],
+        bump = old_skeleton.bump,
+        constraint = old_skeleton.handle_account == old_username_account.key() @ ErrorCode::Unauthorized,
+    )]
+    pub old_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    /// Omitted when the new handle shares the old skeleton
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&new_handle)).as_bytes()],
+        bump,
+    )]
+    pub new_skeleton: Option<Box<Account<'info, HandleSkeleton>>>,
+
+    /// Omitted when the new handle shares the old skeleton
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + HandleTombstone::MAX_SIZE,
+        seeds = [b"tombstone", old_skeleton.skeleton.as_bytes()],
+        bump,
+    )]
+    pub old_tombstone: Option<Box<Account<'info, HandleTombstone>>>,
+
+    /// CHECK: Tombstone for the new skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&new_handle)).as_bytes()],
+        bump,
+    )]
+    pub new_tombstone: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", owner.key().as_ref()],
+        bump = identity_account.bump,
+    )]
+    pub identity_account: Box<Account<'info, IdentityAccount>>,
+
+    #[account(mut, address = old_username_account.mint)]
+    pub old_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = old_mint,
+        associated_token::authority = owner,
+    )]
+    pub old_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        mint::decimals = 0,
+        mint::authority = new_username_account,
+        mint::freeze_authority = new_username_account,
+        seeds = [b"mint", username_seed(&new_handle).as_bytes()],
+        bump,
+    )]
+    pub new_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = new_mint,
+        associated_token::authority = owner,
+    )]
+    pub new_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(handle: String)]
+pub struct CreateAuction<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+        constraint = registry.authority == authority.key() @ ErrorCode::Unauthorized,
+    )]
+    pub registry: Box<Account<'info, UsernameRegistry>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + HandleAuction::MAX_SIZE,
+        seeds = [b"auction", username_seed(&handle).as_bytes()],
+        bump,
+    )]
+    pub auction: Box<Account<'info, HandleAuction>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&handle)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&handle)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+
+    /// DSX escrow vault; only for DSX auctions
+    #[account(
+        init,
+        payer = authority,
+        token::mint = dsx_mint,
+        token::authority = auction,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(address = registry.dsx_mint)]
+    pub dsx_mint: Option<Box<Account<'info, Mint>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"auction", auction.handle.as_bytes()],
+        bump = auction.bump,
+    )]
+    pub auction: Box<Account<'info, HandleAuction>>,
+
+    /// CHECK: Must be empty; a wallet that already has an identity cannot win
+    #[account(seeds = [b"identity", bidder.key().as_ref()], bump)]
+    pub bidder_identity: UncheckedAccount<'info>,
+
+    /// CHECK: Current highest bidder, refunded when outbid
+    #[account(
+        mut,
+        constraint = Some(previous_bidder.key()) == auction.highest_bidder @ ErrorCode::InvalidPreviousBidder,
+    )]
+    pub previous_bidder: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub bidder_dsx_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(constraint = vault.as_ref().map(|vault| vault.mint) == Some(dsx_mint.key()) @ ErrorCode::MissingDsxAccount)]
+    pub dsx_mint: Option<Box<Account<'info, Mint>>>,
+
+    /// Refund escrow of the current highest bidder; the outbid DSX bid
+    /// moves here until they claim it
+    #[account(
+        init_if_needed,
+        payer = bidder,
+        token::mint = dsx_mint,
+        token::authority = bid_refund,
+        seeds = [
+            b"bid_refund",
+            auction.key().as_ref(),
+            auction.highest_bidder.unwrap_or_default().as_ref(),
+        ],
+        bump,
+    )]
+    pub bid_refund: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(auction: Pubkey)]
+pub struct ClaimBidRefund<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"bid_refund", auction.as_ref(), bidder.key().as_ref()],
+        bump,
+    )]
+    pub bid_refund: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = bid_refund.mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    /// Anyone may settle; pays the rent of the new handle accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Highest bidder; receives the handle or the refund
+    #[account(mut, address = auction.highest_bidder.ok_or(ErrorCode::InvalidPreviousBidder)?)]
+    pub winner: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+    )]
+    pub registry: Box<Account<'info, UsernameRegistry>>,
+
+    /// CHECK: Must match the registry treasury
+    #[account(mut, address = registry.treasury @ ErrorCode::InvalidTreasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = registry.dsx_mint,
+        token::authority = registry.treasury,
+    )]
+    pub treasury_dsx_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: Receives the auction rent back
+    #[account(mut, address = auction.creator)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"auction", auction.handle.as_bytes()],
+        bump = auction.bump,
+        close = creator,
+    )]
+    pub auction: Box<Account<'info, HandleAuction>>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Receives a refunded DSX bid
+    #[account(
+        mut,
+        token::mint = registry.dsx_mint,
+        token::authority = winner,
+    )]
+    pub winner_dsx_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [b"skeleton", username_skeleton(&auction.handle).as_bytes()],
+        bump = handle_skeleton.bump,
+        constraint = handle_skeleton.handle_account == auction.key() @ ErrorCode::Unauthorized,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    /// Existing identity of the winner, or a new one when the handle is delivered
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + IdentityAccount::MAX_SIZE,
+        seeds = [b"identity", winner.key().as_ref()],
+        bump,
+    )]
+    pub identity_account: Box<Account<'info, IdentityAccount>>,
+
+    /// Handle accounts; omitted when the bid is refunded
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + UsernameAccount::MAX_SIZE,
+        seeds = [b"username", auction.handle.as_bytes()],
+        bump,
+    )]
+    pub username_account: Option<Box<Account<'info, UsernameAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        mint::decimals = 0,
+        mint::authority = username_account,
+        mint::freeze_authority = username_account,
+        seeds = [b"mint", auction.handle.as_bytes()],
+        bump,
+    )]
+    pub mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = winner,
+    )]
+    pub token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAuction<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+        constraint = registry.authority == authority.key() @ ErrorCode::Unauthorized,
+    )]
+    pub registry: Box<Account<'info, UsernameRegistry>>,
+
+    /// CHECK: Receives the auction rent back
+    #[account(mut, address = auction.creator)]
+    pub creator: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"auction", auction.handle.as_bytes()],
+        bump = auction.bump,
+        constraint = auction.highest_bidder.is_none() @ ErrorCode::AuctionHasBids,
+        close = creator,
+    )]
+    pub auction: Box<Account<'info, HandleAuction>>,
+
+    #[account(
+        mut,
+        seeds = [b"skeleton", username_skeleton(&auction.handle).as_bytes()],
+        bump = handle_skeleton.bump,
+        constraint = handle_skeleton.handle_account == auction.key() @ ErrorCode::Unauthorized,
+        close = creator,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_vault", auction.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CreateUsernameToken2022<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub fee: UsernameFee<'info>,
+
+    // init_if_needed: see CreateUsername
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UsernameAccount::MAX_SIZE,
+        seeds = [b"username", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub username_account: Box<Account<'info, UsernameAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + IdentityAccount::MAX_SIZE,
+        seeds = [b"identity", user.key().as_ref()],
+        bump,
+    )]
+    pub identity_account: Box<Account<'info, IdentityAccount>>,
+
+    /// CHECK: Token-2022 mint created in the handler with extensions
+    #[account(
+        mut,
+        seeds = [b"mint_2022", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub mint: UncheckedAccount<'info>,
+
+    /// CHECK: User's Token-2022 associated token account, created in the handler
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &user.key(),
+            &mint.key(),
+            &token_2022_program.key(),
+        ),
+    )]
+    pub token_account: UncheckedAccount<'info>,
+
+    /// Classic token program, used only for DSX fees
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeHandleTree<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+        has_one = authority @ ErrorCode::Unauthorized,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + HandleTree::MAX_SIZE,
+        seeds = [b"handle_tree", merkle_tree.key().as_ref()],
+        bump,
+    )]
+    pub handle_tree: Account<'info, HandleTree>,
+
+    /// CHECK: Pre-allocated concurrent merkle tree; initialized by Bubblegum
+    #[account(mut, owner = SPL_ACCOUNT_COMPRESSION_ID)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum tree config PDA; created by Bubblegum
+    #[account(
+        mut,
+        seeds = [merkle_tree.key().as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    pub tree_config: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum program
+    #[account(address = mpl_bubblegum::ID)]
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL noop program
+    #[account(address = SPL_NOOP_ID)]
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    /// CHECK: SPL account compression program
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CreateCompressedUsername<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub fee: UsernameFee<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + UsernameAccount::MAX_SIZE,
+        seeds = [b"username", username_seed(&username).as_bytes()],
+        bump,
+    )]
+    pub username_account: Box<Account<'info, UsernameAccount>>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + HandleSkeleton::MAX_SIZE,
+        seeds = [b"skeleton", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    /// CHECK: Tombstone for this skeleton, if any; checked in the handler
+    #[account(
+        seeds = [b"tombstone", username_skeleton(&username_seed(&username)).as_bytes()],
+        bump,
+    )]
+    pub tombstone: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + IdentityAccount::MAX_SIZE,
+        seeds = [b"identity", user.key().as_ref()],
+        bump,
+    )]
+    pub identity_account: Box<Account<'info, IdentityAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"handle_tree", merkle_tree.key().as_ref()],
+        bump = handle_tree.bump,
+    )]
+    pub handle_tree: Box<Account<'info, HandleTree>>,
+
+    /// CHECK: Concurrent merkle tree bound to the handle tree PDA
+    #[account(mut, address = handle_tree.merkle_tree)]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum tree config PDA; read and updated by Bubblegum
+    #[account(
+        mut,
+        seeds = [merkle_tree.key().as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    pub tree_config: UncheckedAccount<'info>,
+
+    /// CHECK: Bubblegum program
+    #[account(address = mpl_bubblegum::ID)]
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    /// CHECK: SPL noop program
+    #[account(address = SPL_NOOP_ID)]
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    /// CHECK: SPL account compression program
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+
+    /// Classic token program, used only for DSX fees
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyCompressedUsername<'info> {
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.standard == SoulboundStandard::Compressed @ ErrorCode::UnsupportedTokenStandard,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    /// CHECK: Merkle tree holding the handle's leaf
+    #[account(
+        constraint = username_account.compressed_leaf
+            .map(|leaf| leaf.merkle_tree == merkle_tree.key())
+            .unwrap_or(false) @ ErrorCode::InvalidHandleTree,
+    )]
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    /// CHECK: SPL account compression program
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    pub compression_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(value: RecordValue)]
+pub struct SetRecord<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = !username_account.suspended @ ErrorCode::HandleSuspended,
+        constraint = username_account.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + HandleRecord::MAX_SIZE,
+        seeds = [b"record", username_account.handle_id.to_le_bytes().as_ref(), value.record_key().as_bytes()],
+        bump,
+    )]
+    pub record: Account<'info, HandleRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(record_key: String)]
+pub struct DeleteRecord<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = !username_account.suspended @ ErrorCode::HandleSuspended,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"record", username_account.handle_id.to_le_bytes().as_ref(), record_key.as_bytes()],
+        bump = record.bump,
+        close = owner,
+    )]
+    pub record: Account<'info, HandleRecord>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String, record_key: String)]
+pub struct ResolveRecord<'info> {
+    #[account(
+        seeds = [b"username", username_seed(&username).as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        seeds = [b"record", username_account.handle_id.to_le_bytes().as_ref(), record_key.as_bytes()],
+        bump = record.bump,
+    )]
+    pub record: Account<'info, HandleRecord>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureSubdomains<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", parent.username.as_bytes()],
+        bump = parent.bump,
+        constraint = parent.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = parent.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+    )]
+    pub parent: Account<'info, UsernameAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + SubdomainPolicy::MAX_SIZE,
+        seeds = [b"subdomain_policy", parent.handle_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub policy: Account<'info, SubdomainPolicy>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(label: String)]
+pub struct CreateSubdomain<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: Owner of the new sub-handle
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"username", parent.username.as_bytes()],
+        bump = parent.bump,
+        constraint = !parent.suspended @ ErrorCode::HandleSuspended,
+        constraint = parent.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+    )]
+    pub parent: Account<'info, UsernameAccount>,
+
+    /// CHECK: Parent owner; receives the price of paid sub-handles
+    #[account(mut, address = parent.owner)]
+    pub parent_owner: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"subdomain_policy", parent.handle_id.to_le_bytes().as_ref()],
+        bump = policy.bump,
+    )]
+    pub policy: Option<Account<'info, SubdomainPolicy>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + SubdomainAccount::MAX_SIZE,
+        seeds = [b"subdomain", parent.handle_id.to_le_bytes().as_ref(), username_seed(&label).as_bytes()],
+        bump,
+    )]
+    pub subdomain: Account<'info, SubdomainAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintSubdomainToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"subdomain", subdomain.parent_id.to_le_bytes().as_ref(), subdomain.label.as_bytes()],
+        bump = subdomain.bump,
+        constraint = subdomain.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = subdomain.mint.is_none() @ ErrorCode::SubdomainTransferLocked,
+    )]
+    pub subdomain: Account<'info, SubdomainAccount>,
+
+    // A sub-handle revoked and issued again keeps its mint; the revoked
+    // token was burned, so the supply is checked in the handler
+    #[account(
+        init_if_needed,
+        payer = owner,
+        mint::decimals = 0,
+        mint::authority = subdomain,
+        mint::freeze_authority = subdomain,
+        seeds = [b"subdomain_mint", subdomain.key().as_ref()],
+        bump,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetSubdomainTransferLock<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", parent.username.as_bytes()],
+        bump = parent.bump,
+        constraint = parent.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub parent: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"subdomain", parent.handle_id.to_le_bytes().as_ref(), subdomain.label.as_bytes()],
+        bump = subdomain.bump,
+    )]
+    pub subdomain: Account<'info, SubdomainAccount>,
+}
+
+#[derive(Accounts)]
+pub struct TransferSubdomain<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", parent.username.as_bytes()],
+        bump = parent.bump,
+    )]
+    pub parent: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"subdomain", parent.handle_id.to_le_bytes().as_ref(), subdomain.label.as_bytes()],
+        bump = subdomain.bump,
+        constraint = subdomain.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub subdomain: Account<'info, SubdomainAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSubdomain<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", parent.username.as_bytes()],
+        bump = parent.bump,
+        constraint = parent.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub parent: Account<'info, UsernameAccount>,
+
+    /// CHECK: Sub-handle owner; receives the account rent back
+    #[account(mut, address = subdomain.owner)]
+    pub subdomain_owner: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"subdomain", parent.handle_id.to_le_bytes().as_ref(), subdomain.label.as_bytes()],
+        bump = subdomain.bump,
+        close = subdomain_owner,
+    )]
+    pub subdomain: Account<'info, SubdomainAccount>,
+
+    #[account(mut, constraint = Some(mint.key()) == subdomain.mint @ ErrorCode::MissingSubdomainToken)]
+    pub mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = subdomain.owner,
+    )]
+    pub token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(handle: String)]
+pub struct VerifyIdentity<'info> {
+    #[account(
+        seeds = [b"username", username_seed(&handle).as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", username_account.owner.as_ref()],
+        bump = identity_account.bump,
+        constraint = identity_account.username_account == username_account.key() @ ErrorCode::Unauthorized,
+    )]
+    pub identity_account: Account<'info, IdentityAccount>,
+
+    /// CHECK: Instructions sysvar
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureModeration<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + ModerationConfig::MAX_SIZE,
+        seeds = [b"moderation"],
+        bump,
+    )]
+    pub moderation_config: Account<'info, ModerationConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SuspendHandle<'info> {
+    #[account(mut)]
+    pub moderator: Signer<'info>,
+
+    #[account(
+        seeds = [b"moderation"],
+        bump = moderation_config.bump,
+        constraint = moderation_config.is_moderator(&moderator.key()) @ ErrorCode::Unauthorized,
+    )]
+    pub moderation_config: Account<'info, ModerationConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        init,
+        payer = moderator,
+        space = 8 + HandleSuspension::MAX_SIZE,
+        seeds = [
+            b"suspension",
+            username_account.key().as_ref(),
+            username_account.handle_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub suspension: Account<'info, HandleSuspension>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AppealSuspension<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"suspension",
+            username_account.key().as_ref(),
+            username_account.handle_id.to_le_bytes().as_ref(),
+        ],
+        bump = suspension.bump,
+    )]
+    pub suspension: Account<'info, HandleSuspension>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveSuspension<'info> {
+    #[account(mut)]
+    pub moderator: Signer<'info>,
+
+    #[account(
+        seeds = [b"moderation"],
+        bump = moderation_config.bump,
+        constraint = moderation_config.is_moderator(&moderator.key()) @ ErrorCode::Unauthorized,
+    )]
+    pub moderation_config: Account<'info, ModerationConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"suspension",
+            username_account.key().as_ref(),
+            username_account.handle_id.to_le_bytes().as_ref(),
+        ],
+        bump = suspension.bump,
+    )]
+    pub suspension: Account<'info, HandleSuspension>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterIssuer<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+        has_one = authority @ ErrorCode::Unauthorized,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    /// CHECK: Wallet that will sign attestations
+    pub issuer_authority: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AttestationIssuer::MAX_SIZE,
+        seeds = [b"issuer", issuer_authority.key().as_ref()],
+        bump,
+    )]
+    pub issuer: Account<'info, AttestationIssuer>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetIssuerActive<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+        has_one = authority @ ErrorCode::Unauthorized,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"issuer", issuer.issuer.as_ref()],
+        bump = issuer.bump,
+    )]
+    pub issuer: Account<'info, AttestationIssuer>,
+}
+
+#[derive(Accounts)]
+#[instruction(schema: u32)]
+pub struct Attest<'info> {
+    #[account(mut)]
+    pub issuer_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"issuer", issuer_authority.key().as_ref()],
+        bump = issuer.bump,
+        constraint = issuer.active @ ErrorCode::IssuerInactive,
+    )]
+    pub issuer: Account<'info, AttestationIssuer>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.handle_id != 0 @ ErrorCode::UsernameNotMigrated,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = issuer_authority,
+        space = 8 + Attestation::MAX_SIZE,
+        seeds = [
+            b"attestation",
+            username_account.handle_id.to_le_bytes().as_ref(),
+            issuer_authority.key().as_ref(),
+            &schema.to_le_bytes(),
+        ],
+        bump,
+    )]
+    pub attestation: Account<'info, Attestation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAttestation<'info> {
+    pub issuer_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"attestation",
+            attestation.handle_id.to_le_bytes().as_ref(),
+            issuer_authority.key().as_ref(),
+            &attestation.schema.to_le_bytes(),
+        ],
+        bump = attestation.bump,
+    )]
+    pub attestation: Account<'info, Attestation>,
+}
+
+#[derive(Accounts)]
+#[instruction(schema: u32)]
+pub struct HasAttestation<'info> {
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        seeds = [b"issuer", issuer.issuer.as_ref()],
+        bump = issuer.bump,
+    )]
+    pub issuer: Account<'info, AttestationIssuer>,
+
+    #[account(
+        seeds = [
+            b"attestation",
+            username_account.handle_id.to_le_bytes().as_ref(),
+            issuer.issuer.as_ref(),
+            &schema.to_le_bytes(),
+        ],
+        bump = attestation.bump,
+    )]
+    pub attestation: Account<'info, Attestation>,
+}
+
+#[derive(Accounts)]
+pub struct BurnUsername<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+    )]
+    pub registry: Box<Account<'info, UsernameRegistry>>,
+
+    #[account(
+        mut,
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = !username_account.suspended @ ErrorCode::HandleSuspended,
+        close = owner,
+    )]
+    pub username_account: Box<Account<'info, UsernameAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"skeleton", handle_skeleton.skeleton.as_bytes()],
+        bump = handle_skeleton.bump,
+        constraint = handle_skeleton.handle_account == username_account.key() @ ErrorCode::Unauthorized,
+        close = owner,
+    )]
+    pub handle_skeleton: Box<Account<'info, HandleSkeleton>>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", owner.key().as_ref()],
+        bump = identity_account.bump,
+        constraint = identity_account.username_account == username_account.key() @ ErrorCode::Unauthorized,
+        constraint = identity_account.active_username_nft.is_none() @ ErrorCode::IdentityHasActiveUsernameNft,
+        close = owner,
+    )]
+    pub identity_account: Box<Account<'info, IdentityAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + HandleTombstone::MAX_SIZE,
+        seeds = [b"tombstone", handle_skeleton.skeleton.as_bytes()],
+        bump,
+    )]
+    pub tombstone: Option<Box<Account<'info, HandleTombstone>>>,
+
+    #[account(
+        mut,
+        address = username_account.mint,
+        mint::token_program = token_program,
+    )]
+    pub mint: Option<Box<InterfaceAccount<'info, InterfaceMint>>>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner,
+        token::token_program = token_program,
+    )]
+    pub token_account: Option<Box<InterfaceAccount<'info, InterfaceTokenAccount>>>,
+
+    /// Classic token program or Token-2022, matching the handle's mint
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUsername<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"username_registry"],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, UsernameRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        constraint = username_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = username_account.standard == SoulboundStandard::FrozenSpl @ ErrorCode::UnsupportedTokenStandard,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(address = username_account.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        constraint = token_account.amount == 1 @ ErrorCode::MissingHandleToken,
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// ============================================================================
+// Return Types
+// ============================================================================
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UsernameInfo {
+    pub owner: Pubkey,
+    pub username: String,
+    pub created_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UsernameCheck {
+    pub canonical: String,
+    pub skeleton: String,
+    /// No registered handle shares the skeleton and it is not quarantined
+    pub available: bool,
+    /// End of the quarantine left by a released handle, while it lasts
+    pub quarantine_until: Option<i64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ResolvedRecord {
+    pub owner: Pubkey,
+    pub username: String,
+    pub key: String,
+    pub value: RecordValue,
+    pub updated_at: i64,
+}
+
+/// Structured challenge a handle owner signs off-chain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct IdentityChallenge {
+    /// Domain of the app asking for the proof
+    pub domain: String,
+    pub nonce: u64,
+    pub expires_at: i64,
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[event]
+pub struct UsernameCreated {
+    pub owner: Pubkey,
+    pub username: String,
+    pub mint: Pubkey,
+    pub fee_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameNftClaimed {
+    pub owner: Pubkey,
+    pub username: String,
+    pub mint: Pubkey,
+    pub fee_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameNftActivated {
+    pub owner: Pubkey,
+    pub identity: Pubkey,
+    pub username: String,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameNftDeactivated {
+    pub owner: Pubkey,
+    pub identity: Pubkey,
+    pub username: String,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameNftTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub username: String,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RegistryConfigUpdated {
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub fee_currency: FeeCurrency,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecoveryConfigured {
+    pub username: String,
+    pub guardians: Vec<Pubkey>,
+    pub threshold: u8,
+    pub timelock_seconds: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecoveryInitiated {
+    pub username: String,
+    pub guardian: Pubkey,
+    pub new_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecoveryApproved {
+    pub username: String,
+    pub guardian: Pubkey,
+    pub new_wallet: Pubkey,
+    pub approvals: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecoveryCancelled {
+    pub username: String,
+    pub new_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecoveryExecuted {
+    pub username: String,
+    pub old_wallet: Pubkey,
+    pub new_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameRenamed {
+    pub owner: Pubkey,
+    pub old_username: String,
+    pub new_username: String,
+    pub mint: Pubkey,
+    /// None when the new handle kept the old skeleton
+    pub quarantine_until: Option<i64>,
+    pub fee_paid: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionCreated {
+    pub handle: String,
+    pub currency: FeeCurrency,
+    pub reserve_price: u64,
+    pub min_increment: u64,
+    pub end_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidPlaced {
+    pub handle: String,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub end_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionSettled {
+    pub handle: String,
+    pub winner: Pubkey,
+    pub amount: u64,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionRefunded {
+    pub handle: String,
+    pub winner: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BidRefundClaimed {
+    pub auction: Pubkey,
+    pub bidder: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuctionCancelled {
+    pub handle: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HandleTreeCreated {
+    pub merkle_tree: Pubkey,
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecordSet {
+    pub username: String,
+    pub key: String,
+    pub value: RecordValue,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RecordDeleted {
+    pub username: String,
+    pub key: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubdomainPolicyUpdated {
+    pub parent: String,
+    pub mode: SubdomainMode,
+    pub price_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubdomainCreated {
+    pub parent: String,
+    pub label: String,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubdomainLockUpdated {
+    pub parent: String,
+    pub label: String,
+    pub locked: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubdomainTransferred {
+    pub parent: String,
+    pub label: String,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubdomainRevoked {
+    pub parent: String,
+    pub label: String,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ModerationConfigUpdated {
+    pub authority: Pubkey,
+    pub moderators: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HandleSuspended {
+    pub username: String,
+    pub reason_code: u16,
+    pub moderator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SuspensionAppealed {
+    pub username: String,
+    pub appeal_uri: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SuspensionResolved {
+    pub username: String,
+    pub lifted: bool,
+    pub moderator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct IssuerRegistered {
+    pub issuer: Pubkey,
+    pub name: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct IssuerStatusUpdated {
+    pub issuer: Pubkey,
+    pub active: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AttestationIssued {
+    pub username: String,
+    pub issuer: Pubkey,
+    pub schema: u32,
+    pub expires_at: Option<i64>,
+    pub data_hash: Option<[u8; 32]>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AttestationRevoked {
+    pub handle_id: u64,
+    pub issuer: Pubkey,
+    pub schema: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UsernameBurned {
+    pub owner: Pubkey,
+    pub username: String,
+    pub quarantine_until: Option<i64>,
+    pub timestamp: i64,
+}
+
+// ============================================================================
+// Error Codes
+// ============================================================================
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Username already exists")]
+    UsernameAlreadyExists,
+    #[msg("Username too short (minimum 3 characters)")]
+    UsernameTooShort,
+    #[msg("Username too long (maximum 20 characters)")]
+    UsernameTooLong,
+    #[msg("Invalid username format (a-z, 0-9 and single '.', '_' or '-' between characters)")]
+    InvalidUsernameFormat,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Username NFT is active and cannot move")]
+    UsernameNftActive,
+    #[msg("Username NFT is not active")]
+    UsernameNftNotActive,
+    #[msg("Username NFT not held by signer")]
+    UsernameNftNotOwned,
+    #[msg("Identity already has an active username NFT")]
+    IdentityHasActiveUsernameNft,
+    #[msg("Treasury does not match the registry")]
+    InvalidTreasury,
+    #[msg("DSX token accounts are required to pay the username fee")]
+    MissingDsxAccount,
+    #[msg("Invalid guardian set")]
+    InvalidGuardianSet,
+    #[msg("Recovery threshold must be between 1 and the number of guardians")]
+    InvalidRecoveryThreshold,
+    #[msg("Recovery timelock is too short")]
+    RecoveryTimelockTooShort,
+    #[msg("Signer is not a guardian for this handle")]
+    NotAGuardian,
+    #[msg("Invalid recovery wallet")]
+    InvalidRecoveryWallet,
+    #[msg("Guardian already approved this recovery")]
+    RecoveryAlreadyApproved,
+    #[msg("Recovery approval threshold not met")]
+    RecoveryThresholdNotMet,
+    #[msg("Recovery timelock has not elapsed")]
+    RecoveryTimelockActive,
+    #[msg("Username is quarantined after being released")]
+    UsernameQuarantined,
+    #[msg("Handle was renamed too recently")]
+    RenameCooldownActive,
+    #[msg("Handle is reserved for auction")]
+    UsernameReservedForAuction,
+    #[msg("Invalid auction parameters")]
+    InvalidAuctionParams,
+    #[msg("Auction has ended")]
+    AuctionEnded,
+    #[msg("Auction has not ended")]
+    AuctionNotEnded,
+    #[msg("Auction already has bids")]
+    AuctionHasBids,
+    #[msg("Bid is below the reserve or minimum increment")]
+    BidTooLow,
+    #[msg("Previous bidder does not match the auction")]
+    InvalidPreviousBidder,
+    #[msg("Wallet already has an identity")]
+    IdentityAlreadyExists,
+    #[msg("Operation not supported for this handle's token standard")]
+    UnsupportedTokenStandard,
+    #[msg("Invalid metadata URI")]
+    InvalidMetadataUri,
+    #[msg("Merkle tree is not a registered handle tree")]
+    InvalidHandleTree,
+    #[msg("Invalid record key")]
+    InvalidRecordKey,
+    #[msg("Invalid record value")]
+    InvalidRecordValue,
+    #[msg("Invalid sub-handle policy")]
+    InvalidSubdomainPolicy,
+    #[msg("Sub-handle policy does not allow this claim")]
+    SubdomainNotAllowed,
+    #[msg("Sub-handle is transfer-locked")]
+    SubdomainTransferLocked,
+    #[msg("Sub-handle token accounts are required")]
+    MissingSubdomainToken,
+    #[msg("Invalid identity challenge")]
+    InvalidIdentityChallenge,
+    #[msg("Handle is suspended")]
+    HandleSuspended,
+    #[msg("Invalid moderator set")]
+    InvalidModeratorSet,
+    #[msg("Invalid or duplicate appeal")]
+    InvalidAppeal,
+    #[msg("Invalid issuer name")]
+    InvalidIssuerName,
+    #[msg("Issuer is not active")]
+    IssuerInactive,
+    #[msg("Attestation expiry must be in the future")]
+    InvalidAttestationExpiry,
+    #[msg("Attestation already revoked")]
+    AttestationRevoked,
+    #[msg("Handle token accounts are required")]
+    MissingHandleToken,
+    #[msg("Tombstone account is required while quarantine is enabled")]
+    MissingTombstone,
+    #[msg("Registry durations must be non-negative and the auction-only length at most 20")]
+    InvalidRegistryConfig,
+    #[msg("Username account already uses the current layout")]
+    UsernameAlreadyMigrated,
+    #[msg("Handle token is not delegated to the handle; run migrate_username first")]
+    HandleTokenNotDelegated,
+    #[msg("The previous handle token was not burned")]
+    HandleTokenNotBurned,
+    #[msg("Handle predates handle ids; run migrate_username first")]
+    UsernameNotMigrated,
+    #[msg("Only classic SPL handles can be renamed; burn and re-register Token-2022 or compressed handles")]
+    RenameUnsupported,
+    #[msg("Skeleton account is required when the new handle has a different skeleton")]
+    MissingHandleSkeleton,
+    #[msg("Handle accounts must be omitted when the winning bid is refunded")]
+    UnexpectedHandleAccounts,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(auction_only_max_length: u8) -> UsernameRegistry {
+        UsernameRegistry {
+            authority: Pubkey::default(),
+            treasury: Pubkey::default(),
+            fee_currency: FeeCurrency::Lamports,
+            dsx_mint: Pubkey::default(),
+            length_prices: [0; PRICE_TABLE_LEN],
+            rename_cooldown_seconds: 0,
+            quarantine_seconds: 0,
+            auction_only_max_length,
+            total_usernames: 0,
+            total_username_nfts: 0,
+            next_handle_id: 1,
+            bump: 0,
+        }
+    }
+
+    fn config(auction_only_max_length: u8) -> RegistryConfig {
+        RegistryConfig {
+            authority: Pubkey::default(),
+            treasury: Pubkey::default(),
+            fee_currency: FeeCurrency::Lamports,
+            dsx_mint: Pubkey::default(),
+            length_prices: [0; PRICE_TABLE_LEN],
+            rename_cooldown_seconds: 0,
+            quarantine_seconds: 0,
+            auction_only_max_length,
+        }
+    }
+
+    #[test]
+    fn canonical_form_drops_at_and_lowercases() {
+        assert_eq!(canonical_username("@Alice_01"), "alice_01");
+        assert_eq!(canonical_username("bob"), "bob");
+    }
+
+    #[test]
+    fn seeds_stay_in_bounds_for_overlong_names() {
+        let long = format!("@{}", "A".repeat(200));
+        let seed = username_seed(&long);
+        assert_eq!(seed.len(), MAX_USERNAME_LEN + 1);
+        assert!(Pubkey::try_find_program_address(&[b"username", seed.as_bytes()], &crate::ID).is_some());
+        assert_eq!(
+            validate_username(&canonical_username(&long)),
+            Err(ErrorCode::UsernameTooLong.into())
+        );
+
+        assert_eq!(username_seed("@Alice_01"), "alice_01");
+        assert_eq!(username_seed(&"é".repeat(20)).len(), MAX_USERNAME_LEN);
+    }
+
+    #[test]
+    fn length_limits() {
+        assert_eq!(validate_username("ab"), Err(ErrorCode::UsernameTooShort.into()));
+        assert!(validate_username("abc").is_ok());
+        assert!(validate_username(&"a".repeat(MAX_USERNAME_LEN)).is_ok());
+        assert_eq!(
+            validate_username(&"a".repeat(MAX_USERNAME_LEN + 1)),
+            Err(ErrorCode::UsernameTooLong.into())
+        );
+    }
+
+    #[test]
+    fn grammar_rejects_bad_characters_and_separators() {
+        for bad in ["Alice", "al ice", "al@ice", "_alice", "alice.", "al..ice", "al-_ice", "alïce"] {
+            assert_eq!(
+                validate_username(bad),
+                Err(ErrorCode::InvalidUsernameFormat.into()),
+                "{bad}"
+            );
+        }
+        for good in ["alice", "a.b_c-d", "0xdead", "neo_engine"] {
+            assert!(validate_username(good).is_ok(), "{good}");
+        }
+    }
+
+    #[test]
+    fn confusables_share_a_skeleton() {
+        assert_eq!(username_skeleton("g00gle"), username_skeleton("google"));
+        assert_eq!(username_skeleton("paypa1"), username_skeleton("paypal"));
+        assert_eq!(username_skeleton("paypai"), username_skeleton("paypal"));
+        assert_eq!(username_skeleton("rnodern"), username_skeleton("modem"));
+        assert_eq!(username_skeleton("vvallet"), username_skeleton("wallet"));
+        assert_eq!(username_skeleton("neo.engine"), username_skeleton("neo-engine"));
+        assert_eq!(username_skeleton("neo-engine"), username_skeleton("neo_engine"));
+        assert_ne!(username_skeleton("alice"), username_skeleton("alicia"));
+    }
+
+    #[test]
+    fn short_handles_are_reserved_for_auction() {
+        let reserved = registry(4);
+        assert_eq!(reserved.ensure_open(3), Err(ErrorCode::UsernameReservedForAuction.into()));
+        assert_eq!(reserved.ensure_open(4), Err(ErrorCode::UsernameReservedForAuction.into()));
+        assert!(reserved.ensure_open(5).is_ok());
+        assert!(registry(0).ensure_open(MIN_USERNAME_LEN).is_ok());
+    }
+
+    #[test]
+    fn registry_config_is_validated() {
+        let mut target = registry(0);
+        assert!(target.apply_config(config(MAX_USERNAME_LEN as u8)).is_ok());
+        assert_eq!(
+            target.apply_config(config(MAX_USERNAME_LEN as u8 + 1)),
+            Err(ErrorCode::InvalidRegistryConfig.into())
+        );
+
+        let mut negative = config(4);
+        negative.rename_cooldown_seconds = -1;
+        assert_eq!(target.apply_config(negative), Err(ErrorCode::InvalidRegistryConfig.into()));
+
+        let mut negative = config(4);
+        negative.quarantine_seconds = -1;
+        assert_eq!(target.apply_config(negative), Err(ErrorCode::InvalidRegistryConfig.into()));
+        assert_eq!(target.auction_only_max_length, MAX_USERNAME_LEN as u8);
+    }
+}
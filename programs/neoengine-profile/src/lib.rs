@@ -1,4 +1,21 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::hash::hash;
+use anchor_spl::{
+    associated_token::{get_associated_token_address, AssociatedToken},
+    metadata::{
+        self,
+        mpl_token_metadata::{
+            accounts::{MasterEdition as MasterEditionPda, Metadata as MetadataPda},
+            types::DataV2,
+        },
+        BurnNft, CreateMasterEditionV3, CreateMetadataAccountsV3, FreezeDelegatedAccount, Metadata,
+        ThawDelegatedAccount, UpdateMetadataAccountsV2,
+    },
+    token::{self, Mint, Token, TokenAccount},
+};
+use neoengine_identity::UsernameAccount;
 
 declare_id!("DfgjPKaYeRdCt6L1eaUpQrU7uRM1bdshgSVJRihfmqas");
 
@@ -7,29 +24,32 @@ pub mod neoengine_profile {
     use super::*;
 
     /// Create a new Profile with rich metadata support
-    /// This stores all profile data on-chain with IPFS metadata URI
+    /// This stores all profile data on-chain with IPFS metadata URI.
+    /// The handle is copied from the caller's identity username account.
+    /// Social links are added afterwards with `set_social_link`.
     pub fn create_profile(
         ctx: Context<CreateProfile>,
         metadata_uri: String,
-        sbt_handle: String,
         name: String,
         bio: String,
         country: String,
         website: String,
-        twitter: String,
-        discord: String,
     ) -> Result<()> {
-        let profile = &mut ctx.accounts.profile_state;
         let clock = Clock::get()?;
+        ensure_cooldown_elapsed(&ctx.accounts.tombstone, clock.unix_timestamp)?;
+        let profile = &mut ctx.accounts.profile_state;
 
         // Validate inputs
-        require!(metadata_uri.len() <= 200, ProfileError::InvalidMetadataUri);
-        require!(sbt_handle.len() <= 32, ProfileError::InvalidSbtHandle);
-        require!(name.len() <= 50, ProfileError::InvalidName);
+        require!(metadata_uri.len() <= MAX_METADATA_URI_LEN, ProfileError::InvalidMetadataUri);
+        require!(name.len() <= MAX_NAME_LEN, ProfileError::InvalidName);
+        validate_details(&bio, &country, &website)?;
 
         // Initialize profile state with rich metadata
+        let sbt_handle = ctx.accounts.username_account.username.clone();
         profile.owner = ctx.accounts.owner.key();
         profile.sbt_handle = sbt_handle.clone();
+        profile.username_account = ctx.accounts.username_account.key();
+        profile.profile_mint = None;
         profile.metadata_uri = metadata_uri.clone();
         
         // Store profile metadata on-chain
@@ -37,23 +57,25 @@ pub mod neoengine_profile {
         profile.bio = bio.clone();
         profile.country = country.clone();
         profile.website = website.clone();
-        profile.social_links = SocialLinks {
-            twitter: twitter.clone(),
-            discord: discord.clone(),
-        };
+        profile.social_links = Vec::new();
         
         // Timestamps and versioning
+        profile.verifications = Vec::new();
+        profile.equipment = EquipmentSlots::default();
+        profile.reputation_score = 0;
+        profile.badges = Vec::new();
+        profile.private_field_count = 0;
         profile.created_at = clock.unix_timestamp;
         profile.updated_at = clock.unix_timestamp;
         profile.update_count = 0;
-        profile.version = 1;
+        profile.version = PROFILE_VERSION;
         profile.bump = ctx.bumps.profile_state;
 
         emit!(ProfileCreatedEvent {
             owner: ctx.accounts.owner.key(),
             sbt_handle: sbt_handle.clone(),
             name: name.clone(),
-            metadata_uri: metadata_uri,
+            metadata_uri,
             timestamp: clock.unix_timestamp,
         });
 
@@ -61,23 +83,23 @@ pub mod neoengine_profile {
         Ok(())
     }
 
-    /// Update profile metadata (both on-chain and IPFS URI)
+    /// Update profile metadata (both on-chain and IPFS URI). If the profile
+    /// has an NFT, its Metaplex URI is updated to match.
     pub fn update_profile(
-        ctx: Context<UpdateProfile>,
+        ctx: Context<UpdateProfileMetadata>,
         new_metadata_uri: String,
         name: String,
         bio: String,
         country: String,
         website: String,
-        twitter: String,
-        discord: String,
     ) -> Result<()> {
         let profile = &mut ctx.accounts.profile_state;
         let clock = Clock::get()?;
 
         // Validate inputs
-        require!(new_metadata_uri.len() <= 200, ProfileError::InvalidMetadataUri);
-        require!(name.len() <= 50, ProfileError::InvalidName);
+        require!(new_metadata_uri.len() <= MAX_METADATA_URI_LEN, ProfileError::InvalidMetadataUri);
+        require!(name.len() <= MAX_NAME_LEN, ProfileError::InvalidName);
+        validate_details(&bio, &country, &website)?;
 
         // Update metadata
         profile.metadata_uri = new_metadata_uri.clone();
@@ -85,30 +107,602 @@ pub mod neoengine_profile {
         profile.bio = bio.clone();
         profile.country = country.clone();
         profile.website = website.clone();
-        profile.social_links.twitter = twitter.clone();
-        profile.social_links.discord = discord.clone();
         
-        // Update timestamps and versioning
+        // Update timestamps
         profile.updated_at = clock.unix_timestamp;
         profile.update_count += 1;
-        profile.version = 2; // Increment version for compatibility
 
         emit!(ProfileUpdatedEvent {
             owner: ctx.accounts.owner.key(),
             name: name.clone(),
-            new_metadata_uri: new_metadata_uri,
+            new_metadata_uri,
             update_count: profile.update_count,
             timestamp: clock.unix_timestamp,
         });
 
         msg!("Profile metadata updated for @{}: {}", profile.sbt_handle, name);
+        ctx.accounts.sync_nft_uri(ctx.bumps.profile_authority)
+    }
+
+    /// Update only the provided fields. Every field is checked against the
+    /// limits behind `ProfileState::SPACE`, and the event lists the fields
+    /// whose value actually changed. A changed URI is pushed to the NFT.
+    pub fn update_profile_fields(
+        ctx: Context<UpdateProfileMetadata>,
+        update: ProfileFieldsUpdate,
+    ) -> Result<()> {
+        update.validate()?;
+
+        let profile: &mut ProfileState = &mut ctx.accounts.profile_state;
+        let clock = Clock::get()?;
+        let mut changed_fields = Vec::new();
+
+        let fields = [
+            (update.metadata_uri, &mut profile.metadata_uri, ProfileField::MetadataUri),
+            (update.name, &mut profile.name, ProfileField::Name),
+            (update.bio, &mut profile.bio, ProfileField::Bio),
+            (update.country, &mut profile.country, ProfileField::Country),
+            (update.website, &mut profile.website, ProfileField::Website),
+        ];
+        for (new_value, current, field) in fields {
+            if let Some(new_value) = new_value {
+                if *current != new_value {
+                    *current = new_value;
+                    changed_fields.push(field);
+                }
+            }
+        }
+        require!(!changed_fields.is_empty(), ProfileError::NoFieldsChanged);
+        let uri_changed = changed_fields.contains(&ProfileField::MetadataUri);
+
+        profile.updated_at = clock.unix_timestamp;
+        profile.update_count += 1;
+
+        emit!(ProfileFieldsUpdatedEvent {
+            owner: ctx.accounts.owner.key(),
+            changed_fields,
+            update_count: profile.update_count,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Profile fields updated for @{}", profile.sbt_handle);
+        if uri_changed {
+            ctx.accounts.sync_nft_uri(ctx.bumps.profile_authority)?;
+        }
+        Ok(())
+    }
+
+    /// Mint the profile's NFT: a one-of-one Metaplex NFT in the owner's
+    /// wallet pointing at `metadata_uri`. The program PDA is update
+    /// authority and freeze delegate, and the token account is frozen so
+    /// the NFT cannot be transferred.
+    pub fn mint_profile_nft(ctx: Context<MintProfileNft>) -> Result<()> {
+        let handle = ctx.accounts.profile_state.sbt_handle.clone();
+        let uri = ctx.accounts.profile_state.metadata_uri.clone();
+        let signer_seeds: &[&[&[u8]]] = &[&[PROFILE_AUTHORITY_SEED, &[ctx.bumps.profile_authority]]];
+
+        let owner = ctx.accounts.owner.to_account_info();
+        let profile_authority = ctx.accounts.profile_authority.to_account_info();
+        let mint = ctx.accounts.mint.to_account_info();
+        let token_account = ctx.accounts.token_account.to_account_info();
+        let nft_metadata = ctx.accounts.nft_metadata.to_account_info();
+        let master_edition = ctx.accounts.master_edition.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let token_metadata_program = ctx.accounts.token_metadata_program.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let rent = ctx.accounts.rent.to_account_info();
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                token::MintTo {
+                    mint: mint.clone(),
+                    to: token_account.clone(),
+                    authority: profile_authority.clone(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+
+        metadata::create_metadata_accounts_v3(
+            CpiContext::new_with_signer(
+                token_metadata_program.clone(),
+                CreateMetadataAccountsV3 {
+                    metadata: nft_metadata.clone(),
+                    mint: mint.clone(),
+                    mint_authority: profile_authority.clone(),
+                    payer: owner.clone(),
+                    update_authority: profile_authority.clone(),
+                    system_program: system_program.clone(),
+                    rent: rent.clone(),
+                },
+                signer_seeds,
+            ),
+            profile_nft_data(&handle, &uri),
+            true,
+            true,
+            None,
+        )?;
+
+        // Hands mint and freeze authority to the edition and caps supply at one
+        metadata::create_master_edition_v3(
+            CpiContext::new_with_signer(
+                token_metadata_program.clone(),
+                CreateMasterEditionV3 {
+                    edition: master_edition.clone(),
+                    mint: mint.clone(),
+                    update_authority: profile_authority.clone(),
+                    mint_authority: profile_authority.clone(),
+                    payer: owner.clone(),
+                    metadata: nft_metadata.clone(),
+                    token_program: token_program.clone(),
+                    system_program,
+                    rent,
+                },
+                signer_seeds,
+            ),
+            Some(0),
+        )?;
+
+        // Delegate to the program PDA, then freeze through the edition
+        token::approve(
+            CpiContext::new(
+                token_program.clone(),
+                token::Approve {
+                    to: token_account.clone(),
+                    delegate: profile_authority.clone(),
+                    authority: owner,
+                },
+            ),
+            1,
+        )?;
+        metadata::freeze_delegated_account(CpiContext::new_with_signer(
+            token_metadata_program,
+            FreezeDelegatedAccount {
+                metadata: nft_metadata,
+                delegate: profile_authority,
+                token_account,
+                edition: master_edition,
+                mint,
+                token_program,
+            },
+            signer_seeds,
+        ))?;
+
+        let profile = &mut ctx.accounts.profile_state;
+        let mint_key = ctx.accounts.mint.key();
+        profile.profile_mint = Some(mint_key);
+
+        emit!(ProfileNftMintedEvent {
+            owner: profile.owner,
+            mint: mint_key,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Profile NFT minted for @{}", handle);
+        Ok(())
+    }
+
+    /// Add a social link, or change the handle of the existing link for
+    /// `platform`. A changed handle loses its verification.
+    pub fn set_social_link(
+        ctx: Context<UpdateProfile>,
+        platform: SocialPlatform,
+        handle: String,
+    ) -> Result<()> {
+        require!(
+            !handle.is_empty() && handle.len() <= MAX_SOCIAL_HANDLE_LEN,
+            ProfileError::InvalidSocialHandle
+        );
+
+        let profile = &mut ctx.accounts.profile_state;
+        let clock = Clock::get()?;
+
+        match profile.social_links.iter_mut().find(|l| l.platform == platform) {
+            Some(link) => {
+                require!(link.handle != handle, ProfileError::NoFieldsChanged);
+                link.handle = handle.clone();
+                link.verified_by = None;
+                link.verified_at = 0;
+            }
+            None => {
+                require!(profile.social_links.len() < MAX_SOCIAL_LINKS, ProfileError::TooManySocialLinks);
+                profile.social_links.push(SocialLink {
+                    platform,
+                    handle: handle.clone(),
+                    verified_by: None,
+                    verified_at: 0,
+                });
+            }
+        }
+        profile.updated_at = clock.unix_timestamp;
+        profile.update_count += 1;
+
+        emit!(SocialLinkUpdatedEvent {
+            owner: profile.owner,
+            platform,
+            handle: Some(handle),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Remove the social link for `platform`
+    pub fn remove_social_link(
+        ctx: Context<UpdateProfile>,
+        platform: SocialPlatform,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let clock = Clock::get()?;
+
+        let index = profile
+            .social_links
+            .iter()
+            .position(|l| l.platform == platform)
+            .ok_or(ProfileError::SocialLinkNotFound)?;
+        profile.social_links.remove(index);
+        profile.updated_at = clock.unix_timestamp;
+        profile.update_count += 1;
+
+        emit!(SocialLinkUpdatedEvent {
+            owner: profile.owner,
+            platform,
+            handle: None,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Mark a social link verified or unverified after an off-chain check
+    /// (allowlisted link oracle only). `handle` is the text the oracle
+    /// checked; the call fails if the owner changed it since.
+    pub fn verify_social_link(
+        ctx: Context<VerifySocialLink>,
+        platform: SocialPlatform,
+        handle: String,
+        verified: bool,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let oracle = ctx.accounts.oracle.key();
+        let clock = Clock::get()?;
+
+        let link = profile
+            .social_links
+            .iter_mut()
+            .find(|l| l.platform == platform)
+            .ok_or(ProfileError::SocialLinkNotFound)?;
+        require!(link.handle == handle, ProfileError::SocialLinkChanged);
+
+        if verified {
+            link.verified_by = Some(oracle);
+            link.verified_at = clock.unix_timestamp;
+        } else {
+            link.verified_by = None;
+            link.verified_at = 0;
+        }
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(SocialLinkVerifiedEvent {
+            owner: profile.owner,
+            platform,
+            handle,
+            verified,
+            oracle,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Replace the oracles allowed to verify social links (admin only)
+    pub fn set_link_oracles(
+        ctx: Context<UpdateConfig>,
+        oracles: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(oracles.len() <= MAX_LINK_ORACLES, ProfileError::InvalidLinkOracles);
+        ctx.accounts.config.link_oracles = oracles.clone();
+
+        emit!(LinkOraclesUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            oracles,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Publish or rotate the caller's x25519 public key, which others use to
+    /// wrap private field keys for this wallet
+    pub fn set_encryption_key(ctx: Context<SetEncryptionKey>, x25519_pubkey: [u8; 32]) -> Result<()> {
+        let encryption_key = &mut ctx.accounts.encryption_key;
+        let clock = Clock::get()?;
+
+        encryption_key.owner = ctx.accounts.owner.key();
+        encryption_key.x25519_pubkey = x25519_pubkey;
+        encryption_key.updated_at = clock.unix_timestamp;
+        encryption_key.bump = ctx.bumps.encryption_key;
+
+        emit!(EncryptionKeyPublishedEvent {
+            owner: encryption_key.owner,
+            x25519_pubkey,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Write a private field: ciphertext under a fresh content key plus
+    /// that key wrapped for each recipient, in the format of the
+    /// `neoengine-private-fields` crate. Only ciphertext reaches the chain.
+    /// Rewriting the field with a new key is how access is fully revoked.
+    /// Every recipient must have published an encryption key; those
+    /// `EncryptionKey` accounts are passed as remaining accounts, in
+    /// recipient order.
+    pub fn set_private_field(
+        ctx: Context<SetPrivateField>,
+        label: String,
+        nonce: [u8; 12],
+        ciphertext: Vec<u8>,
+        recipients: Vec<WrappedKey>,
+    ) -> Result<()> {
+        require!(
+            !label.is_empty() && label.len() <= MAX_PRIVATE_FIELD_LABEL_LEN,
+            ProfileError::InvalidPrivateFieldLabel
+        );
+        require!(ciphertext.len() <= MAX_PRIVATE_FIELD_CIPHERTEXT_LEN, ProfileError::PrivateFieldTooLarge);
+        require!(recipients.len() <= MAX_FIELD_RECIPIENTS, ProfileError::TooManyFieldRecipients);
+        require!(
+            ctx.remaining_accounts.len() == recipients.len(),
+            ProfileError::EncryptionKeyNotPublished
+        );
+        for (i, wrapped) in recipients.iter().enumerate() {
+            require!(
+                !recipients[..i].iter().any(|r| r.recipient == wrapped.recipient),
+                ProfileError::FieldRecipientExists
+            );
+            require_published_key(&ctx.remaining_accounts[i], &wrapped.recipient)?;
+        }
+
+        let field = &mut ctx.accounts.private_field;
+        if field.owner == Pubkey::default() {
+            let profile = &mut ctx.accounts.profile_state;
+            require!(
+                (profile.private_field_count as usize) < MAX_PRIVATE_FIELDS,
+                ProfileError::TooManyPrivateFields
+            );
+            profile.private_field_count += 1;
+        }
+
+        let clock = Clock::get()?;
+        field.owner = ctx.accounts.owner.key();
+        field.label = label.clone();
+        field.nonce = nonce;
+        field.ciphertext = ciphertext;
+        field.recipients = recipients;
+        field.updated_at = clock.unix_timestamp;
+        field.bump = ctx.bumps.private_field;
+
+        emit!(PrivateFieldUpdatedEvent {
+            owner: field.owner,
+            label,
+            recipients: field.recipients.iter().map(|r| r.recipient).collect(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Share a private field with one more recipient, who must have
+    /// published an encryption key
+    pub fn add_private_field_recipient(
+        ctx: Context<AddPrivateFieldRecipient>,
+        _label: String,
+        wrapped: WrappedKey,
+    ) -> Result<()> {
+        let field = &mut ctx.accounts.private_field;
+        require!(
+            !field.recipients.iter().any(|r| r.recipient == wrapped.recipient),
+            ProfileError::FieldRecipientExists
+        );
+        require!(field.recipients.len() < MAX_FIELD_RECIPIENTS, ProfileError::TooManyFieldRecipients);
+
+        let clock = Clock::get()?;
+        let recipient = wrapped.recipient;
+        field.recipients.push(wrapped);
+        field.updated_at = clock.unix_timestamp;
+
+        emit!(PrivateFieldAccessEvent {
+            owner: field.owner,
+            label: field.label.clone(),
+            recipient,
+            granted: true,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Drop a recipient's wrapped key. Anyone who already unwrapped the
+    /// key can still read the current ciphertext; follow with
+    /// `set_private_field` under a new key to cut them off.
+    pub fn revoke_private_field_recipient(
+        ctx: Context<UpdatePrivateField>,
+        _label: String,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        let field = &mut ctx.accounts.private_field;
+        let index = field
+            .recipients
+            .iter()
+            .position(|r| r.recipient == recipient)
+            .ok_or(ProfileError::FieldRecipientNotFound)?;
+
+        let clock = Clock::get()?;
+        field.recipients.remove(index);
+        field.updated_at = clock.unix_timestamp;
+
+        emit!(PrivateFieldAccessEvent {
+            owner: field.owner,
+            label: field.label.clone(),
+            recipient,
+            granted: false,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Delete a private field and reclaim its rent
+    pub fn delete_private_field(ctx: Context<DeletePrivateField>, label: String) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        profile.private_field_count = profile.private_field_count.saturating_sub(1);
+
+        emit!(PrivateFieldDeletedEvent {
+            owner: ctx.accounts.owner.key(),
+            label,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Re-bind the profile to a handle its owner holds, e.g. after a rename
+    /// moved the identity to a new username account. If the profile has an
+    /// NFT, its Metaplex name is renamed to match.
+    pub fn sync_handle(ctx: Context<SyncHandle>) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let username_account = &ctx.accounts.username_account;
+        let old_handle = profile.sbt_handle.clone();
+        let handle_changed = old_handle != username_account.username;
+
+        profile.sbt_handle = username_account.username.clone();
+        profile.username_account = username_account.key();
+        profile.updated_at = Clock::get()?.unix_timestamp;
+
+        emit!(ProfileHandleSyncedEvent {
+            owner: profile.owner,
+            old_handle,
+            new_handle: profile.sbt_handle.clone(),
+            timestamp: profile.updated_at,
+        });
+
+        msg!("Profile handle synced to @{}", profile.sbt_handle);
+        if handle_changed {
+            sync_profile_nft(
+                profile,
+                &ctx.accounts.nft_metadata,
+                &ctx.accounts.profile_authority,
+                &ctx.accounts.token_metadata_program,
+                ctx.bumps.profile_authority,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delete a private field the lost wallet left on a recovered profile.
+    /// Its ciphertext is sealed to keys the new owner does not hold, so it
+    /// can only be removed; the rent goes to the new owner.
+    pub fn purge_recovered_private_field(
+        ctx: Context<PurgeRecoveredPrivateField>,
+        label: String,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.old_profile_state;
+        profile.private_field_count = profile.private_field_count.saturating_sub(1);
+
+        emit!(PrivateFieldDeletedEvent {
+            owner: profile.owner,
+            label,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Move a profile to the wallet that recovered its handle. The profile
+    /// PDA is keyed by owner, so the data is copied into the new owner's
+    /// PDA and the old account is closed. The new owner is held to their
+    /// own recreate cooldown. The old wallet's private fields must be
+    /// purged first. The profile NFT is moved to the new owner and frozen
+    /// there again, so the profile keeps its mint and the equipment staked
+    /// against it.
+    pub fn migrate_recovered_profile(ctx: Context<MigrateRecoveredProfile>) -> Result<()> {
+        let clock = Clock::get()?;
+        ensure_cooldown_elapsed(&ctx.accounts.new_tombstone, clock.unix_timestamp)?;
+        let old_profile = &ctx.accounts.old_profile_state;
+        require!(old_profile.private_field_count == 0, ProfileError::PrivateFieldsRemain);
+        let new_owner = ctx.accounts.new_owner.key();
+
+        if let Some(mint) = old_profile.profile_mint {
+            ctx.accounts.move_profile_nft(mint, ctx.bumps.profile_authority)?;
+        }
+
+        let old_profile = &ctx.accounts.old_profile_state;
+        ctx.accounts.new_profile_state.set_inner(ProfileState {
+            owner: new_owner,
+            sbt_handle: ctx.accounts.username_account.username.clone(),
+            updated_at: clock.unix_timestamp,
+            bump: ctx.bumps.new_profile_state,
+            ..(**old_profile).clone()
+        });
+
+        emit!(ProfileRecoveredEvent {
+            old_owner: old_profile.owner,
+            new_owner,
+            sbt_handle: ctx.accounts.username_account.username.clone(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Profile for @{} moved to recovered wallet", ctx.accounts.username_account.username);
+        Ok(())
+    }
+
+    /// Delete a profile. Private fields must be deleted first. The profile
+    /// NFT is thawed and burned, the published encryption key is closed,
+    /// and the account is closed to the owner, which zeroes its data and so
+    /// wipes every on-chain string. A tombstone blocks `create_profile` for
+    /// `PROFILE_RECREATE_COOLDOWN` seconds. Accepts every supported layout
+    /// version.
+    pub fn close_profile(ctx: Context<CloseProfile>) -> Result<()> {
+        let profile = ProfileState::load_any(&ctx.accounts.profile_state)?;
+        require_keys_eq!(profile.owner, ctx.accounts.owner.key(), ProfileError::Unauthorized);
+        require!(profile.private_field_count == 0, ProfileError::PrivateFieldsRemain);
+
+        if let Some(mint) = profile.profile_mint {
+            ctx.accounts.burn_profile_nft(mint, ctx.bumps.profile_authority)?;
+        }
+        let encryption_key = ctx.accounts.encryption_key.to_account_info();
+        if encryption_key.owner == &crate::ID {
+            close_account(&encryption_key, &ctx.accounts.owner.to_account_info())?;
+        }
+
+        let clock = Clock::get()?;
+        let tombstone = &mut ctx.accounts.tombstone;
+        tombstone.owner = profile.owner;
+        tombstone.closed_at = clock.unix_timestamp;
+        tombstone.bump = ctx.bumps.tombstone;
+
+        close_account(
+            &ctx.accounts.profile_state.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+        )?;
+
+        emit!(ProfileClosedEvent {
+            owner: profile.owner,
+            sbt_handle: profile.sbt_handle,
+            timestamp: clock.unix_timestamp,
+        });
+
         Ok(())
     }
 
     /// Get profile summary (view function)
+    /// Accepts every supported layout version, migrated or not.
     pub fn get_profile_summary(ctx: Context<GetProfile>) -> Result<ProfileSummary> {
-        let profile = &ctx.accounts.profile_state;
-        
+        let profile = ProfileState::load_any(&ctx.accounts.profile_state)?;
+
         Ok(ProfileSummary {
             owner: profile.owner,
             sbt_handle: profile.sbt_handle.clone(),
@@ -118,85 +712,1094 @@ pub mod neoengine_profile {
             created_at: profile.created_at,
             updated_at: profile.updated_at,
             update_count: profile.update_count,
+            verifications: profile.verifications.clone(),
+            equipment: profile.equipment.clone(),
+            reputation_score: profile.reputation_score,
+            badges: profile.badges.clone(),
+            social_links: profile.social_links.clone(),
+            profile_mint: profile.profile_mint,
         })
     }
 
-    /// Mark profile as verified (admin only - future feature)
-    pub fn set_verified_status(
-        ctx: Context<SetVerified>,
-        verified: bool,
+    /// Set the reputation score. Only callable through CPI from an
+    /// allowlisted program signing with its `REPUTATION_AUTHORITY_SEED` PDA.
+    pub fn update_reputation_score(ctx: Context<ReputationCpi>, score: u64) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let clock = Clock::get()?;
+        let old_score = profile.reputation_score;
+
+        profile.reputation_score = score;
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(ReputationUpdatedEvent {
+            owner: profile.owner,
+            old_score,
+            new_score: score,
+            caller_program: ctx.accounts.caller_program.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Award a badge. Same CPI-only rules as `update_reputation_score`;
+    /// each badge id can be held once.
+    pub fn award_badge(ctx: Context<ReputationCpi>, badge_id: String) -> Result<()> {
+        require!(
+            !badge_id.is_empty() && badge_id.len() <= MAX_BADGE_ID_LEN,
+            ProfileError::InvalidBadgeId
+        );
+
+        let profile = &mut ctx.accounts.profile_state;
+        require!(
+            !profile.badges.iter().any(|b| b.badge_id == badge_id),
+            ProfileError::BadgeAlreadyAwarded
+        );
+        require!(profile.badges.len() < MAX_BADGES, ProfileError::TooManyBadges);
+
+        let clock = Clock::get()?;
+        let caller_program = ctx.accounts.caller_program.key();
+        profile.badges.push(Badge {
+            badge_id: badge_id.clone(),
+            awarded_by: caller_program,
+            awarded_at: clock.unix_timestamp,
+        });
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(BadgeAwardedEvent {
+            owner: profile.owner,
+            badge_id,
+            caller_program,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Replace the programs allowed to write reputation and badges (admin only)
+    pub fn set_reputation_programs(
+        ctx: Context<UpdateConfig>,
+        programs: Vec<Pubkey>,
     ) -> Result<()> {
+        require!(programs.len() <= MAX_REPUTATION_PROGRAMS, ProfileError::InvalidReputationPrograms);
+        ctx.accounts.config.reputation_programs = programs.clone();
+
+        emit!(ReputationProgramsUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            programs,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Equip a staked cosmetic into a profile slot. The profile must have
+    /// minted its NFT: the cosmetics program's stake record for (cosmetic
+    /// mint, profile NFT mint) must belong to the profile owner and carry
+    /// a cosmetic type that maps to the slot.
+    pub fn equip_cosmetic(ctx: Context<EquipCosmetic>, slot: EquipmentSlot) -> Result<()> {
+        let stake = CosmeticStakeRecord::try_from_account(&ctx.accounts.stake_record)?;
         let profile = &mut ctx.accounts.profile_state;
-        
-        // TODO: Add admin authority check
-        profile.verified = verified;
-        profile.updated_at = Clock::get()?.unix_timestamp;
 
-        emit!(ProfileVerificationEvent {
+        require_keys_eq!(stake.user, profile.owner, ProfileError::CosmeticNotStaked);
+        require_keys_eq!(stake.cosmetic_mint, ctx.accounts.cosmetic_mint.key(), ProfileError::CosmeticNotStaked);
+        require_keys_eq!(stake.profile_mint, ctx.accounts.profile_mint.key(), ProfileError::CosmeticNotStaked);
+        require!(
+            EquipmentSlot::from_cosmetic_type(&stake.cosmetic_type) == Some(slot),
+            ProfileError::CosmeticSlotMismatch
+        );
+
+        let clock = Clock::get()?;
+        *profile.equipment.slot_mut(slot) = Some(EquippedCosmetic {
+            cosmetic_mint: stake.cosmetic_mint,
+            profile_mint: stake.profile_mint,
+            equipped_at: clock.unix_timestamp,
+        });
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(CosmeticEquippedEvent {
             owner: profile.owner,
-            sbt_handle: profile.sbt_handle.clone(),
-            verified,
-            timestamp: profile.updated_at,
+            slot,
+            cosmetic_mint: Some(stake.cosmetic_mint),
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!("Equipped {:?} on @{}", slot, profile.sbt_handle);
+        Ok(())
+    }
+
+    /// Clear a profile slot
+    pub fn unequip_cosmetic(ctx: Context<UpdateProfile>, slot: EquipmentSlot) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let clock = Clock::get()?;
+
+        *profile.equipment.slot_mut(slot) = None;
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(CosmeticEquippedEvent {
+            owner: profile.owner,
+            slot,
+            cosmetic_mint: None,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Clear a slot whose cosmetic is no longer staked to the profile; anyone
+    /// may call it. The slot is stale when its stake record is gone, or no
+    /// longer names the profile owner, the cosmetic or the profile's
+    /// current NFT.
+    pub fn unequip_stale(ctx: Context<UnequipStale>, slot: EquipmentSlot) -> Result<()> {
+        let cosmetics_program = ctx.accounts.config.cosmetics_program;
+        let stake_record = &ctx.accounts.stake_record;
+        let profile = &mut ctx.accounts.profile_state;
+        let equipped = profile
+            .equipment
+            .slot_mut(slot)
+            .clone()
+            .ok_or(ProfileError::EquipmentNotStale)?;
+
+        let (expected, _) = Pubkey::find_program_address(
+            &[b"stake_record", equipped.cosmetic_mint.as_ref(), equipped.profile_mint.as_ref()],
+            &cosmetics_program,
+        );
+        require_keys_eq!(stake_record.key(), expected, ProfileError::StakeRecordMismatch);
+
+        let live = stake_record.owner == &cosmetics_program
+            && CosmeticStakeRecord::try_from_account(stake_record)
+                .map(|stake| {
+                    stake.user == profile.owner
+                        && stake.cosmetic_mint == equipped.cosmetic_mint
+                        && stake.profile_mint == equipped.profile_mint
+                })
+                .unwrap_or(false)
+            && profile.profile_mint == Some(equipped.profile_mint);
+        require!(!live, ProfileError::EquipmentNotStale);
+
+        let clock = Clock::get()?;
+        *profile.equipment.slot_mut(slot) = None;
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(CosmeticEquippedEvent {
+            owner: profile.owner,
+            slot,
+            cosmetic_mint: None,
+            timestamp: clock.unix_timestamp,
         });
 
-        msg!("Profile verification status updated for @{}: {}", profile.sbt_handle, verified);
         Ok(())
     }
+
+    /// Set the cosmetics program whose stake records back equipment (admin only)
+    pub fn set_cosmetics_program(
+        ctx: Context<UpdateConfig>,
+        cosmetics_program: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.config.cosmetics_program = cosmetics_program;
+
+        emit!(CosmeticsProgramUpdatedEvent {
+            admin: ctx.accounts.admin.key(),
+            cosmetics_program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create the profile config. Only the program's upgrade authority may
+    /// do this; it becomes the first admin.
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        verifiers: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(verifiers.len() <= MAX_VERIFIERS, ProfileError::InvalidVerifierSet);
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.verifiers = verifiers.clone();
+        config.cosmetics_program = Pubkey::default();
+        config.reputation_programs = Vec::new();
+        config.link_oracles = Vec::new();
+        config.bump = ctx.bumps.config;
+
+        emit!(ProfileConfigUpdatedEvent {
+            admin: config.admin,
+            verifiers,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Change the admin and verifier allowlist (admin only)
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        new_admin: Pubkey,
+        verifiers: Vec<Pubkey>,
+    ) -> Result<()> {
+        require!(verifiers.len() <= MAX_VERIFIERS, ProfileError::InvalidVerifierSet);
+
+        let config = &mut ctx.accounts.config;
+        config.admin = new_admin;
+        config.verifiers = verifiers.clone();
+
+        emit!(ProfileConfigUpdatedEvent {
+            admin: new_admin,
+            verifiers,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Grant or remove one verification kind (admin or allowlisted verifier)
+    pub fn set_verified_status(
+        ctx: Context<SetVerified>,
+        kind: VerificationKind,
+        verified: bool,
+    ) -> Result<()> {
+        let profile = &mut ctx.accounts.profile_state;
+        let verifier = ctx.accounts.authority.key();
+        let clock = Clock::get()?;
+
+        profile.verifications.retain(|v| v.kind != kind);
+        if verified {
+            profile.verifications.push(Verification {
+                kind,
+                verifier,
+                verified_at: clock.unix_timestamp,
+            });
+        }
+        profile.updated_at = clock.unix_timestamp;
+
+        emit!(ProfileVerificationEvent {
+            owner: profile.owner,
+            sbt_handle: profile.sbt_handle.clone(),
+            kind,
+            verified,
+            verifier,
+            timestamp: profile.updated_at,
+        });
+
+        msg!("Profile verification status updated for @{}: {:?} = {}", profile.sbt_handle, kind, verified);
+        Ok(())
+    }
+
+    /// Upgrade a profile written with an older layout to the current one in
+    /// place. The account is resized to the current layout (payer tops up
+    /// rent) and rewritten. Profiles migrated from v1 lose the old
+    /// unchecked `verified` flag, their Twitter and Discord handles become
+    /// unverified social links, and their free-text handle is cleared: the
+    /// profile stays unbound until the owner calls `sync_handle`.
+    pub fn migrate_profile(ctx: Context<MigrateProfile>) -> Result<()> {
+        let profile_info = ctx.accounts.profile_state.to_account_info();
+        let stored_version = ProfileState::stored_version(&profile_info.try_borrow_data()?)?;
+        require!(stored_version != PROFILE_VERSION, ProfileError::AlreadyMigrated);
+        let profile = ProfileState::load_any(&profile_info)?;
+
+        let rent = Rent::get()?.minimum_balance(ProfileState::SPACE);
+        let top_up = rent.saturating_sub(profile_info.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: profile_info.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        profile_info.realloc(ProfileState::SPACE, false)?;
+
+        let mut data = profile_info.try_borrow_mut_data()?;
+        profile.try_serialize(&mut &mut data[..])?;
+
+        msg!("Profile of {} migrated from v{} to v{}", profile.owner, stored_version, PROFILE_VERSION);
+        Ok(())
+    }
+}
+
+// Account structs
+#[derive(Accounts)]
+pub struct CreateProfile<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump,
+        payer = owner,
+        space = ProfileState::SPACE
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    /// CHECK: Deletion tombstone of an earlier profile, if any; checked in the handler
+    #[account(seeds = [b"profile_tombstone", owner.key().as_ref()], bump)]
+    pub tombstone: UncheckedAccount<'info>,
+
+    /// Caller's identity handle; must be owned by the signer and not suspended
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        seeds::program = neoengine_identity::ID,
+        constraint = username_account.owner == owner.key() @ ProfileError::HandleNotOwned,
+        constraint = !username_account.suspended @ ProfileError::HandleSuspended,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProfile<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProfileMetadata<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    /// CHECK: Metaplex metadata of the profile NFT; required once one is
+    /// minted and checked against `profile_mint` in the handler
+    #[account(mut)]
+    pub nft_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Program PDA holding the NFT's update authority
+    #[account(seeds = [PROFILE_AUTHORITY_SEED], bump)]
+    pub profile_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+}
+
+impl<'info> UpdateProfileMetadata<'info> {
+    /// Push the profile's metadata URI to its NFT, if it has one
+    fn sync_nft_uri(&self, authority_bump: Option<u8>) -> Result<()> {
+        sync_profile_nft(
+            &self.profile_state,
+            &self.nft_metadata,
+            &self.profile_authority,
+            &self.token_metadata_program,
+            authority_bump,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct MintProfileNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized,
+        constraint = profile_state.profile_mint.is_none() @ ProfileError::ProfileNftAlreadyMinted,
+        constraint = profile_state.is_bound() @ ProfileError::ProfileNotBound
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    /// CHECK: Program PDA; NFT update authority and freeze delegate
+    #[account(seeds = [PROFILE_AUTHORITY_SEED], bump)]
+    pub profile_authority: UncheckedAccount<'info>,
+
+    /// The profile's creation time keeps the address unique per profile:
+    /// `close_profile` leaves the burned NFT's empty mint behind, and a
+    /// recreated profile is at least `PROFILE_RECREATE_COOLDOWN` newer
+    #[account(
+        init,
+        seeds = [b"profile_mint", profile_state.key().as_ref(), profile_state.created_at.to_le_bytes().as_ref()],
+        bump,
+        payer = owner,
+        mint::decimals = 0,
+        mint::authority = profile_authority,
+        mint::freeze_authority = profile_authority
+    )]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = owner
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Created by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub nft_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Created by the token metadata program
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SetEncryptionKey<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"encryption_key", owner.key().as_ref()],
+        bump,
+        payer = owner,
+        space = EncryptionKey::SPACE
+    )]
+    pub encryption_key: Account<'info, EncryptionKey>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(label: String)]
+pub struct SetPrivateField<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"private_field", owner.key().as_ref(), label.as_bytes()],
+        bump,
+        payer = owner,
+        space = PrivateField::SPACE
+    )]
+    pub private_field: Account<'info, PrivateField>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(label: String, wrapped: WrappedKey)]
+pub struct AddPrivateFieldRecipient<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"private_field", owner.key().as_ref(), label.as_bytes()],
+        bump = private_field.bump,
+        has_one = owner @ ProfileError::Unauthorized
+    )]
+    pub private_field: Account<'info, PrivateField>,
+
+    /// Encryption key the recipient published; `wrapped` must be sealed to it
+    #[account(
+        seeds = [b"encryption_key", wrapped.recipient.as_ref()],
+        bump = recipient_key.bump
+    )]
+    pub recipient_key: Account<'info, EncryptionKey>,
+}
+
+#[derive(Accounts)]
+#[instruction(label: String)]
+pub struct UpdatePrivateField<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"private_field", owner.key().as_ref(), label.as_bytes()],
+        bump = private_field.bump,
+        has_one = owner @ ProfileError::Unauthorized
+    )]
+    pub private_field: Account<'info, PrivateField>,
+}
+
+#[derive(Accounts)]
+#[instruction(label: String)]
+pub struct DeletePrivateField<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        mut,
+        seeds = [b"private_field", owner.key().as_ref(), label.as_bytes()],
+        bump = private_field.bump,
+        has_one = owner @ ProfileError::Unauthorized,
+        close = owner
+    )]
+    pub private_field: Account<'info, PrivateField>,
+}
+
+#[derive(Accounts)]
+pub struct SyncHandle<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        seeds::program = neoengine_identity::ID,
+        constraint = username_account.owner == owner.key() @ ProfileError::HandleNotOwned,
+        constraint = !username_account.suspended @ ProfileError::HandleSuspended,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    /// CHECK: Metaplex metadata of the profile NFT; required once one is
+    /// minted and checked against `profile_mint` in the handler
+    #[account(mut)]
+    pub nft_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Program PDA holding the NFT's update authority
+    #[account(seeds = [PROFILE_AUTHORITY_SEED], bump)]
+    pub profile_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateRecoveredProfile<'info> {
+    #[account(mut)]
+    pub new_owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        seeds::program = neoengine_identity::ID,
+        constraint = username_account.owner == new_owner.key() @ ProfileError::HandleNotOwned,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", old_profile_state.owner.as_ref()],
+        bump = old_profile_state.bump,
+        constraint = old_profile_state.username_account == username_account.key() @ ProfileError::HandleNotOwned,
+        constraint = old_profile_state.owner != new_owner.key() @ ProfileError::Unauthorized,
+        close = new_owner
+    )]
+    pub old_profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        init,
+        seeds = [b"profile", new_owner.key().as_ref()],
+        bump,
+        payer = new_owner,
+        space = ProfileState::SPACE
+    )]
+    pub new_profile_state: Account<'info, ProfileState>,
+
+    /// CHECK: Deletion tombstone of the new owner's earlier profile, if any; checked in the handler
+    #[account(seeds = [b"profile_tombstone", new_owner.key().as_ref()], bump)]
+    pub new_tombstone: UncheckedAccount<'info>,
+
+    /// Profile NFT mint; the NFT accounts are required once one is minted
+    #[account(address = old_profile_state.profile_mint.ok_or(ProfileError::InvalidProfileNft)? @ ProfileError::InvalidProfileNft)]
+    pub nft_mint: Option<Box<Account<'info, Mint>>>,
+
+    /// The lost wallet's frozen NFT token account
+    #[account(
+        mut,
+        associated_token::mint = nft_mint,
+        associated_token::authority = old_profile_state.owner
+    )]
+    pub old_nft_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = new_owner,
+        associated_token::mint = nft_mint,
+        associated_token::authority = new_owner
+    )]
+    pub new_nft_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: Metaplex metadata of the profile NFT; checked in the handler
+    pub nft_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex master edition of the profile NFT; checked in the handler
+    pub nft_master_edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Program PDA; freeze delegate of the NFT token account
+    #[account(seeds = [PROFILE_AUTHORITY_SEED], bump)]
+    pub profile_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateRecoveredProfile<'info> {
+    /// Thaw the NFT in the lost wallet, move it with the program's delegate
+    /// authority, then delegate and freeze it in the new owner's wallet
+    fn move_profile_nft(&self, mint: Pubkey, authority_bump: Option<u8>) -> Result<()> {
+        let (
+            Some(nft_mint),
+            Some(old_token_account),
+            Some(new_token_account),
+            Some(nft_metadata),
+            Some(nft_master_edition),
+            Some(profile_authority),
+            Some(token_program),
+            Some(token_metadata_program),
+            Some(bump),
+        ) = (
+            &self.nft_mint,
+            &self.old_nft_token_account,
+            &self.new_nft_token_account,
+            &self.nft_metadata,
+            &self.nft_master_edition,
+            &self.profile_authority,
+            &self.token_program,
+            &self.token_metadata_program,
+            authority_bump,
+        ) else {
+            return err!(ProfileError::MissingProfileNftAccounts);
+        };
+        require_keys_eq!(nft_metadata.key(), MetadataPda::find_pda(&mint).0, ProfileError::InvalidProfileNft);
+        require_keys_eq!(
+            nft_master_edition.key(),
+            MasterEditionPda::find_pda(&mint).0,
+            ProfileError::InvalidProfileNft
+        );
+        let signer_seeds: &[&[&[u8]]] = &[&[PROFILE_AUTHORITY_SEED, &[bump]]];
+
+        metadata::thaw_delegated_account(CpiContext::new_with_signer(
+            token_metadata_program.to_account_info(),
+            ThawDelegatedAccount {
+                metadata: nft_metadata.to_account_info(),
+                delegate: profile_authority.to_account_info(),
+                token_account: old_token_account.to_account_info(),
+                edition: nft_master_edition.to_account_info(),
+                mint: nft_mint.to_account_info(),
+                token_program: token_program.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::Transfer {
+                    from: old_token_account.to_account_info(),
+                    to: new_token_account.to_account_info(),
+                    authority: profile_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+        token::approve(
+            CpiContext::new(
+                token_program.to_account_info(),
+                token::Approve {
+                    to: new_token_account.to_account_info(),
+                    delegate: profile_authority.to_account_info(),
+                    authority: self.new_owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+        metadata::freeze_delegated_account(CpiContext::new_with_signer(
+            token_metadata_program.to_account_info(),
+            FreezeDelegatedAccount {
+                metadata: nft_metadata.to_account_info(),
+                delegate: profile_authority.to_account_info(),
+                token_account: new_token_account.to_account_info(),
+                edition: nft_master_edition.to_account_info(),
+                mint: nft_mint.to_account_info(),
+                token_program: token_program.to_account_info(),
+            },
+            signer_seeds,
+        ))
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(label: String)]
+pub struct PurgeRecoveredPrivateField<'info> {
+    #[account(mut)]
+    pub new_owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"username", username_account.username.as_bytes()],
+        bump = username_account.bump,
+        seeds::program = neoengine_identity::ID,
+        constraint = username_account.owner == new_owner.key() @ ProfileError::HandleNotOwned,
+    )]
+    pub username_account: Account<'info, UsernameAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", old_profile_state.owner.as_ref()],
+        bump = old_profile_state.bump,
+        constraint = old_profile_state.username_account == username_account.key() @ ProfileError::HandleNotOwned,
+        constraint = old_profile_state.owner != new_owner.key() @ ProfileError::Unauthorized
+    )]
+    pub old_profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        mut,
+        seeds = [b"private_field", old_profile_state.owner.as_ref(), label.as_bytes()],
+        bump = private_field.bump,
+        close = new_owner
+    )]
+    pub private_field: Account<'info, PrivateField>,
+}
+
+#[derive(Accounts)]
+pub struct EquipCosmetic<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump = profile_state.bump,
+        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    #[account(
+        seeds = [b"profile_config"],
+        bump = config.bump,
+        constraint = config.cosmetics_program != Pubkey::default() @ ProfileError::CosmeticNotStaked
+    )]
+    pub config: Account<'info, ProfileConfig>,
+
+    /// CHECK: Only the key is used, to derive the stake record
+    pub cosmetic_mint: UncheckedAccount<'info>,
+
+    /// CHECK: The profile's own NFT mint; only the key is used, to derive the stake record
+    #[account(address = profile_state.profile_mint.ok_or(ProfileError::ProfileNftRequired)?)]
+    pub profile_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Cosmetics stake record; owner and PDA checked here, contents in the handler
+    #[account(
+        owner = config.cosmetics_program @ ProfileError::CosmeticNotStaked,
+        seeds = [b"stake_record", cosmetic_mint.key().as_ref(), profile_mint.key().as_ref()],
+        bump,
+        seeds::program = config.cosmetics_program
+    )]
+    pub stake_record: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnequipStale<'info> {
+    #[account(
+        mut,
+        seeds = [b"profile", profile_state.owner.as_ref()],
+        bump = profile_state.bump
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+
+    #[account(seeds = [b"profile_config"], bump = config.bump)]
+    pub config: Account<'info, ProfileConfig>,
+
+    /// CHECK: Stake record of the equipped cosmetic; may be closed. The
+    /// address is checked in the handler
+    pub stake_record: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReputationCpi<'info> {
+    /// PDA of the calling program, signed through `invoke_signed`
+    pub caller_authority: Signer<'info>,
+
+    /// CHECK: Allowlisted calling program; its PDA must be `caller_authority`
+    #[account(
+        constraint = config.reputation_programs.contains(&caller_program.key()) @ ProfileError::Unauthorized,
+        constraint = Pubkey::find_program_address(&[REPUTATION_AUTHORITY_SEED], &caller_program.key()).0
+            == caller_authority.key() @ ProfileError::Unauthorized
+    )]
+    pub caller_program: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"profile_config"], bump = config.bump)]
+    pub config: Account<'info, ProfileConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", profile_state.owner.as_ref()],
+        bump = profile_state.bump
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseProfile<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: ProfileState of any supported version; owner checked in the handler
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"profile", owner.key().as_ref()],
+        bump
+    )]
+    pub profile_state: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"profile_tombstone", owner.key().as_ref()],
+        bump,
+        payer = owner,
+        space = ProfileTombstone::SPACE
+    )]
+    pub tombstone: Account<'info, ProfileTombstone>,
+
+    /// CHECK: The owner's published encryption key, closed if it exists
+    #[account(mut, seeds = [b"encryption_key", owner.key().as_ref()], bump)]
+    pub encryption_key: UncheckedAccount<'info>,
+
+    /// CHECK: Mint of the profile NFT; required once one is minted and
+    /// checked against `profile_mint` in the handler
+    #[account(mut)]
+    pub nft_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Owner's frozen NFT token account; checked in the handler
+    #[account(mut)]
+    pub nft_token_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex metadata of the profile NFT; checked in the handler
+    #[account(mut)]
+    pub nft_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex master edition of the profile NFT; checked in the handler
+    #[account(mut)]
+    pub nft_master_edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Program PDA; freeze delegate of the NFT token account
+    #[account(seeds = [PROFILE_AUTHORITY_SEED], bump)]
+    pub profile_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CloseProfile<'info> {
+    /// Thaw and burn the profile NFT. Metaplex closes its token, metadata
+    /// and edition accounts to the owner; the empty mint stays behind.
+    fn burn_profile_nft(&self, mint: Pubkey, authority_bump: Option<u8>) -> Result<()> {
+        let (
+            Some(nft_mint),
+            Some(nft_token_account),
+            Some(nft_metadata),
+            Some(nft_master_edition),
+            Some(profile_authority),
+            Some(token_program),
+            Some(token_metadata_program),
+            Some(bump),
+        ) = (
+            &self.nft_mint,
+            &self.nft_token_account,
+            &self.nft_metadata,
+            &self.nft_master_edition,
+            &self.profile_authority,
+            &self.token_program,
+            &self.token_metadata_program,
+            authority_bump,
+        ) else {
+            return err!(ProfileError::MissingProfileNftAccounts);
+        };
+        require_keys_eq!(nft_mint.key(), mint, ProfileError::InvalidProfileNft);
+        require_keys_eq!(
+            nft_token_account.key(),
+            get_associated_token_address(&self.owner.key(), &mint),
+            ProfileError::InvalidProfileNft
+        );
+        require_keys_eq!(nft_metadata.key(), MetadataPda::find_pda(&mint).0, ProfileError::InvalidProfileNft);
+        require_keys_eq!(
+            nft_master_edition.key(),
+            MasterEditionPda::find_pda(&mint).0,
+            ProfileError::InvalidProfileNft
+        );
+
+        metadata::thaw_delegated_account(CpiContext::new_with_signer(
+            token_metadata_program.to_account_info(),
+            ThawDelegatedAccount {
+                metadata: nft_metadata.to_account_info(),
+                delegate: profile_authority.to_account_info(),
+                token_account: nft_token_account.to_account_info(),
+                edition: nft_master_edition.to_account_info(),
+                mint: nft_mint.to_account_info(),
+                token_program: token_program.to_account_info(),
+            },
+            &[&[PROFILE_AUTHORITY_SEED, &[bump]]],
+        ))?;
+        metadata::burn_nft(
+            CpiContext::new(
+                token_metadata_program.to_account_info(),
+                BurnNft {
+                    metadata: nft_metadata.to_account_info(),
+                    owner: self.owner.to_account_info(),
+                    mint: nft_mint.to_account_info(),
+                    token: nft_token_account.to_account_info(),
+                    edition: nft_master_edition.to_account_info(),
+                    spl_token: token_program.to_account_info(),
+                },
+            ),
+            None,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct GetProfile<'info> {
+    /// CHECK: ProfileState of any supported version; decoded by `ProfileState::load_any`
+    #[account(owner = crate::ID)]
+    pub profile_state: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetVerified<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"profile_config"],
+        bump = config.bump,
+        constraint = config.is_verifier(&authority.key()) @ ProfileError::Unauthorized
+    )]
+    pub config: Account<'info, ProfileConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", profile_state.owner.as_ref()],
+        bump = profile_state.bump
+    )]
+    pub profile_state: Account<'info, ProfileState>,
+}
+
+#[derive(Accounts)]
+pub struct VerifySocialLink<'info> {
+    pub oracle: Signer<'info>,
+
+    #[account(
+        seeds = [b"profile_config"],
+        bump = config.bump,
+        constraint = config.link_oracles.contains(&oracle.key()) @ ProfileError::Unauthorized
+    )]
+    pub config: Account<'info, ProfileConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"profile", profile_state.owner.as_ref()],
+        bump = profile_state.bump
+    )]
+    pub profile_state: Account<'info, ProfileState>,
 }
 
-// Account structs
 #[derive(Accounts)]
-#[instruction(metadata_uri: String, sbt_handle: String, name: String, bio: String, country: String, website: String, twitter: String, discord: String)]
-pub struct CreateProfile<'info> {
+pub struct InitializeConfig<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub admin: Signer<'info>,
 
     #[account(
         init,
-        seeds = [b"profile", owner.key().as_ref()],
+        seeds = [b"profile_config"],
         bump,
-        payer = owner,
-        space = ProfileState::SPACE
+        payer = admin,
+        space = ProfileConfig::SPACE
     )]
-    pub profile_state: Account<'info, ProfileState>,
+    pub config: Account<'info, ProfileConfig>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ProfileError::Unauthorized)]
+    pub program: Program<'info, crate::program::NeoengineProfile>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ProfileError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateProfile<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
 
     #[account(
         mut,
-        seeds = [b"profile", owner.key().as_ref()],
-        bump = profile_state.bump,
-        constraint = profile_state.owner == owner.key() @ ProfileError::Unauthorized
+        seeds = [b"profile_config"],
+        bump = config.bump,
+        has_one = admin @ ProfileError::Unauthorized
     )]
-    pub profile_state: Account<'info, ProfileState>,
-}
-
-#[derive(Accounts)]
-pub struct GetProfile<'info> {
-    pub profile_state: Account<'info, ProfileState>,
+    pub config: Account<'info, ProfileConfig>,
 }
 
 #[derive(Accounts)]
-pub struct SetVerified<'info> {
+pub struct MigrateProfile<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>, // TODO: Add authority constraint
+    pub payer: Signer<'info>,
 
-    #[account(mut)]
-    pub profile_state: Account<'info, ProfileState>,
+    /// CHECK: ProfileState of any supported version; layout is checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub profile_state: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 // Data structures
 #[account]
 #[derive(Debug)]
 pub struct ProfileState {
+    /// Layout version; stored first so readers can dispatch on it before
+    /// decoding the rest
+    pub version: u8,
     pub owner: Pubkey,
+    /// Handle copied from `username_account`; empty while unbound
     pub sbt_handle: String,
+    /// Identity username account; `Pubkey::default()` while unbound
+    pub username_account: Pubkey,
+    /// Mint of the profile NFT, once minted
+    pub profile_mint: Option<Pubkey>,
     pub metadata_uri: String,
     
     // Rich on-chain metadata
@@ -204,11 +1807,15 @@ pub struct ProfileState {
     pub bio: String,
     pub country: String,
     pub website: String,
-    pub social_links: SocialLinks,
+    pub social_links: Vec<SocialLink>,
     
     // Status and versioning
-    pub verified: bool,
-    pub version: u8,
+    pub verifications: Vec<Verification>,
+    pub equipment: EquipmentSlots,
+    pub reputation_score: u64,
+    pub badges: Vec<Badge>,
+    /// Private fields of the owner; must reach zero before `close_profile`
+    pub private_field_count: u8,
     
     // Timestamps
     pub created_at: i64,
@@ -218,31 +1825,523 @@ pub struct ProfileState {
     pub bump: u8,
 }
 
+/// Platforms a social link can point to, at most one link each
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocialPlatform {
+    Twitter,
+    Discord,
+    Telegram,
+    GitHub,
+    Instagram,
+    YouTube,
+    TikTok,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct SocialLinks {
-    pub twitter: String,
-    pub discord: String,
+pub struct SocialLink {
+    pub platform: SocialPlatform,
+    pub handle: String,
+    /// Link oracle that checked `handle`; cleared whenever the handle changes
+    pub verified_by: Option<Pubkey>,
+    /// 0 while unverified
+    pub verified_at: i64,
 }
 
-impl ProfileState {
+impl SocialLink {
+    pub const SPACE: usize = 1 + (4 + MAX_SOCIAL_HANDLE_LEN) + (1 + 32) + 8;
+}
+
+/// Fixed Twitter/Discord pair stored by v1 profiles
+#[derive(AnchorDeserialize)]
+struct LegacySocialLinks {
+    twitter: String,
+    discord: String,
+}
+
+impl LegacySocialLinks {
+    fn upgrade(self) -> Vec<SocialLink> {
+        [(SocialPlatform::Twitter, self.twitter), (SocialPlatform::Discord, self.discord)]
+            .into_iter()
+            .filter(|(_, handle)| !handle.is_empty())
+            .map(|(platform, handle)| SocialLink {
+                platform,
+                handle,
+                verified_by: None,
+                verified_at: 0,
+            })
+            .collect()
+    }
+}
+
+/// Kinds of verification a profile can hold at the same time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerificationKind {
+    Team,
+    Creator,
+    Organization,
+    Notable,
+}
+
+/// One granted verification, at most one per kind
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Verification {
+    pub kind: VerificationKind,
+    pub verifier: Pubkey,
+    pub verified_at: i64,
+}
+
+/// Optional field changes for `update_profile_fields`; `None` leaves a field as is
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct ProfileFieldsUpdate {
+    pub metadata_uri: Option<String>,
+    pub name: Option<String>,
+    pub bio: Option<String>,
+    pub country: Option<String>,
+    pub website: Option<String>,
+}
+
+impl ProfileFieldsUpdate {
+    pub fn validate(&self) -> Result<()> {
+        let fits = |value: &Option<String>, max: usize| value.as_ref().filter(|v| v.len() > max).is_none();
+
+        require!(fits(&self.metadata_uri, MAX_METADATA_URI_LEN), ProfileError::InvalidMetadataUri);
+        require!(fits(&self.name, MAX_NAME_LEN), ProfileError::InvalidName);
+        require!(fits(&self.bio, MAX_BIO_LEN), ProfileError::InvalidBio);
+        require!(fits(&self.country, MAX_COUNTRY_LEN), ProfileError::InvalidCountry);
+        require!(fits(&self.website, MAX_WEBSITE_LEN), ProfileError::InvalidWebsite);
+        Ok(())
+    }
+}
+
+/// Profile fields reported in change events
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileField {
+    MetadataUri,
+    Name,
+    Bio,
+    Country,
+    Website,
+}
+
+pub const MAX_SBT_HANDLE_LEN: usize = 32;
+pub const MAX_METADATA_URI_LEN: usize = 200;
+pub const MAX_NAME_LEN: usize = 50;
+pub const MAX_BIO_LEN: usize = 200;
+pub const MAX_COUNTRY_LEN: usize = 50;
+pub const MAX_WEBSITE_LEN: usize = 100;
+pub const MAX_SOCIAL_HANDLE_LEN: usize = 50;
+pub const MAX_SOCIAL_LINKS: usize = 8;
+pub const MAX_LINK_ORACLES: usize = 5;
+
+/// Cosmetic slots on a profile
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EquipmentSlot {
+    Frame,
+    Background,
+    Animation,
+    BadgeEffect,
+}
+
+impl EquipmentSlot {
+    /// Slot for a `cosmetic_type` recorded by the cosmetics program, or
+    /// `None` for a type no slot accepts
+    pub fn from_cosmetic_type(cosmetic_type: &str) -> Option<Self> {
+        match cosmetic_type {
+            "frame" => Some(EquipmentSlot::Frame),
+            "background" => Some(EquipmentSlot::Background),
+            "animation" => Some(EquipmentSlot::Animation),
+            "badge_effect" => Some(EquipmentSlot::BadgeEffect),
+            _ => None,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct EquippedCosmetic {
+    pub cosmetic_mint: Pubkey,
+    pub profile_mint: Pubkey,
+    pub equipped_at: i64,
+}
+
+impl EquippedCosmetic {
+    pub const SPACE: usize = 32 + 32 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct EquipmentSlots {
+    pub frame: Option<EquippedCosmetic>,
+    pub background: Option<EquippedCosmetic>,
+    pub animation: Option<EquippedCosmetic>,
+    pub badge_effect: Option<EquippedCosmetic>,
+}
+
+impl EquipmentSlots {
+    pub const SPACE: usize = 4 * (1 + EquippedCosmetic::SPACE);
+
+    pub fn slot_mut(&mut self, slot: EquipmentSlot) -> &mut Option<EquippedCosmetic> {
+        match slot {
+            EquipmentSlot::Frame => &mut self.frame,
+            EquipmentSlot::Background => &mut self.background,
+            EquipmentSlot::Animation => &mut self.animation,
+            EquipmentSlot::BadgeEffect => &mut self.badge_effect,
+        }
+    }
+}
+
+/// Mirror of the cosmetics program's stake record, read without a crate dependency
+#[derive(AnchorDeserialize)]
+struct CosmeticStakeRecord {
+    user: Pubkey,
+    cosmetic_mint: Pubkey,
+    profile_mint: Pubkey,
+    cosmetic_type: String,
+    #[allow(dead_code)]
+    staked_at: i64,
+}
+
+impl CosmeticStakeRecord {
+    fn try_from_account(info: &AccountInfo) -> Result<Self> {
+        let data = info.try_borrow_data()?;
+        let discriminator = &hash(b"account:CosmeticStakeRecord").to_bytes()[..8];
+        require!(
+            data.len() >= 8 && &data[..8] == discriminator,
+            ProfileError::CosmeticNotStaked
+        );
+        Ok(Self::deserialize(&mut &data[8..])?)
+    }
+}
+
+/// Badge awarded by an allowlisted program
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Badge {
+    pub badge_id: String,
+    pub awarded_by: Pubkey,
+    pub awarded_at: i64,
+}
+
+impl Badge {
+    pub const SPACE: usize = 4 + MAX_BADGE_ID_LEN + 32 + 8;
+}
+
+/// Seed of the PDA a caller program signs with for reputation CPIs
+pub const REPUTATION_AUTHORITY_SEED: &[u8] = b"profile_reputation";
+pub const MAX_REPUTATION_PROGRAMS: usize = 5;
+pub const MAX_BADGES: usize = 16;
+pub const MAX_BADGE_ID_LEN: usize = 32;
+pub const MAX_VERIFICATIONS: usize = 4;
+pub const MAX_VERIFIERS: usize = 10;
+
+/// Seconds after `close_profile` before the owner may create a new profile
+pub const PROFILE_RECREATE_COOLDOWN: i64 = 7 * 24 * 60 * 60;
+
+/// Minimal record left behind by `close_profile`
+#[account]
+pub struct ProfileTombstone {
+    pub owner: Pubkey,
+    pub closed_at: i64,
+    pub bump: u8,
+}
+
+impl ProfileTombstone {
+    pub const SPACE: usize = 8 + // discriminator
+        32 + // owner
+        8 + // closed_at
+        1; // bump
+}
+
+/// x25519 public key a wallet publishes for receiving private fields
+#[account]
+pub struct EncryptionKey {
+    pub owner: Pubkey,
+    pub x25519_pubkey: [u8; 32],
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl EncryptionKey {
+    pub const SPACE: usize = 8 + // discriminator
+        32 + // owner
+        32 + // x25519_pubkey
+        8 + // updated_at
+        1; // bump
+}
+
+/// Content key of a private field, wrapped for one recipient with an
+/// ephemeral x25519 key
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct WrappedKey {
+    pub recipient: Pubkey,
+    pub ephemeral_pubkey: [u8; 32],
+    /// ChaCha20-Poly1305 sealed 32-byte content key
+    pub wrapped_key: [u8; 48],
+}
+
+impl WrappedKey {
+    pub const SPACE: usize = 32 + 32 + 48;
+}
+
+/// Encrypted profile field such as email or phone
+#[account]
+pub struct PrivateField {
+    pub owner: Pubkey,
+    pub label: String,
+    pub nonce: [u8; 12],
+    pub ciphertext: Vec<u8>,
+    pub recipients: Vec<WrappedKey>,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl PrivateField {
     pub const SPACE: usize = 8 + // discriminator
         32 + // owner
-        4 + 32 + // sbt_handle (max 32 chars)
-        4 + 200 + // metadata_uri (max 200 chars)
-        4 + 50 + // name (max 50 chars)
-        4 + 200 + // bio (max 200 chars)
-        4 + 50 + // country (max 50 chars)
-        4 + 100 + // website (max 100 chars)
-        4 + 50 + // twitter (max 50 chars)
-        4 + 50 + // discord (max 50 chars)
-        1 + // verified
+        4 + MAX_PRIVATE_FIELD_LABEL_LEN + // label
+        12 + // nonce
+        4 + MAX_PRIVATE_FIELD_CIPHERTEXT_LEN + // ciphertext
+        4 + MAX_FIELD_RECIPIENTS * WrappedKey::SPACE + // recipients
+        8 + // updated_at
+        1; // bump
+}
+
+pub const MAX_PRIVATE_FIELD_LABEL_LEN: usize = 32;
+pub const MAX_PRIVATE_FIELD_CIPHERTEXT_LEN: usize = 512;
+pub const MAX_FIELD_RECIPIENTS: usize = 10;
+pub const MAX_PRIVATE_FIELDS: usize = 16;
+
+/// Layout version written by this program
+pub const PROFILE_VERSION: u8 = 2;
+
+/// Seed of the program PDA that is update authority of every profile NFT
+pub const PROFILE_AUTHORITY_SEED: &[u8] = b"profile_authority";
+pub const PROFILE_NFT_SYMBOL: &str = "NEOPROF";
+
+/// Version 1 ProfileState layout, before identity binding, verification
+/// kinds, social links, equipment, reputation and the profile NFT. It has
+/// no leading version byte, so v1 accounts are recognized by their size.
+#[derive(AnchorDeserialize)]
+struct ProfileStateV1 {
+    owner: Pubkey,
+    #[allow(dead_code)]
+    sbt_handle: String,
+    metadata_uri: String,
+    name: String,
+    bio: String,
+    country: String,
+    website: String,
+    social_links: LegacySocialLinks,
+    #[allow(dead_code)]
+    verified: bool,
+    #[allow(dead_code)]
+    version: u8,
+    created_at: i64,
+    updated_at: i64,
+    update_count: u32,
+    bump: u8,
+}
+
+impl ProfileStateV1 {
+    const SPACE: usize = 8 + 32 + (4 + 32) + (4 + 200) + (4 + 50) + (4 + 200) + (4 + 50)
+        + (4 + 100) + (4 + 50) + (4 + 50) + 1 + 1 + 8 + 8 + 4 + 1;
+
+    fn upgrade(self) -> ProfileState {
+        ProfileState {
+            version: PROFILE_VERSION,
+            owner: self.owner,
+            // v1 handles were free text, so the profile stays unbound until
+            // the owner proves a handle with sync_handle
+            sbt_handle: String::new(),
+            username_account: Pubkey::default(),
+            profile_mint: None,
+            metadata_uri: self.metadata_uri,
+            name: self.name,
+            bio: self.bio,
+            country: self.country,
+            website: self.website,
+            social_links: self.social_links.upgrade(),
+            verifications: Vec::new(),
+            equipment: EquipmentSlots::default(),
+            reputation_score: 0,
+            badges: Vec::new(),
+            private_field_count: 0,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            update_count: self.update_count,
+            bump: self.bump,
+        }
+    }
+}
+
+/// Program-wide admin and verifier allowlist
+#[account]
+pub struct ProfileConfig {
+    pub admin: Pubkey,
+    pub verifiers: Vec<Pubkey>,
+    pub cosmetics_program: Pubkey,
+    pub reputation_programs: Vec<Pubkey>,
+    pub link_oracles: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl ProfileConfig {
+    pub const SPACE: usize = 8 + // discriminator
+        32 + // admin
+        4 + 32 * MAX_VERIFIERS + // verifiers
+        32 + // cosmetics_program
+        4 + 32 * MAX_REPUTATION_PROGRAMS + // reputation_programs
+        4 + 32 * MAX_LINK_ORACLES + // link_oracles
+        1; // bump
+
+    pub fn is_verifier(&self, key: &Pubkey) -> bool {
+        *key == self.admin || self.verifiers.contains(key)
+    }
+}
+
+impl ProfileState {
+    /// Decode a profile of any supported layout version, upgrading older
+    /// layouts in memory
+    pub fn load_any(info: &AccountInfo) -> Result<ProfileState> {
+        let data = info.try_borrow_data()?;
+        match Self::stored_version(&data)? {
+            1 => Ok(ProfileStateV1::deserialize(&mut &data[8..])?.upgrade()),
+            PROFILE_VERSION => Ok(ProfileState::deserialize(&mut &data[8..])?),
+            _ => err!(ProfileError::UnsupportedProfileVersion),
+        }
+    }
+
+    /// Layout version of raw profile account data: 1 for the unversioned
+    /// v1 layout, otherwise the leading version byte
+    fn stored_version(data: &[u8]) -> Result<u8> {
+        require!(
+            data.len() > 8 && data[..8] == ProfileState::DISCRIMINATOR,
+            ProfileError::ProfileNotFound
+        );
+        if data.len() == ProfileStateV1::SPACE {
+            return Ok(1);
+        }
+        Ok(data[8])
+    }
+
+    /// Whether the profile is bound to an identity handle
+    pub fn is_bound(&self) -> bool {
+        self.username_account != Pubkey::default()
+    }
+
+    pub const SPACE: usize = 8 + // discriminator
         1 + // version
+        32 + // owner
+        4 + MAX_SBT_HANDLE_LEN + // sbt_handle
+        32 + // username_account
+        1 + 32 + // profile_mint
+        4 + MAX_METADATA_URI_LEN + // metadata_uri
+        4 + MAX_NAME_LEN + // name
+        4 + MAX_BIO_LEN + // bio
+        4 + MAX_COUNTRY_LEN + // country
+        4 + MAX_WEBSITE_LEN + // website
+        4 + MAX_SOCIAL_LINKS * SocialLink::SPACE + // social_links
+        4 + MAX_VERIFICATIONS * (1 + 32 + 8) + // verifications
+        EquipmentSlots::SPACE + // equipment
+        8 + // reputation_score
+        4 + MAX_BADGES * Badge::SPACE + // badges
+        1 + // private_field_count
         8 + // created_at
         8 + // updated_at
         4 + // update_count
         1; // bump
 }
 
+/// Length checks for the free-form profile details
+fn validate_details(bio: &str, country: &str, website: &str) -> Result<()> {
+    require!(bio.len() <= MAX_BIO_LEN, ProfileError::InvalidBio);
+    require!(country.len() <= MAX_COUNTRY_LEN, ProfileError::InvalidCountry);
+    require!(website.len() <= MAX_WEBSITE_LEN, ProfileError::InvalidWebsite);
+    Ok(())
+}
+
+/// Metaplex data for a profile NFT; royalty-free, named after the handle
+fn profile_nft_data(handle: &str, uri: &str) -> DataV2 {
+    DataV2 {
+        name: format!("@{}", handle),
+        symbol: PROFILE_NFT_SYMBOL.to_string(),
+        uri: uri.to_string(),
+        seller_fee_basis_points: 0,
+        creators: None,
+        collection: None,
+        uses: None,
+    }
+}
+
+/// Rewrite the Metaplex name and URI of the profile's NFT, if it has one
+fn sync_profile_nft<'info>(
+    profile: &ProfileState,
+    nft_metadata: &Option<UncheckedAccount<'info>>,
+    profile_authority: &Option<UncheckedAccount<'info>>,
+    token_metadata_program: &Option<Program<'info, Metadata>>,
+    authority_bump: Option<u8>,
+) -> Result<()> {
+    let Some(mint) = profile.profile_mint else {
+        return Ok(());
+    };
+    let (Some(nft_metadata), Some(profile_authority), Some(token_metadata_program), Some(bump)) = (
+        nft_metadata,
+        profile_authority,
+        token_metadata_program,
+        authority_bump,
+    ) else {
+        return err!(ProfileError::MissingProfileNftAccounts);
+    };
+    require_keys_eq!(nft_metadata.key(), MetadataPda::find_pda(&mint).0, ProfileError::InvalidProfileNft);
+
+    metadata::update_metadata_accounts_v2(
+        CpiContext::new_with_signer(
+            token_metadata_program.to_account_info(),
+            UpdateMetadataAccountsV2 {
+                metadata: nft_metadata.to_account_info(),
+                update_authority: profile_authority.to_account_info(),
+            },
+            &[&[PROFILE_AUTHORITY_SEED, &[bump]]],
+        ),
+        None,
+        Some(profile_nft_data(&profile.sbt_handle, &profile.metadata_uri)),
+        None,
+        None,
+    )
+}
+
+/// Close a program-owned account of unknown layout into `destination`
+fn close_account<'info>(info: &AccountInfo<'info>, destination: &AccountInfo<'info>) -> Result<()> {
+    let lamports = info.lamports();
+    **destination.try_borrow_mut_lamports()? += lamports;
+    **info.try_borrow_mut_lamports()? = 0;
+    info.assign(&system_program::ID);
+    info.realloc(0, false)?;
+    Ok(())
+}
+
+/// Require `info` to be the `EncryptionKey` published by `recipient`
+fn require_published_key(info: &AccountInfo, recipient: &Pubkey) -> Result<()> {
+    require_keys_eq!(*info.owner, crate::ID, ProfileError::EncryptionKeyNotPublished);
+    let data = info.try_borrow_data()?;
+    let key = EncryptionKey::try_deserialize(&mut &data[..])
+        .map_err(|_| error!(ProfileError::EncryptionKeyNotPublished))?;
+    require_keys_eq!(key.owner, *recipient, ProfileError::EncryptionKeyNotPublished);
+    Ok(())
+}
+
+/// Reject profile creation while a deletion tombstone is cooling down
+fn ensure_cooldown_elapsed(tombstone: &AccountInfo, now: i64) -> Result<()> {
+    if tombstone.data_is_empty() {
+        return Ok(());
+    }
+
+    let data = tombstone.try_borrow_data()?;
+    let tombstone = ProfileTombstone::try_deserialize(&mut &data[..])?;
+    require!(
+        now >= tombstone.closed_at + PROFILE_RECREATE_COOLDOWN,
+        ProfileError::ProfileCooldownActive
+    );
+    Ok(())
+}
+
 // Return types
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct ProfileSummary {
@@ -254,6 +2353,12 @@ pub struct ProfileSummary {
     pub created_at: i64,
     pub updated_at: i64,
     pub update_count: u32,
+    pub verifications: Vec<Verification>,
+    pub equipment: EquipmentSlots,
+    pub reputation_score: u64,
+    pub badges: Vec<Badge>,
+    pub social_links: Vec<SocialLink>,
+    pub profile_mint: Option<Pubkey>,
 }
 
 // Events
@@ -275,11 +2380,155 @@ pub struct ProfileUpdatedEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct ProfileFieldsUpdatedEvent {
+    pub owner: Pubkey,
+    pub changed_fields: Vec<ProfileField>,
+    pub update_count: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProfileHandleSyncedEvent {
+    pub owner: Pubkey,
+    pub old_handle: String,
+    pub new_handle: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProfileRecoveredEvent {
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub sbt_handle: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CosmeticEquippedEvent {
+    pub owner: Pubkey,
+    pub slot: EquipmentSlot,
+    /// `None` when the slot was cleared
+    pub cosmetic_mint: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReputationUpdatedEvent {
+    pub owner: Pubkey,
+    pub old_score: u64,
+    pub new_score: u64,
+    pub caller_program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BadgeAwardedEvent {
+    pub owner: Pubkey,
+    pub badge_id: String,
+    pub caller_program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProfileNftMintedEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EncryptionKeyPublishedEvent {
+    pub owner: Pubkey,
+    pub x25519_pubkey: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PrivateFieldUpdatedEvent {
+    pub owner: Pubkey,
+    pub label: String,
+    pub recipients: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PrivateFieldAccessEvent {
+    pub owner: Pubkey,
+    pub label: String,
+    pub recipient: Pubkey,
+    pub granted: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PrivateFieldDeletedEvent {
+    pub owner: Pubkey,
+    pub label: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SocialLinkUpdatedEvent {
+    pub owner: Pubkey,
+    pub platform: SocialPlatform,
+    /// `None` when the link was removed
+    pub handle: Option<String>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SocialLinkVerifiedEvent {
+    pub owner: Pubkey,
+    pub platform: SocialPlatform,
+    pub handle: String,
+    pub verified: bool,
+    pub oracle: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProfileClosedEvent {
+    pub owner: Pubkey,
+    pub sbt_handle: String,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct ProfileVerificationEvent {
     pub owner: Pubkey,
     pub sbt_handle: String,
+    pub kind: VerificationKind,
     pub verified: bool,
+    pub verifier: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProfileConfigUpdatedEvent {
+    pub admin: Pubkey,
+    pub verifiers: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CosmeticsProgramUpdatedEvent {
+    pub admin: Pubkey,
+    pub cosmetics_program: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReputationProgramsUpdatedEvent {
+    pub admin: Pubkey,
+    pub programs: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LinkOraclesUpdatedEvent {
+    pub admin: Pubkey,
+    pub oracles: Vec<Pubkey>,
     pub timestamp: i64,
 }
 
@@ -298,4 +2547,76 @@ pub enum ProfileError {
     InvalidName,
     #[msg("Profile not found")]
     ProfileNotFound,
+    #[msg("Identity handle is suspended")]
+    HandleSuspended,
+    #[msg("Too many verifiers")]
+    InvalidVerifierSet,
+    #[msg("Profile already migrated")]
+    AlreadyMigrated,
+    #[msg("Invalid bio")]
+    InvalidBio,
+    #[msg("Invalid country")]
+    InvalidCountry,
+    #[msg("Invalid website")]
+    InvalidWebsite,
+    #[msg("Invalid social handle")]
+    InvalidSocialHandle,
+    #[msg("No profile fields changed")]
+    NoFieldsChanged,
+    #[msg("Identity handle is not owned by the signer")]
+    HandleNotOwned,
+    #[msg("Cosmetic is not staked to this profile")]
+    CosmeticNotStaked,
+    #[msg("Cosmetic type does not match the slot")]
+    CosmeticSlotMismatch,
+    #[msg("Invalid badge id")]
+    InvalidBadgeId,
+    #[msg("Badge already awarded")]
+    BadgeAlreadyAwarded,
+    #[msg("Badge limit reached")]
+    TooManyBadges,
+    #[msg("Too many reputation programs")]
+    InvalidReputationPrograms,
+    #[msg("Unsupported profile layout version")]
+    UnsupportedProfileVersion,
+    #[msg("Profile was closed recently; wait for the cooldown")]
+    ProfileCooldownActive,
+    #[msg("Too many social links")]
+    TooManySocialLinks,
+    #[msg("No social link for this platform")]
+    SocialLinkNotFound,
+    #[msg("Social link changed since it was checked")]
+    SocialLinkChanged,
+    #[msg("Too many link oracles")]
+    InvalidLinkOracles,
+    #[msg("Profile NFT already minted")]
+    ProfileNftAlreadyMinted,
+    #[msg("Profile NFT accounts are required to sync its metadata")]
+    MissingProfileNftAccounts,
+    #[msg("Metadata account does not belong to the profile NFT")]
+    InvalidProfileNft,
+    #[msg("Invalid private field label")]
+    InvalidPrivateFieldLabel,
+    #[msg("Private field ciphertext too large")]
+    PrivateFieldTooLarge,
+    #[msg("Too many private field recipients")]
+    TooManyFieldRecipients,
+    #[msg("Recipient already has access to this field")]
+    FieldRecipientExists,
+    #[msg("Recipient has no access to this field")]
+    FieldRecipientNotFound,
+    #[msg("Profile NFT must be minted first")]
+    ProfileNftRequired,
+    #[msg("Profile is not bound to a handle; call sync_handle first")]
+    ProfileNotBound,
+    #[msg("Delete the profile's private fields first")]
+    PrivateFieldsRemain,
+    #[msg("Private field limit reached")]
+    TooManyPrivateFields,
+    #[msg("Recipient has not published an encryption key")]
+    EncryptionKeyNotPublished,
+    #[msg("Slot is empty or its cosmetic is still staked to the profile")]
+    EquipmentNotStale,
+    #[msg("Stake record does not match the equipped cosmetic")]
+    StakeRecordMismatch,
 }
\ No newline at end of file
@@ -1,427 +1,1941 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { BN, Program } from "@coral-xyz/anchor";
 import { NeoengineIdentity } from "../target/types/neoengine_identity";
-import { NeoengineUsernames } from "../target/types/neoengine_usernames";
-import { 
-  PublicKey, 
-  Keypair, 
+import {
+  Ed25519Program,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   SystemProgram,
-  SYSVAR_RENT_PUBKEY,
+  Transaction,
+  TransactionInstruction,
 } from "@solana/web3.js";
 import {
-  TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
-  getAssociatedTokenAddress,
+  ExtensionType,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  closeAccount,
+  createAssociatedTokenAccount,
+  createAssociatedTokenAccountIdempotent,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getExtensionTypes,
+  getMint,
+  mintTo,
+  transferChecked,
 } from "@solana/spl-token";
+import { keccak_256 } from "@noble/hashes/sha3";
 import { expect } from "chai";
 
-describe("NeoEngine Identity & Username System", () => {
+const BPF_LOADER_UPGRADEABLE = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+const BUBBLEGUM_PROGRAM_ID = new PublicKey("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+const SPL_ACCOUNT_COMPRESSION_ID = new PublicKey("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+const SPL_NOOP_ID = new PublicKey("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+const BUBBLEGUM_TRANSFER_DISCRIMINATOR = Buffer.from([163, 52, 200, 231, 140, 3, 69, 186]);
+const TREE_MAX_DEPTH = 3;
+const TREE_MAX_BUFFER_SIZE = 8;
+// Account header (56) + sequence, active index and buffer size (24) +
+// change logs and the rightmost proof (40 + 32 * depth each)
+const CHANGE_LOG_SIZE = 40 + 32 * TREE_MAX_DEPTH;
+const TREE_ACCOUNT_SIZE = 56 + 24 + (TREE_MAX_BUFFER_SIZE + 1) * CHANGE_LOG_SIZE;
+// MAX_USERNAME_LEN - MIN_USERNAME_LEN + 1
+const PRICE_TABLE_LEN = 18;
+const HANDLE_PRICE = new BN(0.01 * LAMPORTS_PER_SOL);
+
+// Mirrors canonical_username and username_skeleton in the program
+function canonical(handle: string): string {
+  return handle.replace(/^@/, "").toLowerCase();
+}
+
+function skeleton(handle: string): string {
+  const folded = [...canonical(handle)]
+    .map((c) => ({ "0": "o", "1": "l", i: "l", ".": "_", "-": "_" } as Record<string, string>)[c] ?? c)
+    .join("");
+  return folded.split("rn").join("m").split("vv").join("w");
+}
+
+const u32 = (value: number) => {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(value);
+  return buf;
+};
+
+const u64 = (value: number) => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(BigInt(value));
+  return buf;
+};
+
+const borshString = (value: string) => Buffer.concat([u32(Buffer.byteLength(value)), Buffer.from(value)]);
+
+const keccak = (...parts: Buffer[]) => Buffer.from(keccak_256(Buffer.concat(parts)));
+
+// Mirrors compressed_username_metadata and Bubblegum's data and creator hashes
+function compressedHandleHashes(handle: string, uri: string, holder: PublicKey) {
+  const metadata = Buffer.concat([
+    borshString(`@${canonical(handle)}`),
+    borshString("NEOID"),
+    borshString(uri),
+    Buffer.from([0, 0]), // seller_fee_basis_points
+    Buffer.from([0]), // primary_sale_happened
+    Buffer.from([0]), // is_mutable
+    Buffer.from([0]), // edition_nonce: None
+    Buffer.from([1, 0]), // token_standard: Some(NonFungible)
+    Buffer.from([0]), // collection: None
+    Buffer.from([0]), // uses: None
+    Buffer.from([0]), // token_program_version: Original
+    u32(1),
+    holder.toBuffer(),
+    Buffer.from([0, 100]), // unverified, 100% share
+  ]);
+  return {
+    dataHash: keccak(keccak(metadata), Buffer.from([0, 0])),
+    creatorHash: keccak(holder.toBuffer(), Buffer.from([0, 100])),
+  };
+}
+
+describe("neoengine-identity", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
 
-  const identityProgram = anchor.workspace.NeoengineIdentity as Program<NeoengineIdentity>;
-  const usernameProgram = anchor.workspace.NeoengineUsernames as Program<NeoengineUsernames>;
+  const program = anchor.workspace.NeoengineIdentity as Program<NeoengineIdentity>;
+  const authority = provider.wallet.publicKey;
 
-  // Test accounts
   const user1 = Keypair.generate();
   const user2 = Keypair.generate();
-  const authority = Keypair.generate();
+  const user3 = Keypair.generate();
+  const treasury = Keypair.generate().publicKey;
+
+  const pda = (...seeds: (Buffer | Uint8Array)[]) =>
+    PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+  const registryPda = pda(Buffer.from("username_registry"));
+  const programData = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE
+  )[0];
+
+  const usernamePda = (handle: string) => pda(Buffer.from("username"), Buffer.from(canonical(handle)));
+  const identityPda = (wallet: PublicKey) => pda(Buffer.from("identity"), wallet.toBuffer());
+  const mintPda = (handle: string) => pda(Buffer.from("mint"), Buffer.from(canonical(handle)));
+  const usernameNftPda = (handle: string) => pda(Buffer.from("username_nft"), Buffer.from(canonical(handle)));
+  const usernameNftMintPda = (handle: string) =>
+    pda(Buffer.from("username_nft_mint"), Buffer.from(canonical(handle)));
+
+  const registryConfig = (registryAuthority: PublicKey) => ({
+    authority: registryAuthority,
+    treasury,
+    feeCurrency: { lamports: {} },
+    dsxMint: PublicKey.default,
+    lengthPrices: Array.from({ length: PRICE_TABLE_LEN }, () => HANDLE_PRICE),
+    renameCooldownSeconds: new BN(0),
+    quarantineSeconds: new BN(0),
+    auctionOnlyMaxLength: 0,
+  });
 
-  // PDAs
-  let identityPda: PublicKey;
-  let identityBump: number;
-  let usernameRegistryPda: PublicKey;
-  let usernameRegistryBump: number;
-  let usernamePda: PublicKey;
-  let usernameBump: number;
+  const createUsername = (user: Keypair, handle: string) => {
+    const mint = mintPda(handle);
+    return program.methods
+      .createUsername(handle)
+      .accountsPartial({
+        user: user.publicKey,
+        fee: {
+          registry: registryPda,
+          treasury,
+          payerDsxAccount: null,
+          treasuryDsxAccount: null,
+        },
+        usernameAccount: usernamePda(handle),
+        handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+        tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+        identityAccount: identityPda(user.publicKey),
+        mint,
+        tokenAccount: getAssociatedTokenAddressSync(mint, user.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  };
 
-  // Mint and token accounts
-  let identityMint: Keypair;
-  let usernameMint: Keypair;
-  let identityTokenAccount: PublicKey;
-  let usernameTokenAccount: PublicKey;
+  const claimUsernameNft = (user: Keypair, handle: string) => {
+    const mint = usernameNftMintPda(handle);
+    return program.methods
+      .claimUsernameNft(handle)
+      .accountsPartial({
+        user: user.publicKey,
+        fee: {
+          registry: registryPda,
+          treasury,
+          payerDsxAccount: null,
+          treasuryDsxAccount: null,
+        },
+        usernameNft: usernameNftPda(handle),
+        handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+        tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+        mint,
+        tokenAccount: getAssociatedTokenAddressSync(mint, user.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+  };
 
-  const testUsername = "@testuser";
-  const testSymbol = "TEST";
+  // Activation and deactivation take the same accounts
+  const usernameNftAccounts = (user: Keypair, handle: string) => {
+    const mint = usernameNftMintPda(handle);
+    return {
+      user: user.publicKey,
+      identityAccount: identityPda(user.publicKey),
+      usernameNft: usernameNftPda(handle),
+      mint,
+      tokenAccount: getAssociatedTokenAddressSync(mint, user.publicKey),
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+  };
+
+  const transferUsernameNft = (from: Keypair, to: PublicKey, handle: string) => {
+    const mint = usernameNftMintPda(handle);
+    return program.methods
+      .transferUsernameNft()
+      .accountsPartial({
+        user: from.publicKey,
+        recipient: to,
+        usernameNft: usernameNftPda(handle),
+        mint,
+        fromTokenAccount: getAssociatedTokenAddressSync(mint, from.publicKey),
+        toTokenAccount: getAssociatedTokenAddressSync(mint, to),
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([from])
+      .rpc();
+  };
+
+  const checkUsername = (handle: string) =>
+    program.methods
+      .checkUsername(handle)
+      .accountsPartial({
+        handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+        tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+      })
+      .view();
+
+  const expectError = async (tx: Promise<unknown>, message: string) => {
+    try {
+      await tx;
+    } catch (err: any) {
+      expect(`${err}${(err.logs ?? []).join("\n")}`).to.include(message);
+      return;
+    }
+    expect.fail(`expected failure with ${message}`);
+  };
 
   before(async () => {
-    // Airdrop SOL to test accounts
-    await provider.connection.requestAirdrop(user1.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
-    await provider.connection.requestAirdrop(user2.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
-    await provider.connection.requestAirdrop(authority.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
-    
-    // Wait for confirmations
-    await new Promise(resolve => setTimeout(resolve, 1000));
-
-    // Generate PDAs
-    [identityPda, identityBump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("identity"), user1.publicKey.toBuffer()],
-      identityProgram.programId
-    );
-
-    [usernameRegistryPda, usernameRegistryBump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("username_registry")],
-      usernameProgram.programId
-    );
-
-    [usernamePda, usernameBump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("username"), Buffer.from(testUsername)],
-      usernameProgram.programId
-    );
-
-    // Generate mint keypairs
-    identityMint = Keypair.generate();
-    usernameMint = Keypair.generate();
-
-    // Get associated token accounts
-    identityTokenAccount = await getAssociatedTokenAddress(
-      identityMint.publicKey,
-      user1.publicKey
-    );
-
-    usernameTokenAccount = await getAssociatedTokenAddress(
-      usernameMint.publicKey,
-      user1.publicKey
-    );
+    for (const wallet of [user1, user2, user3]) {
+      const signature = await provider.connection.requestAirdrop(wallet.publicKey, 2 * LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+    }
   });
 
   describe("Username Registry", () => {
+    it("rejects initialization by a wallet that is not the upgrade authority", async () => {
+      await expectError(
+        program.methods
+          .initializeRegistry(registryConfig(user1.publicKey))
+          .accountsPartial({
+            authority: user1.publicKey,
+            usernameRegistry: registryPda,
+            program: program.programId,
+            programData,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
     it("Initializes username registry", async () => {
-      await usernameProgram.methods
-        .initializeRegistry()
-        .accounts({
-          authority: authority.publicKey,
-          usernameRegistry: usernameRegistryPda,
+      await program.methods
+        .initializeRegistry(registryConfig(authority))
+        .accountsPartial({
+          authority,
+          usernameRegistry: registryPda,
+          program: program.programId,
+          programData,
           systemProgram: SystemProgram.programId,
         })
-        .signers([authority])
         .rpc();
 
-      const registry = await usernameProgram.account.usernameRegistry.fetch(usernameRegistryPda);
-      expect(registry.authority.toBase58()).to.equal(authority.publicKey.toBase58());
+      const registry = await program.account.usernameRegistry.fetch(registryPda);
+      expect(registry.authority.toBase58()).to.equal(authority.toBase58());
+      expect(registry.treasury.toBase58()).to.equal(treasury.toBase58());
       expect(registry.totalUsernames.toNumber()).to.equal(0);
+      expect(registry.nextHandleId.toNumber()).to.equal(1);
     });
   });
 
   describe("Identity Creation", () => {
+    it("reports a free handle as available in canonical form", async () => {
+      const check = await checkUsername("@Alice");
+      expect(check.canonical).to.equal("alice");
+      expect(check.skeleton).to.equal("allce");
+      expect(check.available).to.be.true;
+      expect(check.quarantineUntil).to.be.null;
+    });
+
     it("Creates a new soulbound identity", async () => {
-      const metadataPda = PublicKey.findProgramAddressSync(
-        [
-          Buffer.from("metadata"),
-          new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s").toBuffer(),
-          identityMint.publicKey.toBuffer(),
-        ],
-        new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s")
-      )[0];
-
-      await identityProgram.methods
-        .createIdentity(5, 24) // 5 username limit, 24 hour cooldown
-        .accounts({
-          user: user1.publicKey,
-          identityAccount: identityPda,
-          mint: identityMint.publicKey,
-          tokenAccount: identityTokenAccount,
-          metadata: metadataPda,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          rent: SYSVAR_RENT_PUBKEY,
-        })
-        .signers([user1, identityMint])
-        .rpc();
+      await createUsername(user1, "@Alice");
 
-      const identity = await identityProgram.account.identityAccount.fetch(identityPda);
+      const username = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      expect(username.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(username.username).to.equal("alice");
+      expect(username.handleId.toNumber()).to.equal(1);
+
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
       expect(identity.wallet.toBase58()).to.equal(user1.publicKey.toBase58());
-      expect(identity.currentUsername).to.be.null;
-      expect(identity.usernameHistory).to.be.empty;
-      expect(identity.maxUsernames).to.equal(5);
-      expect(identity.changeCooldownHours.toNumber()).to.equal(24);
+      expect(identity.usernameAccount.toBase58()).to.equal(usernamePda("alice").toBase58());
+      expect(identity.username).to.equal("alice");
+      expect(identity.activeUsernameNft).to.be.null;
+
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(mintPda("alice"), user1.publicKey)
+      );
+      expect(Number(token.amount)).to.equal(1);
+      expect(token.isFrozen).to.be.true;
+      expect(token.delegate?.toBase58()).to.equal(usernamePda("alice").toBase58());
+
+      const treasuryBalance = await provider.connection.getBalance(treasury);
+      expect(treasuryBalance).to.equal(HANDLE_PRICE.toNumber());
+    });
+
+    it("reports a taken handle as unavailable", async () => {
+      const check = await checkUsername("alice");
+      expect(check.available).to.be.false;
     });
 
     it("Prevents creating multiple identities for same wallet", async () => {
-      try {
-        await identityProgram.methods
-          .createIdentity(5, 24)
-          .accounts({
-            user: user1.publicKey,
-            identityAccount: identityPda,
-            mint: Keypair.generate().publicKey,
-            tokenAccount: await getAssociatedTokenAddress(
-              Keypair.generate().publicKey,
-              user1.publicKey
-            ),
-            metadata: PublicKey.default,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            rent: SYSVAR_RENT_PUBKEY,
-          })
-          .signers([user1])
-          .rpc();
-        
-        expect.fail("Should have failed");
-      } catch (error) {
-        expect(error.message).to.include("IdentityAlreadyExists");
-      }
+      await expectError(createUsername(user1, "bobby"), "IdentityAlreadyExists");
+
+      const handle = await program.account.usernameAccount.fetchNullable(usernamePda("bobby"));
+      expect(handle).to.be.null;
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
+      expect(identity.username).to.equal("alice");
+    });
+
+    it("Prevents registering a taken handle", async () => {
+      await expectError(createUsername(user2, "ALICE"), "IdentityAlreadyExists");
+    });
+
+    it("Prevents registering a handle confusable with a registered one", async () => {
+      await expectError(createUsername(user2, "al1ce"), "IdentityAlreadyExists");
+    });
+
+    it("rejects handles outside the grammar", async () => {
+      await expectError(createUsername(user2, "bad__name"), "InvalidUsernameFormat");
+      await expectError(createUsername(user2, "ab"), "UsernameTooShort");
+    });
+
+    it("lets another wallet register its own identity", async () => {
+      await createUsername(user2, "bobby");
+
+      const identity = await program.account.identityAccount.fetch(identityPda(user2.publicKey));
+      expect(identity.username).to.equal("bobby");
+      const username = await program.account.usernameAccount.fetch(usernamePda("bobby"));
+      expect(username.handleId.toNumber()).to.equal(2);
     });
   });
 
   describe("Username NFTs", () => {
     it("Claims a username NFT", async () => {
-      const metadataPda = PublicKey.findProgramAddressSync(
-        [
-          Buffer.from("metadata"),
-          new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s").toBuffer(),
-          usernameMint.publicKey.toBuffer(),
-        ],
-        new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s")
-      )[0];
-
-      await usernameProgram.methods
-        .claimUsername(testUsername, testSymbol)
-        .accounts({
-          user: user1.publicKey,
-          usernameRegistry: usernameRegistryPda,
-          usernameAccount: usernamePda,
-          mint: usernameMint.publicKey,
-          tokenAccount: usernameTokenAccount,
-          metadata: metadataPda,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          rent: SYSVAR_RENT_PUBKEY,
-        })
-        .signers([user1, usernameMint])
-        .rpc();
+      await claimUsernameNft(user1, "tradable");
 
-      const username = await usernameProgram.account.usernameAccount.fetch(usernamePda);
-      expect(username.name).to.equal(testUsername);
-      expect(username.symbol).to.equal(testSymbol);
-      expect(username.owner.toBase58()).to.equal(user1.publicKey.toBase58());
-      expect(username.isActive).to.be.false;
+      const usernameNft = await program.account.usernameNft.fetch(usernameNftPda("tradable"));
+      expect(usernameNft.username).to.equal("tradable");
+      expect(usernameNft.mint.toBase58()).to.equal(usernameNftMintPda("tradable").toBase58());
+      expect(usernameNft.claimedBy.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(usernameNft.active).to.be.false;
+      expect(usernameNft.assignedIdentity).to.be.null;
+
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(usernameNftMintPda("tradable"), user1.publicKey)
+      );
+      expect(Number(token.amount)).to.equal(1);
+      expect(token.isFrozen).to.be.false;
 
-      const registry = await usernameProgram.account.usernameRegistry.fetch(usernameRegistryPda);
-      expect(registry.totalUsernames.toNumber()).to.equal(1);
+      const registry = await program.account.usernameRegistry.fetch(registryPda);
+      expect(registry.totalUsernameNfts.toNumber()).to.equal(1);
     });
 
     it("Prevents claiming duplicate usernames", async () => {
-      try {
-        await usernameProgram.methods
-          .claimUsername(testUsername, testSymbol)
-          .accounts({
-            user: user2.publicKey,
-            usernameRegistry: usernameRegistryPda,
-            usernameAccount: usernamePda,
-            mint: Keypair.generate().publicKey,
-            tokenAccount: await getAssociatedTokenAddress(
-              Keypair.generate().publicKey,
-              user2.publicKey
-            ),
-            metadata: PublicKey.default,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            tokenMetadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            rent: SYSVAR_RENT_PUBKEY,
-          })
-          .signers([user2])
-          .rpc();
-        
-        expect.fail("Should have failed");
-      } catch (error) {
-        expect(error.message).to.include("already in use");
-      }
+      await expectError(claimUsernameNft(user2, "tradable"), "already in use");
     });
   });
 
   describe("Username Assignment", () => {
     it("Activates username NFT", async () => {
-      await usernameProgram.methods
-        .activateUsername(identityPda)
-        .accounts({
-          user: user1.publicKey,
-          usernameAccount: usernamePda,
-        })
+      await program.methods
+        .activateUsernameNft()
+        .accountsPartial(usernameNftAccounts(user1, "tradable"))
         .signers([user1])
         .rpc();
 
-      const username = await usernameProgram.account.usernameAccount.fetch(usernamePda);
-      expect(username.isActive).to.be.true;
-      expect(username.assignedTo?.toBase58()).to.equal(identityPda.toBase58());
+      const usernameNft = await program.account.usernameNft.fetch(usernameNftPda("tradable"));
+      expect(usernameNft.active).to.be.true;
+      expect(usernameNft.assignedIdentity?.toBase58()).to.equal(identityPda(user1.publicKey).toBase58());
+
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(usernameNftMintPda("tradable"), user1.publicKey)
+      );
+      expect(token.isFrozen).to.be.true;
     });
 
     it("Assigns username to identity", async () => {
-      await identityProgram.methods
-        .assignUsername(usernameMint.publicKey, testUsername)
-        .accounts({
-          user: user1.publicKey,
-          identityAccount: identityPda,
-        })
-        .signers([user1])
-        .rpc();
-
-      const identity = await identityProgram.account.identityAccount.fetch(identityPda);
-      expect(identity.currentUsername).to.equal(testUsername);
-      expect(identity.usernameChangeCount).to.equal(1);
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
+      expect(identity.activeUsernameNft?.toBase58()).to.equal(usernameNftPda("tradable").toBase58());
+      // The soulbound handle stays the identity's own name
+      expect(identity.username).to.equal("alice");
     });
 
     it("Prevents unauthorized username assignment", async () => {
-      try {
-        await identityProgram.methods
-          .assignUsername(usernameMint.publicKey, testUsername)
-          .accounts({
-            user: user2.publicKey, // Wrong user
-            identityAccount: identityPda,
-          })
+      // user2 has an identity and a token account for the mint, but the
+      // active NFT is assigned to user1's identity
+      await createAssociatedTokenAccount(
+        provider.connection,
+        user2,
+        usernameNftMintPda("tradable"),
+        user2.publicKey
+      );
+
+      await expectError(
+        program.methods
+          .deactivateUsernameNft()
+          .accountsPartial(usernameNftAccounts(user2, "tradable"))
           .signers([user2])
-          .rpc();
-        
-        expect.fail("Should have failed");
-      } catch (error) {
-        expect(error.message).to.include("Unauthorized");
-      }
+          .rpc(),
+        "Unauthorized"
+      );
     });
   });
 
   describe("Username Deactivation", () => {
     it("Deactivates username from identity", async () => {
-      await identityProgram.methods
-        .deactivateUsername()
-        .accounts({
-          user: user1.publicKey,
-          identityAccount: identityPda,
-        })
+      await program.methods
+        .deactivateUsernameNft()
+        .accountsPartial(usernameNftAccounts(user1, "tradable"))
         .signers([user1])
         .rpc();
 
-      const identity = await identityProgram.account.identityAccount.fetch(identityPda);
-      expect(identity.currentUsername).to.be.null;
-      expect(identity.usernameHistory).to.include(testUsername);
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
+      expect(identity.activeUsernameNft).to.be.null;
     });
 
     it("Deactivates username NFT", async () => {
-      await usernameProgram.methods
-        .deactivateUsername()
-        .accounts({
-          user: user1.publicKey,
-          usernameAccount: usernamePda,
-        })
-        .signers([user1])
-        .rpc();
+      const usernameNft = await program.account.usernameNft.fetch(usernameNftPda("tradable"));
+      expect(usernameNft.active).to.be.false;
+      expect(usernameNft.assignedIdentity).to.be.null;
 
-      const username = await usernameProgram.account.usernameAccount.fetch(usernamePda);
-      expect(username.isActive).to.be.false;
-      expect(username.assignedTo).to.be.null;
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(usernameNftMintPda("tradable"), user1.publicKey)
+      );
+      expect(token.isFrozen).to.be.false;
     });
   });
 
   describe("Username Transfer", () => {
     it("Transfers username NFT to another user", async () => {
-      const user2TokenAccount = await getAssociatedTokenAddress(
-        usernameMint.publicKey,
-        user2.publicKey
+      await transferUsernameNft(user1, user2.publicKey, "tradable");
+
+      const mint = usernameNftMintPda("tradable");
+      const from = await getAccount(provider.connection, getAssociatedTokenAddressSync(mint, user1.publicKey));
+      const to = await getAccount(provider.connection, getAssociatedTokenAddressSync(mint, user2.publicKey));
+      expect(Number(from.amount)).to.equal(0);
+      expect(Number(to.amount)).to.equal(1);
+    });
+
+    it("Prevents transferring active username", async () => {
+      await program.methods
+        .activateUsernameNft()
+        .accountsPartial(usernameNftAccounts(user2, "tradable"))
+        .signers([user2])
+        .rpc();
+
+      await expectError(transferUsernameNft(user2, user1.publicKey, "tradable"), "UsernameNftActive");
+
+      const usernameNft = await program.account.usernameNft.fetch(usernameNftPda("tradable"));
+      expect(usernameNft.assignedIdentity?.toBase58()).to.equal(identityPda(user2.publicKey).toBase58());
+    });
+  });
+
+  describe("View Functions", () => {
+    it("Gets identity information", async () => {
+      const info = await program.methods
+        .getIdentityByOwner(user1.publicKey)
+        .accountsPartial({ identityAccount: identityPda(user1.publicKey) })
+        .view();
+
+      expect(info.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(info.username).to.equal("alice");
+    });
+
+    it("Gets username information", async () => {
+      const info = await program.methods
+        .getUsernameInfo("bobby")
+        .accountsPartial({ usernameAccount: usernamePda("bobby") })
+        .view();
+
+      expect(info.username).to.equal("bobby");
+      expect(info.owner.toBase58()).to.equal(user2.publicKey.toBase58());
+    });
+  });
+
+  describe("Settings and Limits", () => {
+    it("Updates identity settings", async () => {
+      await program.methods
+        .updateRegistryConfig({ ...registryConfig(authority), renameCooldownSeconds: new BN(3600) })
+        .accountsPartial({ authority, usernameRegistry: registryPda })
+        .rpc();
+
+      const registry = await program.account.usernameRegistry.fetch(registryPda);
+      expect(registry.renameCooldownSeconds.toNumber()).to.equal(3600);
+    });
+
+    it("rejects settings updates from a non-authority", async () => {
+      await expectError(
+        program.methods
+          .updateRegistryConfig(registryConfig(user1.publicKey))
+          .accountsPartial({ authority: user1.publicKey, usernameRegistry: registryPda })
+          .signers([user1])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("Compressed Handles", () => {
+    const merkleTree = Keypair.generate();
+    const treeConfig = PublicKey.findProgramAddressSync([merkleTree.publicKey.toBuffer()], BUBBLEGUM_PROGRAM_ID)[0];
+    const handleTree = pda(Buffer.from("handle_tree"), merkleTree.publicKey.toBuffer());
+    const uri = "https://neoengine.example/carol.json";
+
+    // A tree holding a single leaf: its proof is the empty node at each level
+    const emptyProof = () => {
+      const nodes = [Buffer.alloc(32)];
+      for (let level = 1; level < TREE_MAX_DEPTH; level++) {
+        nodes.push(keccak(nodes[level - 1], nodes[level - 1]));
+      }
+      return nodes;
+    };
+
+    const currentRoot = async () => {
+      const data = (await provider.connection.getAccountInfo(merkleTree.publicKey))!.data;
+      const activeIndex = Number(data.readBigUInt64LE(64));
+      const offset = 80 + activeIndex * CHANGE_LOG_SIZE;
+      return data.subarray(offset, offset + 32);
+    };
+
+    // Bubblegum transfer with `leafOwner` as owner and delegate; `holder`
+    // signs as the leaf owner when given
+    const bubblegumTransfer = async (leafOwner: PublicKey, newOwner: PublicKey, holder?: Keypair) => {
+      const { dataHash, creatorHash } = compressedHandleHashes("carol", uri, user3.publicKey);
+      const keys = [
+        { pubkey: treeConfig, isSigner: false, isWritable: false },
+        { pubkey: leafOwner, isSigner: holder !== undefined, isWritable: false },
+        { pubkey: leafOwner, isSigner: false, isWritable: false },
+        { pubkey: newOwner, isSigner: false, isWritable: false },
+        { pubkey: merkleTree.publicKey, isSigner: false, isWritable: true },
+        { pubkey: SPL_NOOP_ID, isSigner: false, isWritable: false },
+        { pubkey: SPL_ACCOUNT_COMPRESSION_ID, isSigner: false, isWritable: false },
+        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ...emptyProof().map((node) => ({ pubkey: new PublicKey(node), isSigner: false, isWritable: false })),
+      ];
+      const data = Buffer.concat([
+        BUBBLEGUM_TRANSFER_DISCRIMINATOR,
+        await currentRoot(),
+        dataHash,
+        creatorHash,
+        u64(0),
+        u32(0),
+      ]);
+      const ix = new TransactionInstruction({ programId: BUBBLEGUM_PROGRAM_ID, keys, data });
+      return provider.sendAndConfirm(new Transaction().add(ix), holder ? [holder] : []);
+    };
+
+    before(async () => {
+      const lamports = await provider.connection.getMinimumBalanceForRentExemption(TREE_ACCOUNT_SIZE);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: authority,
+            newAccountPubkey: merkleTree.publicKey,
+            lamports,
+            space: TREE_ACCOUNT_SIZE,
+            programId: SPL_ACCOUNT_COMPRESSION_ID,
+          })
+        ),
+        [merkleTree]
+      );
+
+      await program.methods
+        .initializeHandleTree(TREE_MAX_DEPTH, TREE_MAX_BUFFER_SIZE)
+        .accountsPartial({
+          authority,
+          handleTree,
+          merkleTree: merkleTree.publicKey,
+          treeConfig,
+          bubblegumProgram: BUBBLEGUM_PROGRAM_ID,
+          logWrapper: SPL_NOOP_ID,
+          compressionProgram: SPL_ACCOUNT_COMPRESSION_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    });
+
+    it("mints a compressed handle whose leaf is owned by the username PDA", async () => {
+      await program.methods
+        .createCompressedUsername("carol", uri)
+        .accountsPartial({
+          user: user3.publicKey,
+          fee: {
+            registry: registryPda,
+            treasury,
+            payerDsxAccount: null,
+            treasuryDsxAccount: null,
+          },
+          usernameAccount: usernamePda("carol"),
+          handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton("carol"))),
+          tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton("carol"))),
+          identityAccount: identityPda(user3.publicKey),
+          handleTree,
+          merkleTree: merkleTree.publicKey,
+          treeConfig,
+          bubblegumProgram: BUBBLEGUM_PROGRAM_ID,
+          logWrapper: SPL_NOOP_ID,
+          compressionProgram: SPL_ACCOUNT_COMPRESSION_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user3])
+        .rpc();
+
+      const username = await program.account.usernameAccount.fetch(usernamePda("carol"));
+      expect(username.owner.toBase58()).to.equal(user3.publicKey.toBase58());
+      expect(username.standard).to.deep.equal({ compressed: {} });
+      expect(username.compressedLeaf?.nonce.toNumber()).to.equal(0);
+
+      // The leaf verifies with the username PDA as owner and delegate
+      const { dataHash, creatorHash } = compressedHandleHashes("carol", uri, user3.publicKey);
+      await program.methods
+        .verifyCompressedUsername([...(await currentRoot())], [...dataHash], [...creatorHash])
+        .accountsPartial({
+          usernameAccount: usernamePda("carol"),
+          merkleTree: merkleTree.publicKey,
+          compressionProgram: SPL_ACCOUNT_COMPRESSION_ID,
+        })
+        .remainingAccounts(
+          emptyProof().map((node) => ({ pubkey: new PublicKey(node), isSigner: false, isWritable: false }))
+        )
+        .rpc();
+    });
+
+    it("rejects a Bubblegum transfer signed by the handle holder", async () => {
+      // The holder is not the leaf owner, so the recomputed leaf is not in the tree
+      await expectError(bubblegumTransfer(user3.publicKey, user1.publicKey, user3), "ConcurrentMerkleTreeError");
+    });
+
+    it("rejects a Bubblegum transfer that the username PDA does not sign", async () => {
+      await expectError(
+        bubblegumTransfer(usernamePda("carol"), user3.publicKey),
+        "LeafAuthorityMustSign"
       );
+    });
+  });
+
+  describe("Handle Auctions", () => {
+    const bidderA = Keypair.generate();
+    const bidderB = Keypair.generate();
+    const handle = "auctioned";
+    const auctionPda = pda(Buffer.from("auction"), Buffer.from(handle));
+    const vaultPda = pda(Buffer.from("auction_vault"), auctionPda.toBuffer());
+    const bidRefundPda = (bidder: PublicKey) =>
+      pda(Buffer.from("bid_refund"), auctionPda.toBuffer(), bidder.toBuffer());
+    let dsxMint: PublicKey;
+    let treasuryDsx: PublicKey;
+
+    const placeBid = (bidder: Keypair, amount: number, previousBidder: PublicKey | null) =>
+      program.methods
+        .placeBid(new BN(amount))
+        .accountsPartial({
+          bidder: bidder.publicKey,
+          auction: auctionPda,
+          bidderIdentity: identityPda(bidder.publicKey),
+          previousBidder,
+          vault: vaultPda,
+          bidderDsxAccount: getAssociatedTokenAddressSync(dsxMint, bidder.publicKey),
+          dsxMint: previousBidder ? dsxMint : null,
+          bidRefund: previousBidder ? bidRefundPda(previousBidder) : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([bidder])
+        .rpc();
 
-      await usernameProgram.methods
-        .transferUsername(user2.publicKey)
-        .accounts({
-          currentOwner: user1.publicKey,
-          usernameAccount: usernamePda,
-          fromTokenAccount: usernameTokenAccount,
-          toTokenAccount: user2TokenAccount,
-          newOwner: user2.publicKey,
+    const settleAuction = (winner: PublicKey) =>
+      program.methods
+        .settleAuction()
+        .accountsPartial({
+          payer: authority,
+          winner,
+          registry: registryPda,
+          treasury,
+          treasuryDsxAccount: treasuryDsx,
+          creator: authority,
+          auction: auctionPda,
+          vault: vaultPda,
+          winnerDsxAccount: null,
+          handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+          identityAccount: identityPda(winner),
+          usernameAccount: usernamePda(handle),
+          mint: mintPda(handle),
+          tokenAccount: getAssociatedTokenAddressSync(mintPda(handle), winner),
           tokenProgram: TOKEN_PROGRAM_ID,
           associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
-        .signers([user1])
         .rpc();
 
-      const username = await usernameProgram.account.usernameAccount.fetch(usernamePda);
-      expect(username.owner.toBase58()).to.equal(user2.publicKey.toBase58());
-      expect(username.transferCount).to.equal(1);
+    before(async () => {
+      for (const wallet of [bidderA, bidderB]) {
+        const signature = await provider.connection.requestAirdrop(wallet.publicKey, LAMPORTS_PER_SOL);
+        const latest = await provider.connection.getLatestBlockhash();
+        await provider.connection.confirmTransaction({ signature, ...latest });
+      }
+
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      dsxMint = await createMint(provider.connection, payer, authority, null, 0);
+      treasuryDsx = await createAssociatedTokenAccount(provider.connection, payer, dsxMint, treasury);
+      // bidderA holds exactly one reserve-priced bid, so their account can be closed after bidding
+      for (const [wallet, amount] of [
+        [bidderA, 100],
+        [bidderB, 1_000],
+      ] as [Keypair, number][]) {
+        const account = await createAssociatedTokenAccount(provider.connection, payer, dsxMint, wallet.publicKey);
+        await mintTo(provider.connection, payer, dsxMint, account, payer, amount);
+      }
+
+      await program.methods
+        .updateRegistryConfig({ ...registryConfig(authority), dsxMint })
+        .accountsPartial({ authority, usernameRegistry: registryPda })
+        .rpc();
     });
 
-    it("Prevents transferring active username", async () => {
-      // First activate the username
-      await usernameProgram.methods
-        .activateUsername(identityPda)
-        .accounts({
-          user: user2.publicKey,
-          usernameAccount: usernamePda,
+    it("creates a DSX auction for a reserved handle", async () => {
+      await program.methods
+        .createAuction(handle, { dsx: {} }, new BN(100), new BN(10), new BN(15), new BN(0), new BN(0))
+        .accountsPartial({
+          authority,
+          registry: registryPda,
+          auction: auctionPda,
+          handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+          tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+          vault: vaultPda,
+          dsxMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         })
-        .signers([user2])
         .rpc();
 
-      try {
-        await usernameProgram.methods
-          .transferUsername(user1.publicKey)
-          .accounts({
-            currentOwner: user2.publicKey,
-            usernameAccount: usernamePda,
-            fromTokenAccount: await getAssociatedTokenAddress(
-              usernameMint.publicKey,
-              user2.publicKey
-            ),
-            toTokenAccount: usernameTokenAccount,
-            newOwner: user1.publicKey,
+      const auction = await program.account.handleAuction.fetch(auctionPda);
+      expect(auction.handle).to.equal(handle);
+      expect(auction.highestBidder).to.be.null;
+      expect((await checkUsername(handle)).available).to.be.false;
+    });
+
+    it("rejects a bid below the reserve", async () => {
+      await expectError(placeBid(bidderA, 99, null), "BidTooLow");
+    });
+
+    it("rejects a bid from a wallet that already has an identity", async () => {
+      const account = await createAssociatedTokenAccount(
+        provider.connection,
+        (provider.wallet as anchor.Wallet).payer,
+        dsxMint,
+        user1.publicKey
+      );
+      await expectError(
+        program.methods
+          .placeBid(new BN(100))
+          .accountsPartial({
+            bidder: user1.publicKey,
+            auction: auctionPda,
+            bidderIdentity: identityPda(user1.publicKey),
+            previousBidder: null,
+            vault: vaultPda,
+            bidderDsxAccount: account,
+            dsxMint: null,
+            bidRefund: null,
             tokenProgram: TOKEN_PROGRAM_ID,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
             systemProgram: SystemProgram.programId,
           })
-          .signers([user2])
+          .signers([user1])
+          .rpc(),
+        "IdentityAlreadyExists"
+      );
+    });
+
+    it("escrows the opening bid", async () => {
+      await placeBid(bidderA, 100, null);
+
+      const auction = await program.account.handleAuction.fetch(auctionPda);
+      expect(auction.highestBidder?.toBase58()).to.equal(bidderA.publicKey.toBase58());
+      expect(Number((await getAccount(provider.connection, vaultPda)).amount)).to.equal(100);
+    });
+
+    it("outbids a leader whose DSX account is closed and escrows their refund", async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      await closeAccount(
+        provider.connection,
+        payer,
+        getAssociatedTokenAddressSync(dsxMint, bidderA.publicKey),
+        bidderA.publicKey,
+        bidderA
+      );
+
+      await expectError(placeBid(bidderB, 105, bidderA.publicKey), "BidTooLow");
+      await placeBid(bidderB, 110, bidderA.publicKey);
+
+      const auction = await program.account.handleAuction.fetch(auctionPda);
+      expect(auction.highestBidder?.toBase58()).to.equal(bidderB.publicKey.toBase58());
+      expect(Number((await getAccount(provider.connection, vaultPda)).amount)).to.equal(110);
+      const refund = await getAccount(provider.connection, bidRefundPda(bidderA.publicKey));
+      expect(Number(refund.amount)).to.equal(100);
+    });
+
+    it("lets the outbid bidder claim their refund once", async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      const destination = await createAssociatedTokenAccount(provider.connection, payer, dsxMint, bidderA.publicKey);
+      const claim = () =>
+        program.methods
+          .claimBidRefund(auctionPda)
+          .accountsPartial({
+            bidder: bidderA.publicKey,
+            bidRefund: bidRefundPda(bidderA.publicKey),
+            destination,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([bidderA])
           .rpc();
-        
-        expect.fail("Should have failed");
-      } catch (error) {
-        expect(error.message).to.include("CannotTransferActiveUsername");
-      }
+
+      await claim();
+      expect(Number((await getAccount(provider.connection, destination)).amount)).to.equal(100);
+      expect(await provider.connection.getAccountInfo(bidRefundPda(bidderA.publicKey))).to.be.null;
+
+      await expectError(claim(), "AccountNotInitialized");
+    });
+
+    it("rejects settlement before the auction ends", async () => {
+      await expectError(settleAuction(bidderB.publicKey), "AuctionNotEnded");
+    });
+
+    it("lets anyone settle an ended auction and delivers the handle", async () => {
+      const { endTime } = await program.account.handleAuction.fetch(auctionPda);
+      await new Promise((resolve) => setTimeout(resolve, (endTime.toNumber() + 2) * 1000 - Date.now()));
+      await settleAuction(bidderB.publicKey);
+
+      const username = await program.account.usernameAccount.fetch(usernamePda(handle));
+      expect(username.owner.toBase58()).to.equal(bidderB.publicKey.toBase58());
+      const identity = await program.account.identityAccount.fetch(identityPda(bidderB.publicKey));
+      expect(identity.username).to.equal(handle);
+      expect(Number((await getAccount(provider.connection, treasuryDsx)).amount)).to.equal(110);
+      expect(await provider.connection.getAccountInfo(auctionPda)).to.be.null;
     });
   });
 
-  describe("View Functions", () => {
-    it("Gets identity information", async () => {
-      const info = await identityProgram.methods
-        .getIdentityInfo()
-        .accounts({
-          identityAccount: identityPda,
+  describe("Moderation", () => {
+    const moderator = Keypair.generate();
+    const moderationPda = pda(Buffer.from("moderation"));
+    let suspensionPda: PublicKey;
+
+    const suspend = (signer: Keypair) =>
+      program.methods
+        .suspendHandle(7)
+        .accountsPartial({
+          moderator: signer.publicKey,
+          moderationConfig: moderationPda,
+          usernameAccount: usernamePda("bobby"),
+          suspension: suspensionPda,
+          systemProgram: SystemProgram.programId,
         })
-        .view();
+        .signers([signer])
+        .rpc();
+
+    const appeal = (owner: Keypair, uri: string) =>
+      program.methods
+        .appealSuspension(uri)
+        .accountsPartial({ owner: owner.publicKey, usernameAccount: usernamePda("bobby"), suspension: suspensionPda })
+        .signers([owner])
+        .rpc();
+
+    const resolve = (lift: boolean) =>
+      program.methods
+        .resolveSuspension(lift)
+        .accountsPartial({
+          moderator: moderator.publicKey,
+          moderationConfig: moderationPda,
+          usernameAccount: usernamePda("bobby"),
+          suspension: suspensionPda,
+        })
+        .signers([moderator])
+        .rpc();
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(moderator.publicKey, LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
 
-      expect(info.wallet.toBase58()).to.equal(user1.publicKey.toBase58());
-      expect(info.usernameHistory).to.include(testUsername);
+      const { handleId } = await program.account.usernameAccount.fetch(usernamePda("bobby"));
+      suspensionPda = pda(Buffer.from("suspension"), usernamePda("bobby").toBuffer(), u64(handleId.toNumber()));
     });
 
-    it("Gets username information", async () => {
-      const info = await usernameProgram.methods
-        .getUsernameInfo()
-        .accounts({
-          usernameAccount: usernamePda,
+    it("rejects moderation config from a wallet that is not the registry authority", async () => {
+      await expectError(
+        program.methods
+          .configureModeration(user1.publicKey, [user1.publicKey])
+          .accountsPartial({
+            signer: user1.publicKey,
+            registry: registryPda,
+            moderationConfig: moderationPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("bootstraps moderators from the registry authority", async () => {
+      await program.methods
+        .configureModeration(authority, [moderator.publicKey])
+        .accountsPartial({
+          signer: authority,
+          registry: registryPda,
+          moderationConfig: moderationPda,
+          systemProgram: SystemProgram.programId,
         })
-        .view();
+        .rpc();
 
-      expect(info.name).to.equal(testUsername);
-      expect(info.owner.toBase58()).to.equal(user2.publicKey.toBase58());
+      const config = await program.account.moderationConfig.fetch(moderationPda);
+      expect(config.moderators.map((m) => m.toBase58())).to.deep.equal([moderator.publicKey.toBase58()]);
+    });
+
+    it("rejects a suspension from a non-moderator", async () => {
+      await expectError(suspend(user1), "Unauthorized");
+    });
+
+    it("suspends a handle", async () => {
+      await suspend(moderator);
+
+      const username = await program.account.usernameAccount.fetch(usernamePda("bobby"));
+      expect(username.suspended).to.be.true;
+      const suspension = await program.account.handleSuspension.fetch(suspensionPda);
+      expect(suspension.reasonCode).to.equal(7);
+      expect(suspension.status).to.deep.equal({ suspended: {} });
+    });
+
+    it("accepts one appeal from the handle owner only", async () => {
+      await expectError(appeal(user1, "https://neoengine.example/appeal"), "Unauthorized");
+      await appeal(user2, "https://neoengine.example/appeal");
+
+      const suspension = await program.account.handleSuspension.fetch(suspensionPda);
+      expect(suspension.status).to.deep.equal({ appealed: {} });
+      await expectError(appeal(user2, "https://neoengine.example/again"), "InvalidAppeal");
+    });
+
+    it("upholds and later lifts a suspension, closing the record", async () => {
+      await resolve(false);
+      expect((await program.account.handleSuspension.fetch(suspensionPda)).status).to.deep.equal({ upheld: {} });
+      await expectError(appeal(user2, "https://neoengine.example/appeal"), "InvalidAppeal");
+
+      await resolve(true);
+      expect(await provider.connection.getAccountInfo(suspensionPda)).to.be.null;
+      expect((await program.account.usernameAccount.fetch(usernamePda("bobby"))).suspended).to.be.false;
+    });
+
+    it("suspends the same handle again after a lift", async () => {
+      await suspend(moderator);
+      expect((await program.account.usernameAccount.fetch(usernamePda("bobby"))).suspended).to.be.true;
+      await resolve(true);
     });
   });
 
-  describe("Settings and Limits", () => {
-    it("Updates identity settings", async () => {
-      await identityProgram.methods
-        .updateIdentitySettings(10, 12) // New limits
-        .accounts({
-          user: user1.publicKey,
-          identityAccount: identityPda,
+  describe("Username NFT Ownership", () => {
+    it("claims a second username NFT and hands it to another wallet", async () => {
+      await claimUsernameNft(user1, "spare");
+      await createAssociatedTokenAccount(provider.connection, user2, usernameNftMintPda("spare"), user2.publicKey);
+      await transferUsernameNft(user1, user2.publicKey, "spare");
+
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(usernameNftMintPda("spare"), user2.publicKey)
+      );
+      expect(Number(token.amount)).to.equal(1);
+    });
+
+    it("rejects activation by a wallet that no longer holds the NFT", async () => {
+      await expectError(
+        program.methods
+          .activateUsernameNft()
+          .accountsPartial(usernameNftAccounts(user1, "spare"))
+          .signers([user1])
+          .rpc(),
+        "UsernameNftNotOwned"
+      );
+
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
+      expect(identity.activeUsernameNft).to.be.null;
+    });
+  });
+
+  describe("Social Recovery", () => {
+    const rescue = Keypair.generate();
+    const DAY = 24 * 60 * 60;
+    let handleId: Buffer;
+
+    const recoveryPda = () => pda(Buffer.from("recovery"), handleId);
+    const recoveryRequestPda = () => pda(Buffer.from("recovery_request"), handleId);
+
+    const configureRecovery = (owner: Keypair, guardians: PublicKey[], threshold: number, timelock: number) =>
+      program.methods
+        .configureRecovery(guardians, threshold, new BN(timelock))
+        .accountsPartial({
+          owner: owner.publicKey,
+          usernameAccount: usernamePda("alice"),
+          recoveryConfig: recoveryPda(),
+          systemProgram: SystemProgram.programId,
         })
-        .signers([user1])
+        .signers([owner])
+        .rpc();
+
+    const initiateRecovery = (guardian: Keypair, newWallet: PublicKey) =>
+      program.methods
+        .initiateRecovery(newWallet)
+        .accountsPartial({
+          guardian: guardian.publicKey,
+          usernameAccount: usernamePda("alice"),
+          recoveryConfig: recoveryPda(),
+          recoveryRequest: recoveryRequestPda(),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([guardian])
         .rpc();
 
-      const identity = await identityProgram.account.identityAccount.fetch(identityPda);
-      expect(identity.maxUsernames).to.equal(10);
-      expect(identity.changeCooldownHours.toNumber()).to.equal(12);
+    const approveRecovery = (guardian: Keypair) =>
+      program.methods
+        .approveRecovery()
+        .accountsPartial({
+          guardian: guardian.publicKey,
+          usernameAccount: usernamePda("alice"),
+          recoveryConfig: recoveryPda(),
+          recoveryRequest: recoveryRequestPda(),
+        })
+        .signers([guardian])
+        .rpc();
+
+    const executeRecovery = () => {
+      const mint = mintPda("alice");
+      return program.methods
+        .executeRecovery()
+        .accountsPartial({
+          newWallet: rescue.publicKey,
+          usernameAccount: usernamePda("alice"),
+          recoveryConfig: recoveryPda(),
+          initiator: user2.publicKey,
+          recoveryRequest: recoveryRequestPda(),
+          oldIdentity: identityPda(user1.publicKey),
+          newIdentity: identityPda(rescue.publicKey),
+          mint,
+          oldTokenAccount: getAssociatedTokenAddressSync(mint, user1.publicKey),
+          newTokenAccount: getAssociatedTokenAddressSync(mint, rescue.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([rescue])
+        .rpc();
+    };
+
+    before(async () => {
+      const username = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      handleId = username.handleId.toArrayLike(Buffer, "le", 8);
+
+      const signature = await provider.connection.requestAirdrop(rescue.publicKey, LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+    });
+
+    it("rejects a threshold above the guardian count", async () => {
+      await expectError(
+        configureRecovery(user1, [user2.publicKey, user3.publicKey], 3, DAY),
+        "InvalidRecoveryThreshold"
+      );
+    });
+
+    it("rejects a timelock shorter than the minimum", async () => {
+      await expectError(
+        configureRecovery(user1, [user2.publicKey, user3.publicKey], 2, 60),
+        "RecoveryTimelockTooShort"
+      );
+    });
+
+    it("rejects the owner as their own guardian", async () => {
+      await expectError(configureRecovery(user1, [user1.publicKey], 1, DAY), "InvalidGuardianSet");
+    });
+
+    it("rejects recovery config from a wallet that does not own the handle", async () => {
+      await expectError(configureRecovery(user2, [user3.publicKey], 1, DAY), "Unauthorized");
+    });
+
+    it("configures guardians for a handle", async () => {
+      await configureRecovery(user1, [user2.publicKey, user3.publicKey], 2, DAY);
+
+      const config = await program.account.recoveryConfig.fetch(recoveryPda());
+      expect(config.guardians.map((g) => g.toBase58())).to.deep.equal([
+        user2.publicKey.toBase58(),
+        user3.publicKey.toBase58(),
+      ]);
+      expect(config.threshold).to.equal(2);
+      expect(config.timelockSeconds.toNumber()).to.equal(DAY);
+    });
+
+    it("rejects a recovery opened by a non-guardian", async () => {
+      await expectError(initiateRecovery(rescue, rescue.publicKey), "NotAGuardian");
+    });
+
+    it("rejects recovery to the current owner", async () => {
+      await expectError(initiateRecovery(user2, user1.publicKey), "InvalidRecoveryWallet");
+    });
+
+    it("opens a recovery request counting the initiator's approval", async () => {
+      await initiateRecovery(user2, rescue.publicKey);
+
+      const request = await program.account.recoveryRequest.fetch(recoveryRequestPda());
+      expect(request.newWallet.toBase58()).to.equal(rescue.publicKey.toBase58());
+      expect(request.initiator.toBase58()).to.equal(user2.publicKey.toBase58());
+      expect(request.approvals).to.have.length(1);
+      expect(request.thresholdReachedAt).to.be.null;
+    });
+
+    it("rejects execution before the threshold is met", async () => {
+      await expectError(executeRecovery(), "RecoveryThresholdNotMet");
+    });
+
+    it("rejects a second approval from the same guardian", async () => {
+      await expectError(approveRecovery(user2), "RecoveryAlreadyApproved");
+    });
+
+    it("starts the timelock once the threshold is met", async () => {
+      await approveRecovery(user3);
+
+      const request = await program.account.recoveryRequest.fetch(recoveryRequestPda());
+      expect(request.approvals).to.have.length(2);
+      expect(request.thresholdReachedAt).to.not.be.null;
+    });
+
+    it("rejects execution while the timelock is active", async () => {
+      await expectError(executeRecovery(), "RecoveryTimelockActive");
+
+      const username = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      expect(username.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+    });
+
+    it("rejects cancellation by anyone but the owner", async () => {
+      await expectError(
+        program.methods
+          .cancelRecovery()
+          .accountsPartial({
+            owner: user2.publicKey,
+            usernameAccount: usernamePda("alice"),
+            initiator: user2.publicKey,
+            recoveryRequest: recoveryRequestPda(),
+          })
+          .signers([user2])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("lets the owner cancel a pending request", async () => {
+      await program.methods
+        .cancelRecovery()
+        .accountsPartial({
+          owner: user1.publicKey,
+          usernameAccount: usernamePda("alice"),
+          initiator: user2.publicKey,
+          recoveryRequest: recoveryRequestPda(),
+        })
+        .signers([user1])
+        .rpc();
+
+      const request = await program.account.recoveryRequest.fetchNullable(recoveryRequestPda());
+      expect(request).to.be.null;
+    });
+  });
+
+  describe("Handle Renaming", () => {
+    const renamer = Keypair.generate();
+
+    const rename = (owner: Keypair, from: string, to: string) => {
+      const oldMint = mintPda(from);
+      const newMint = mintPda(to);
+      const sameSkeleton = skeleton(from) === skeleton(to);
+      return program.methods
+        .updateHandle(to)
+        .accountsPartial({
+          owner: owner.publicKey,
+          fee: {
+            registry: registryPda,
+            treasury,
+            payerDsxAccount: null,
+            treasuryDsxAccount: null,
+          },
+          oldUsernameAccount: usernamePda(from),
+          newUsernameAccount: usernamePda(to),
+          oldSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(from))),
+          newSkeleton: sameSkeleton ? null : pda(Buffer.from("skeleton"), Buffer.from(skeleton(to))),
+          oldTombstone: sameSkeleton ? null : pda(Buffer.from("tombstone"), Buffer.from(skeleton(from))),
+          newTombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(to))),
+          identityAccount: identityPda(owner.publicKey),
+          oldMint,
+          oldTokenAccount: getAssociatedTokenAddressSync(oldMint, owner.publicKey),
+          newMint,
+          newTokenAccount: getAssociatedTokenAddressSync(newMint, owner.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(renamer.publicKey, 2 * LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+
+      await program.methods
+        .updateRegistryConfig({ ...registryConfig(authority), renameCooldownSeconds: new BN(3600) })
+        .accountsPartial({ authority, usernameRegistry: registryPda })
+        .rpc();
+      await createUsername(renamer, "oldname");
+    });
+
+    after(async () => {
+      await program.methods
+        .updateRegistryConfig(registryConfig(authority))
+        .accountsPartial({ authority, usernameRegistry: registryPda })
+        .rpc();
+    });
+
+    it("rejects a rename by a wallet that does not own the handle", async () => {
+      await expectError(rename(user2, "oldname", "stolen"), "Unauthorized");
+    });
+
+    it("renames a handle, tombstones the old skeleton and keeps the handle id", async () => {
+      const before = await program.account.usernameAccount.fetch(usernamePda("oldname"));
+      await rename(renamer, "oldname", "newname");
+
+      expect(await provider.connection.getAccountInfo(usernamePda("oldname"))).to.be.null;
+      const renamed = await program.account.usernameAccount.fetch(usernamePda("newname"));
+      expect(renamed.owner.toBase58()).to.equal(renamer.publicKey.toBase58());
+      expect(renamed.handleId.toNumber()).to.equal(before.handleId.toNumber());
+      expect(renamed.previousUsernames).to.deep.equal(["oldname"]);
+      expect(renamed.lastRenamedAt.toNumber()).to.be.greaterThan(0);
+
+      const identity = await program.account.identityAccount.fetch(identityPda(renamer.publicKey));
+      expect(identity.username).to.equal("newname");
+      expect(identity.usernameAccount.toBase58()).to.equal(usernamePda("newname").toBase58());
+
+      const tombstone = await program.account.handleTombstone.fetch(
+        pda(Buffer.from("tombstone"), Buffer.from(skeleton("oldname")))
+      );
+      expect(tombstone.username).to.equal("oldname");
+      expect(tombstone.formerOwner.toBase58()).to.equal(renamer.publicKey.toBase58());
+
+      const oldToken = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(mintPda("oldname"), renamer.publicKey)
+      );
+      expect(Number(oldToken.amount)).to.equal(0);
+      const newToken = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(mintPda("newname"), renamer.publicKey)
+      );
+      expect(Number(newToken.amount)).to.equal(1);
+      expect(newToken.isFrozen).to.be.true;
+    });
+
+    it("rejects a second rename inside the cooldown", async () => {
+      await expectError(rename(renamer, "newname", "thirdname"), "RenameCooldownActive");
+
+      const identity = await program.account.identityAccount.fetch(identityPda(renamer.publicKey));
+      expect(identity.username).to.equal("newname");
+    });
+  });
+
+  describe("Token-2022 Handles", () => {
+    const holder = Keypair.generate();
+    const handle = "sbtholder";
+    const mint = pda(Buffer.from("mint_2022"), Buffer.from(handle));
+    const holderAta = getAssociatedTokenAddressSync(mint, holder.publicKey, false, TOKEN_2022_PROGRAM_ID);
+
+    const createUsernameToken2022 = (uri: string) =>
+      program.methods
+        .createUsernameToken2022(handle, uri)
+        .accountsPartial({
+          user: holder.publicKey,
+          fee: {
+            registry: registryPda,
+            treasury,
+            payerDsxAccount: null,
+            treasuryDsxAccount: null,
+          },
+          usernameAccount: usernamePda(handle),
+          handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+          tombstone: pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+          identityAccount: identityPda(holder.publicKey),
+          mint,
+          tokenAccount: holderAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([holder])
+        .rpc();
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(holder.publicKey, LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+    });
+
+    it("rejects a metadata uri over the length limit", async () => {
+      await expectError(createUsernameToken2022(`https://${"x".repeat(200)}`), "InvalidMetadataUri");
+    });
+
+    it("mints a NonTransferable handle with on-mint metadata", async () => {
+      await createUsernameToken2022("https://neoengine.example/sbtholder.json");
+
+      const username = await program.account.usernameAccount.fetch(usernamePda(handle));
+      expect(username.standard).to.deep.equal({ nonTransferable2022: {} });
+      expect(username.mint.toBase58()).to.equal(mint.toBase58());
+
+      const mintInfo = await getMint(provider.connection, mint, undefined, TOKEN_2022_PROGRAM_ID);
+      const extensions = getExtensionTypes(mintInfo.tlvData);
+      expect(extensions).to.include(ExtensionType.NonTransferable);
+      expect(extensions).to.include(ExtensionType.PermanentDelegate);
+      expect(extensions).to.include(ExtensionType.MetadataPointer);
+
+      const token = await getAccount(provider.connection, holderAta, undefined, TOKEN_2022_PROGRAM_ID);
+      expect(Number(token.amount)).to.equal(1);
+    });
+
+    it("rejects a token transfer out of the holder's wallet", async () => {
+      const destination = await createAssociatedTokenAccountIdempotent(
+        provider.connection,
+        holder,
+        mint,
+        user1.publicKey,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      await expectError(
+        transferChecked(
+          provider.connection,
+          holder,
+          holderAta,
+          mint,
+          destination,
+          holder,
+          1,
+          0,
+          undefined,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        ),
+        "0x25"
+      );
+
+      const token = await getAccount(provider.connection, holderAta, undefined, TOKEN_2022_PROGRAM_ID);
+      expect(Number(token.amount)).to.equal(1);
+    });
+  });
+
+  describe("Name-Service Records", () => {
+    let handleId: Buffer;
+    const recordPda = (key: string) => pda(Buffer.from("record"), handleId, Buffer.from(key));
+
+    const setRecord = (owner: Keypair, value: any, key: string) =>
+      program.methods
+        .setRecord(value)
+        .accountsPartial({
+          owner: owner.publicKey,
+          usernameAccount: usernamePda("alice"),
+          record: recordPda(key),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+
+    const resolveRecord = (key: string) =>
+      program.methods
+        .resolveRecord("alice", key)
+        .accountsPartial({ usernameAccount: usernamePda("alice"), record: recordPda(key) })
+        .view();
+
+    before(async () => {
+      const username = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      handleId = username.handleId.toArrayLike(Buffer, "le", 8);
+    });
+
+    it("sets and resolves a payment address record", async () => {
+      await setRecord(user1, { solAddress: { "0": user1.publicKey } }, "sol");
+
+      const resolved = await resolveRecord("sol");
+      expect(resolved.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(resolved.username).to.equal("alice");
+      expect(resolved.key).to.equal("sol");
+      expect(resolved.value.solAddress["0"].toBase58()).to.equal(user1.publicKey.toBase58());
+    });
+
+    it("overwrites a record under the same key", async () => {
+      await setRecord(user1, { solAddress: { "0": user3.publicKey } }, "sol");
+
+      const resolved = await resolveRecord("sol");
+      expect(resolved.value.solAddress["0"].toBase58()).to.equal(user3.publicKey.toBase58());
+    });
+
+    it("rejects records from a wallet that does not own the handle", async () => {
+      await expectError(setRecord(user2, { url: { "0": "https://evil.example" } }, "url"), "Unauthorized");
+    });
+
+    it("rejects a text record with an invalid key", async () => {
+      await expectError(
+        setRecord(user1, { text: { key: "Bad Key", value: "hi" } }, "text.Bad Key"),
+        "InvalidRecordKey"
+      );
+    });
+
+    it("deletes a record and reclaims its rent", async () => {
+      await program.methods
+        .deleteRecord("sol")
+        .accountsPartial({ owner: user1.publicKey, usernameAccount: usernamePda("alice"), record: recordPda("sol") })
+        .signers([user1])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(recordPda("sol"))).to.be.null;
+    });
+  });
+
+  describe("Sub-handles", () => {
+    let parentId: Buffer;
+    const policyPda = () => pda(Buffer.from("subdomain_policy"), parentId);
+    const subdomainPda = (label: string) => pda(Buffer.from("subdomain"), parentId, Buffer.from(label));
+    const subdomainMintPda = (label: string) => pda(Buffer.from("subdomain_mint"), subdomainPda(label).toBuffer());
+
+    const createSubdomain = (signer: Keypair, recipient: PublicKey, label: string, withPolicy: boolean) =>
+      program.methods
+        .createSubdomain(label)
+        .accountsPartial({
+          authority: signer.publicKey,
+          recipient,
+          parent: usernamePda("alice"),
+          parentOwner: user1.publicKey,
+          policy: withPolicy ? policyPda() : null,
+          subdomain: subdomainPda(label),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    const transferSubdomain = (owner: Keypair, label: string, newOwner: PublicKey) =>
+      program.methods
+        .transferSubdomain(newOwner)
+        .accountsPartial({ owner: owner.publicKey, parent: usernamePda("alice"), subdomain: subdomainPda(label) })
+        .signers([owner])
+        .rpc();
+
+    const revokeSubdomain = (owner: Keypair, label: string, holder: PublicKey, tokenized: boolean) =>
+      program.methods
+        .revokeSubdomain()
+        .accountsPartial({
+          owner: owner.publicKey,
+          parent: usernamePda("alice"),
+          subdomainOwner: holder,
+          subdomain: subdomainPda(label),
+          mint: tokenized ? subdomainMintPda(label) : null,
+          tokenAccount: tokenized ? getAssociatedTokenAddressSync(subdomainMintPda(label), holder) : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+
+    before(async () => {
+      const parent = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      parentId = parent.handleId.toArrayLike(Buffer, "le", 8);
+    });
+
+    it("rejects a self-claim under a parent without a policy", async () => {
+      await expectError(createSubdomain(user2, user2.publicKey, "guest", false), "SubdomainNotAllowed");
+    });
+
+    it("lets the parent owner issue a sub-handle to any wallet", async () => {
+      await createSubdomain(user1, user2.publicKey, "team", false);
+
+      const subdomain = await program.account.subdomainAccount.fetch(subdomainPda("team"));
+      expect(subdomain.owner.toBase58()).to.equal(user2.publicKey.toBase58());
+      expect(subdomain.label).to.equal("team");
+      expect(subdomain.mint).to.be.null;
+    });
+
+    it("rejects a policy change from a wallet that does not own the parent", async () => {
+      await expectError(
+        program.methods
+          .configureSubdomains({ open: {} }, new BN(0), [])
+          .accountsPartial({
+            owner: user2.publicKey,
+            parent: usernamePda("alice"),
+            policy: policyPda(),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user2])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("admits only whitelisted wallets under a whitelist policy", async () => {
+      await program.methods
+        .configureSubdomains({ whitelist: {} }, new BN(0), [user3.publicKey])
+        .accountsPartial({
+          owner: user1.publicKey,
+          parent: usernamePda("alice"),
+          policy: policyPda(),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      await expectError(createSubdomain(user2, user2.publicKey, "guest", true), "SubdomainNotAllowed");
+      await createSubdomain(user3, user3.publicKey, "crew", true);
+      const subdomain = await program.account.subdomainAccount.fetch(subdomainPda("crew"));
+      expect(subdomain.owner.toBase58()).to.equal(user3.publicKey.toBase58());
+    });
+
+    it("transfers an unlocked sub-handle", async () => {
+      await transferSubdomain(user2, "team", user3.publicKey);
+
+      const subdomain = await program.account.subdomainAccount.fetch(subdomainPda("team"));
+      expect(subdomain.owner.toBase58()).to.equal(user3.publicKey.toBase58());
+    });
+
+    it("rejects transfers of a locked sub-handle", async () => {
+      await program.methods
+        .setSubdomainTransferLock(true)
+        .accountsPartial({ owner: user1.publicKey, parent: usernamePda("alice"), subdomain: subdomainPda("team") })
+        .signers([user1])
+        .rpc();
+
+      await expectError(transferSubdomain(user3, "team", user2.publicKey), "SubdomainTransferLocked");
+    });
+
+    it("mints a soulbound token that pins the sub-handle to its owner", async () => {
+      const mint = subdomainMintPda("crew");
+      await program.methods
+        .mintSubdomainToken()
+        .accountsPartial({
+          owner: user3.publicKey,
+          subdomain: subdomainPda("crew"),
+          mint,
+          tokenAccount: getAssociatedTokenAddressSync(mint, user3.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user3])
+        .rpc();
+
+      const token = await getAccount(provider.connection, getAssociatedTokenAddressSync(mint, user3.publicKey));
+      expect(Number(token.amount)).to.equal(1);
+      expect(token.isFrozen).to.be.true;
+      await expectError(transferSubdomain(user3, "crew", user2.publicKey), "SubdomainTransferLocked");
+    });
+
+    it("rejects a revoke from a wallet that does not own the parent", async () => {
+      await expectError(revokeSubdomain(user3, "team", user3.publicKey, false), "Unauthorized");
+    });
+
+    it("revokes a tokenized sub-handle and burns its token", async () => {
+      await revokeSubdomain(user1, "crew", user3.publicKey, true);
+
+      expect(await provider.connection.getAccountInfo(subdomainPda("crew"))).to.be.null;
+      const token = await getAccount(
+        provider.connection,
+        getAssociatedTokenAddressSync(subdomainMintPda("crew"), user3.publicKey)
+      );
+      expect(Number(token.amount)).to.equal(0);
+    });
+  });
+
+  describe("Identity Proofs", () => {
+    const domain = "app.neoengine.example";
+
+    const challengeFor = (nonce: number, expiresAt = Math.floor(Date.now() / 1000) + 300) => ({
+      domain,
+      nonce: new BN(nonce),
+      expiresAt: new BN(expiresAt),
+    });
+
+    // Mirrors identity_challenge_message in the program
+    const challengeMessage = (handle: string, challenge: ReturnType<typeof challengeFor>) =>
+      Buffer.from(
+        `neoengine-identity:verify\nhandle:@${canonical(handle)}\ndomain:${challenge.domain}\n` +
+          `nonce:${challenge.nonce.toString()}\nexpires_at:${challenge.expiresAt.toString()}`
+      );
+
+    const verifyIdentity = (signer: Keypair, challenge: ReturnType<typeof challengeFor>) =>
+      program.methods
+        .verifyIdentity("alice", challenge)
+        .accountsPartial({
+          usernameAccount: usernamePda("alice"),
+          identityAccount: identityPda(user1.publicKey),
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: signer.secretKey,
+            message: challengeMessage("alice", challenge),
+          }),
+        ]);
+
+    // verify_identity writes the accepted nonce, so it cannot run as a view
+    const simulateVerify = async (signer: Keypair, challenge: ReturnType<typeof challengeFor>) => {
+      const { raw } = await verifyIdentity(signer, challenge).simulate();
+      const prefix = `Program return: ${program.programId.toBase58()} `;
+      const returned = raw.find((line) => line.startsWith(prefix));
+      return Buffer.from(returned!.slice(prefix.length), "base64")[0] === 1;
+    };
+
+    it("accepts a challenge signed by the handle owner and records its nonce", async () => {
+      expect(await simulateVerify(user1, challengeFor(1))).to.be.true;
+      await verifyIdentity(user1, challengeFor(1)).rpc();
+
+      const identity = await program.account.identityAccount.fetch(identityPda(user1.publicKey));
+      expect(identity.lastChallengeNonce.toNumber()).to.equal(1);
+    });
+
+    it("rejects a replayed nonce", async () => {
+      expect(await simulateVerify(user1, challengeFor(1))).to.be.false;
+      expect(await simulateVerify(user1, challengeFor(2))).to.be.true;
+    });
+
+    it("rejects a challenge signed by another wallet", async () => {
+      expect(await simulateVerify(user2, challengeFor(3))).to.be.false;
+    });
+
+    it("rejects an expired challenge", async () => {
+      expect(await simulateVerify(user1, challengeFor(4, Math.floor(Date.now() / 1000) - 60))).to.be.false;
+    });
+
+    it("rejects a challenge without a domain", async () => {
+      await expectError(
+        verifyIdentity(user1, { ...challengeFor(5), domain: "" }).rpc(),
+        "InvalidIdentityChallenge"
+      );
+    });
+  });
+
+  describe("Suspension and Recovery", () => {
+    const rescue = Keypair.generate();
+    const moderationPda = pda(Buffer.from("moderation"));
+    let handleId: Buffer;
+    let suspensionPda: PublicKey;
+
+    const recoveryAccounts = () => ({
+      usernameAccount: usernamePda("alice"),
+      recoveryConfig: pda(Buffer.from("recovery"), handleId),
+      recoveryRequest: pda(Buffer.from("recovery_request"), handleId),
+    });
+
+    before(async () => {
+      const username = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      handleId = username.handleId.toArrayLike(Buffer, "le", 8);
+      suspensionPda = pda(Buffer.from("suspension"), usernamePda("alice").toBuffer(), handleId);
+
+      // The moderation authority appoints itself so this block can moderate
+      await program.methods
+        .configureModeration(authority, [authority])
+        .accountsPartial({
+          signer: authority,
+          registry: registryPda,
+          moderationConfig: moderationPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      await program.methods
+        .initiateRecovery(rescue.publicKey)
+        .accountsPartial({ guardian: user2.publicKey, ...recoveryAccounts(), systemProgram: SystemProgram.programId })
+        .signers([user2])
+        .rpc();
+      await program.methods
+        .approveRecovery()
+        .accountsPartial({ guardian: user3.publicKey, ...recoveryAccounts() })
+        .signers([user3])
+        .rpc();
+
+      await program.methods
+        .suspendHandle(3)
+        .accountsPartial({
+          moderator: authority,
+          moderationConfig: moderationPda,
+          usernameAccount: usernamePda("alice"),
+          suspension: suspensionPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    });
+
+    it("rejects recovery of a suspended handle", async () => {
+      const mint = mintPda("alice");
+      await expectError(
+        program.methods
+          .executeRecovery()
+          .accountsPartial({
+            newWallet: rescue.publicKey,
+            ...recoveryAccounts(),
+            initiator: user2.publicKey,
+            oldIdentity: identityPda(user1.publicKey),
+            newIdentity: identityPda(rescue.publicKey),
+            mint,
+            oldTokenAccount: getAssociatedTokenAddressSync(mint, user1.publicKey),
+            newTokenAccount: getAssociatedTokenAddressSync(mint, rescue.publicKey),
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([rescue])
+          .rpc(),
+        "HandleSuspended"
+      );
+    });
+
+    it("lifts the suspension and leaves the request for the owner to cancel", async () => {
+      await program.methods
+        .resolveSuspension(true)
+        .accountsPartial({
+          moderator: authority,
+          moderationConfig: moderationPda,
+          usernameAccount: usernamePda("alice"),
+          suspension: suspensionPda,
+        })
+        .rpc();
+      expect((await program.account.usernameAccount.fetch(usernamePda("alice"))).suspended).to.be.false;
+
+      await program.methods
+        .cancelRecovery()
+        .accountsPartial({ owner: user1.publicKey, ...recoveryAccounts(), initiator: user2.publicKey })
+        .signers([user1])
+        .rpc();
+      expect(await provider.connection.getAccountInfo(recoveryAccounts().recoveryRequest)).to.be.null;
+    });
+  });
+
+  describe("Attestations", () => {
+    const issuerAuthority = Keypair.generate();
+    const issuerPda = pda(Buffer.from("issuer"), issuerAuthority.publicKey.toBuffer());
+    const SCHEMA = 1;
+    let attestationPda: PublicKey;
+
+    const attest = (expiresAt: BN | null) =>
+      program.methods
+        .attest(SCHEMA, expiresAt, null)
+        .accountsPartial({
+          issuerAuthority: issuerAuthority.publicKey,
+          issuer: issuerPda,
+          usernameAccount: usernamePda("alice"),
+          attestation: attestationPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([issuerAuthority])
+        .rpc();
+
+    const setIssuerActive = (active: boolean) =>
+      program.methods
+        .setIssuerActive(active)
+        .accountsPartial({ authority, registry: registryPda, issuer: issuerPda })
+        .rpc();
+
+    const hasAttestation = () =>
+      program.methods
+        .hasAttestation(SCHEMA)
+        .accountsPartial({ usernameAccount: usernamePda("alice"), issuer: issuerPda, attestation: attestationPda })
+        .view();
+
+    const revoke = () =>
+      program.methods
+        .revokeAttestation()
+        .accountsPartial({ issuerAuthority: issuerAuthority.publicKey, attestation: attestationPda })
+        .signers([issuerAuthority])
+        .rpc();
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(issuerAuthority.publicKey, LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+
+      const { handleId } = await program.account.usernameAccount.fetch(usernamePda("alice"));
+      attestationPda = pda(
+        Buffer.from("attestation"),
+        handleId.toArrayLike(Buffer, "le", 8),
+        issuerAuthority.publicKey.toBuffer(),
+        u32(SCHEMA)
+      );
+    });
+
+    it("rejects issuer registration by a non-authority", async () => {
+      await expectError(
+        program.methods
+          .registerIssuer("Rogue KYC")
+          .accountsPartial({
+            authority: user1.publicKey,
+            registry: registryPda,
+            issuerAuthority: user1.publicKey,
+            issuer: pda(Buffer.from("issuer"), user1.publicKey.toBuffer()),
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("registers an issuer that attests to a handle", async () => {
+      await program.methods
+        .registerIssuer("Example KYC")
+        .accountsPartial({
+          authority,
+          registry: registryPda,
+          issuerAuthority: issuerAuthority.publicKey,
+          issuer: issuerPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      await attest(null);
+
+      const attestation = await program.account.attestation.fetch(attestationPda);
+      expect(attestation.issuer.toBase58()).to.equal(issuerAuthority.publicKey.toBase58());
+      expect(attestation.schema).to.equal(SCHEMA);
+      expect(attestation.revoked).to.be.false;
+      expect(await hasAttestation()).to.be.true;
+    });
+
+    it("rejects an attestation that has already expired", async () => {
+      await expectError(attest(new BN(Math.floor(Date.now() / 1000) - 60)), "InvalidAttestationExpiry");
+    });
+
+    it("stops counting attestations from a disabled issuer", async () => {
+      await setIssuerActive(false);
+      expect(await hasAttestation()).to.be.false;
+      await expectError(attest(null), "IssuerInactive");
+
+      await setIssuerActive(true);
+      expect(await hasAttestation()).to.be.true;
+    });
+
+    it("lets the issuer revoke an attestation once", async () => {
+      await revoke();
+      expect(await hasAttestation()).to.be.false;
+      await expectError(revoke(), "AttestationRevoked");
+    });
+  });
+
+  describe("Burning Handles", () => {
+    const burner = Keypair.generate();
+
+    const burnUsername = (owner: Keypair, handle: string, quarantined: boolean) =>
+      program.methods
+        .burnUsername()
+        .accountsPartial({
+          owner: owner.publicKey,
+          registry: registryPda,
+          usernameAccount: usernamePda(handle),
+          handleSkeleton: pda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+          identityAccount: identityPda(owner.publicKey),
+          tombstone: quarantined ? pda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))) : null,
+          mint: mintPda(handle),
+          tokenAccount: getAssociatedTokenAddressSync(mintPda(handle), owner.publicKey),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+
+    const setQuarantine = (seconds: number) =>
+      program.methods
+        .updateRegistryConfig({ ...registryConfig(authority), quarantineSeconds: new BN(seconds) })
+        .accountsPartial({ authority, usernameRegistry: registryPda })
+        .rpc();
+
+    before(async () => {
+      const signature = await provider.connection.requestAirdrop(burner.publicKey, 2 * LAMPORTS_PER_SOL);
+      const latest = await provider.connection.getLatestBlockhash();
+      await provider.connection.confirmTransaction({ signature, ...latest });
+    });
+
+    after(async () => {
+      await setQuarantine(0);
+    });
+
+    it("rejects a burn by a wallet that does not own the handle", async () => {
+      await createUsername(burner, "burnfree");
+      await expectError(burnUsername(user2, "burnfree", false), "Unauthorized");
+    });
+
+    it("burns a handle, closes its accounts and lets the name be registered under a new handle id", async () => {
+      const { handleId } = await program.account.usernameAccount.fetch(usernamePda("burnfree"));
+      await burnUsername(burner, "burnfree", false);
+
+      expect(await provider.connection.getAccountInfo(usernamePda("burnfree"))).to.be.null;
+      expect(await provider.connection.getAccountInfo(identityPda(burner.publicKey))).to.be.null;
+      expect(
+        await provider.connection.getAccountInfo(getAssociatedTokenAddressSync(mintPda("burnfree"), burner.publicKey))
+      ).to.be.null;
+
+      await createUsername(burner, "burnfree");
+      const reissued = await program.account.usernameAccount.fetch(usernamePda("burnfree"));
+      expect(reissued.handleId.toNumber()).to.be.greaterThan(handleId.toNumber());
+    });
+
+    it("quarantines a burned name while the registry has a quarantine period", async () => {
+      await setQuarantine(3600);
+      await burnUsername(burner, "burnfree", true);
+
+      const check = await checkUsername("burnfree");
+      expect(check.available).to.be.false;
+      expect(check.quarantineUntil).to.not.be.null;
+      await expectError(createUsername(burner, "burnfree"), "UsernameQuarantined");
     });
   });
-});
\ No newline at end of file
+});
@@ -0,0 +1,946 @@
+import * as anchor from "@coral-xyz/anchor";
+import { BN, Program } from "@coral-xyz/anchor";
+import { NeoengineIdentity } from "../target/types/neoengine_identity";
+import { NeoengineProfile } from "../target/types/neoengine_profile";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SYSVAR_RENT_PUBKEY, SystemProgram } from "@solana/web3.js";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccount,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  transfer,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import { readFileSync } from "fs";
+
+const BPF_LOADER_UPGRADEABLE = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+// MAX_USERNAME_LEN - MIN_USERNAME_LEN + 1 in neoengine-identity
+const PRICE_TABLE_LEN = 18;
+
+// Mirrors canonical_username and username_skeleton in neoengine-identity
+function skeleton(handle: string): string {
+  const folded = [...handle.replace(/^@/, "").toLowerCase()]
+    .map((c) => ({ "0": "o", "1": "l", i: "l", ".": "_", "-": "_" } as Record<string, string>)[c] ?? c)
+    .join("");
+  return folded.split("rn").join("m").split("vv").join("w");
+}
+
+describe("neoengine-profile", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.NeoengineProfile as Program<NeoengineProfile>;
+  const identity = anchor.workspace.NeoengineIdentity as Program<NeoengineIdentity>;
+  const authority = provider.wallet.publicKey;
+
+  const owner = Keypair.generate();
+  const reader = Keypair.generate();
+  const stranger = Keypair.generate();
+  const verifier = Keypair.generate();
+
+  const pda = (...seeds: (Buffer | Uint8Array)[]) =>
+    PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const identityPda = (...seeds: (Buffer | Uint8Array)[]) =>
+    PublicKey.findProgramAddressSync(seeds, identity.programId)[0];
+
+  const configPda = pda(Buffer.from("profile_config"));
+  const profilePda = (wallet: PublicKey) => pda(Buffer.from("profile"), wallet.toBuffer());
+  const tombstonePda = (wallet: PublicKey) => pda(Buffer.from("profile_tombstone"), wallet.toBuffer());
+  const registryPda = identityPda(Buffer.from("username_registry"));
+  const usernamePda = (handle: string) => identityPda(Buffer.from("username"), Buffer.from(handle));
+
+  const airdrop = async (wallet: PublicKey) => {
+    const signature = await provider.connection.requestAirdrop(wallet, 2 * LAMPORTS_PER_SOL);
+    const latest = await provider.connection.getLatestBlockhash();
+    await provider.connection.confirmTransaction({ signature, ...latest });
+  };
+
+  // Registers a soulbound handle through neoengine-identity
+  const createHandle = async (wallet: Keypair, handle: string) => {
+    const { treasury } = await identity.account.usernameRegistry.fetch(registryPda);
+    const mint = identityPda(Buffer.from("mint"), Buffer.from(handle));
+    await identity.methods
+      .createUsername(handle)
+      .accountsPartial({
+        user: wallet.publicKey,
+        fee: {
+          registry: registryPda,
+          treasury,
+          payerDsxAccount: null,
+          treasuryDsxAccount: null,
+        },
+        usernameAccount: usernamePda(handle),
+        handleSkeleton: identityPda(Buffer.from("skeleton"), Buffer.from(skeleton(handle))),
+        tombstone: identityPda(Buffer.from("tombstone"), Buffer.from(skeleton(handle))),
+        identityAccount: identityPda(Buffer.from("identity"), wallet.publicKey.toBuffer()),
+        mint,
+        tokenAccount: getAssociatedTokenAddressSync(mint, wallet.publicKey),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([wallet])
+      .rpc();
+  };
+
+  const createProfile = (wallet: Keypair, handle: string, name: string) =>
+    program.methods
+      .createProfile(`https://neoengine.example/${handle}.json`, name, "gm", "PT", "https://neoengine.example")
+      .accountsPartial({
+        owner: wallet.publicKey,
+        profileState: profilePda(wallet.publicKey),
+        tombstone: tombstonePda(wallet.publicKey),
+        usernameAccount: usernamePda(handle),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([wallet])
+      .rpc();
+
+  const setVerified = (signer: Keypair | null, kind: any, verified: boolean) =>
+    program.methods
+      .setVerifiedStatus(kind, verified)
+      .accountsPartial({
+        authority: signer ? signer.publicKey : authority,
+        config: configPda,
+        profileState: profilePda(owner.publicKey),
+      })
+      .signers(signer ? [signer] : [])
+      .rpc();
+
+  const expectError = async (tx: Promise<unknown>, message: string) => {
+    try {
+      await tx;
+    } catch (err: any) {
+      expect(`${err}${(err.logs ?? []).join("\n")}`).to.include(message);
+      return;
+    }
+    expect.fail(`expected failure with ${message}`);
+  };
+
+  before(async () => {
+    for (const wallet of [owner, reader, stranger, verifier]) {
+      await airdrop(wallet.publicKey);
+    }
+
+    // The identity suite sets the registry up when both run together
+    if (!(await identity.account.usernameRegistry.fetchNullable(registryPda))) {
+      await identity.methods
+        .initializeRegistry({
+          authority,
+          treasury: Keypair.generate().publicKey,
+          feeCurrency: { lamports: {} },
+          dsxMint: PublicKey.default,
+          lengthPrices: Array.from({ length: PRICE_TABLE_LEN }, () => new BN(0.01 * LAMPORTS_PER_SOL)),
+          renameCooldownSeconds: new BN(0),
+          quarantineSeconds: new BN(0),
+          auctionOnlyMaxLength: 0,
+        })
+        .accountsPartial({
+          authority,
+          usernameRegistry: registryPda,
+          program: identity.programId,
+          programData: PublicKey.findProgramAddressSync([identity.programId.toBuffer()], BPF_LOADER_UPGRADEABLE)[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    await createHandle(owner, "profiler");
+    await createHandle(reader, "reader");
+    await createProfile(owner, "profiler", "Profiler");
+    await createProfile(reader, "reader", "Reader");
+  });
+
+  describe("Config and Verification", () => {
+    it("rejects config initialization by a wallet that is not the upgrade authority", async () => {
+      await expectError(
+        program.methods
+          .initializeConfig([])
+          .accountsPartial({
+            admin: stranger.publicKey,
+            config: configPda,
+            program: program.programId,
+            programData: PublicKey.findProgramAddressSync([program.programId.toBuffer()], BPF_LOADER_UPGRADEABLE)[0],
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("initializes the config with the upgrade authority as admin", async () => {
+      await program.methods
+        .initializeConfig([verifier.publicKey])
+        .accountsPartial({
+          admin: authority,
+          config: configPda,
+          program: program.programId,
+          programData: PublicKey.findProgramAddressSync([program.programId.toBuffer()], BPF_LOADER_UPGRADEABLE)[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+      const config = await program.account.profileConfig.fetch(configPda);
+      expect(config.admin.toBase58()).to.equal(authority.toBase58());
+      expect(config.verifiers.map((v) => v.toBase58())).to.deep.equal([verifier.publicKey.toBase58()]);
+    });
+
+    it("rejects verification by a wallet that is neither admin nor verifier", async () => {
+      await expectError(setVerified(stranger, { team: {} }, true), "Unauthorized");
+      await expectError(setVerified(owner, { team: {} }, true), "Unauthorized");
+    });
+
+    it("grants several verification kinds from the admin and a verifier", async () => {
+      await setVerified(verifier, { creator: {} }, true);
+      await setVerified(null, { team: {} }, true);
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.verifications.map((v) => Object.keys(v.kind)[0])).to.deep.equal(["creator", "team"]);
+      expect(profile.verifications[0].verifier.toBase58()).to.equal(verifier.publicKey.toBase58());
+    });
+
+    it("removes one verification kind and keeps the others", async () => {
+      await setVerified(verifier, { creator: {} }, false);
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.verifications.map((v) => Object.keys(v.kind)[0])).to.deep.equal(["team"]);
+    });
+
+    it("rejects config updates from a non-admin", async () => {
+      await expectError(
+        program.methods
+          .updateConfig(stranger.publicKey, [stranger.publicKey])
+          .accountsPartial({ admin: stranger.publicKey, config: configPda })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("Partial Updates", () => {
+    const updateFields = (wallet: Keypair, update: Record<string, string | null>) =>
+      program.methods
+        .updateProfileFields({
+          metadataUri: null,
+          name: null,
+          bio: null,
+          country: null,
+          website: null,
+          ...update,
+        })
+        .accountsPartial({
+          owner: wallet.publicKey,
+          profileState: profilePda(wallet.publicKey),
+          nftMetadata: null,
+          profileAuthority: null,
+          tokenMetadataProgram: null,
+        })
+        .signers([wallet])
+        .rpc();
+
+    it("updates only the provided fields", async () => {
+      const before = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      await updateFields(owner, { bio: "building on solana", country: "PT" });
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.bio).to.equal("building on solana");
+      expect(profile.country).to.equal("PT");
+      expect(profile.name).to.equal(before.name);
+      expect(profile.website).to.equal(before.website);
+      expect(profile.updateCount).to.equal(before.updateCount + 1);
+    });
+
+    it("rejects an update that changes nothing", async () => {
+      await expectError(updateFields(owner, { country: "PT" }), "NoFieldsChanged");
+    });
+
+    it("rejects a field over its length limit", async () => {
+      await expectError(updateFields(owner, { bio: "x".repeat(201) }), "InvalidBio");
+      await expectError(updateFields(owner, { website: "x".repeat(101) }), "InvalidWebsite");
+    });
+
+    it("rejects updates to another wallet's profile", async () => {
+      await expectError(
+        program.methods
+          .updateProfileFields({ metadataUri: null, name: "Mallory", bio: null, country: null, website: null })
+          .accountsPartial({
+            owner: stranger.publicKey,
+            profileState: profilePda(owner.publicKey),
+            nftMetadata: null,
+            profileAuthority: null,
+            tokenMetadataProgram: null,
+          })
+          .signers([stranger])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+    });
+  });
+
+  describe("Handle Binding", () => {
+    const syncHandle = (wallet: Keypair, handle: string) =>
+      program.methods
+        .syncHandle()
+        .accountsPartial({
+          owner: wallet.publicKey,
+          profileState: profilePda(wallet.publicKey),
+          usernameAccount: usernamePda(handle),
+          nftMetadata: null,
+          profileAuthority: null,
+          tokenMetadataProgram: null,
+        })
+        .signers([wallet])
+        .rpc();
+
+    const migrateRecoveredProfile = (newOwner: Keypair, handle: string, oldOwner: PublicKey) =>
+      program.methods
+        .migrateRecoveredProfile()
+        .accountsPartial({
+          newOwner: newOwner.publicKey,
+          usernameAccount: usernamePda(handle),
+          oldProfileState: profilePda(oldOwner),
+          newProfileState: profilePda(newOwner.publicKey),
+          newTombstone: tombstonePda(newOwner.publicKey),
+          nftMint: null,
+          oldNftTokenAccount: null,
+          newNftTokenAccount: null,
+          nftMetadata: null,
+          nftMasterEdition: null,
+          profileAuthority: null,
+          tokenProgram: null,
+          associatedTokenProgram: null,
+          tokenMetadataProgram: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newOwner])
+        .rpc();
+
+    it("binds a new profile to the signer's handle", async () => {
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.sbtHandle).to.equal("profiler");
+      expect(profile.usernameAccount.toBase58()).to.equal(usernamePda("profiler").toBase58());
+      expect(profile.version).to.equal(2);
+    });
+
+    it("rejects a profile for a handle the signer does not own", async () => {
+      await expectError(createProfile(stranger, "profiler", "Impostor"), "HandleNotOwned");
+    });
+
+    it("re-syncs a profile to a handle its owner holds", async () => {
+      await syncHandle(owner, "profiler");
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.sbtHandle).to.equal("profiler");
+    });
+
+    it("rejects a sync to someone else's handle", async () => {
+      await expectError(syncHandle(owner, "reader"), "HandleNotOwned");
+    });
+
+    it("rejects a recovery migration by a wallet that does not hold the handle", async () => {
+      await expectError(migrateRecoveredProfile(stranger, "profiler", owner.publicKey), "HandleNotOwned");
+      await expectError(migrateRecoveredProfile(reader, "reader", owner.publicKey), "HandleNotOwned");
+    });
+
+    it("rejects a recovery migration onto the profile's own owner", async () => {
+      await expectError(migrateRecoveredProfile(owner, "profiler", owner.publicKey), "Unauthorized");
+    });
+  });
+
+  describe("Cosmetic Equipment", () => {
+    // Fixture accounts from Anchor.toml: the equipper's profile carries
+    // PROFILE_MINT, FRAME is staked to it by the equipper and LENT by
+    // another wallet, both as "frame" cosmetics of COSMETICS_PROGRAM
+    const equipper = Keypair.fromSecretKey(
+      Uint8Array.from(JSON.parse(readFileSync("tests/fixtures/equipper.json", "utf8")))
+    );
+    const COSMETICS_PROGRAM = new PublicKey("HUsK6ekwPp9yJwB3gxCXjgHZetoXHZWa9c3sQgdryXBz");
+    const PROFILE_MINT = new PublicKey("CkY782orXVRPQTEyEboPykm5fotDfzmkfWq6nez8b7Hg");
+    const FRAME = new PublicKey("5tEpwdSVCzteMdoAix1See9zqRsrB6iwFVcbf7mcPcJL");
+    const LENT = new PublicKey("8LZ23qzj23sZy7eVUrJiZxQDmS8C8fY4ncy8gFSETFnf");
+
+    const stakeRecordPda = (cosmeticMint: PublicKey, cosmeticsProgram = COSMETICS_PROGRAM) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("stake_record"), cosmeticMint.toBuffer(), PROFILE_MINT.toBuffer()],
+        cosmeticsProgram
+      )[0];
+
+    const setCosmeticsProgram = (cosmeticsProgram: PublicKey) =>
+      program.methods
+        .setCosmeticsProgram(cosmeticsProgram)
+        .accountsPartial({ admin: authority, config: configPda })
+        .rpc();
+
+    const equip = (wallet: Keypair, cosmeticMint: PublicKey, slot: any, profileMint: PublicKey = PROFILE_MINT) =>
+      program.methods
+        .equipCosmetic(slot)
+        .accountsPartial({
+          owner: wallet.publicKey,
+          profileState: profilePda(wallet.publicKey),
+          config: configPda,
+          cosmeticMint,
+          profileMint,
+          stakeRecord: stakeRecordPda(cosmeticMint),
+        })
+        .signers([wallet])
+        .rpc();
+
+    const unequipStale = (stakeRecord: PublicKey) =>
+      program.methods
+        .unequipStale({ frame: {} })
+        .accountsPartial({ profileState: profilePda(equipper.publicKey), config: configPda, stakeRecord })
+        .rpc();
+
+    before(async () => {
+      await setCosmeticsProgram(COSMETICS_PROGRAM);
+    });
+
+    it("rejects a cosmetics program change from a non-admin", async () => {
+      await expectError(
+        program.methods
+          .setCosmeticsProgram(stranger.publicKey)
+          .accountsPartial({ admin: stranger.publicKey, config: configPda })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("requires a profile NFT before equipping", async () => {
+      await expectError(equip(owner, FRAME, { frame: {} }), "ProfileNftRequired");
+    });
+
+    it("rejects a cosmetic staked to the profile NFT by another wallet", async () => {
+      await expectError(equip(equipper, LENT, { frame: {} }), "CosmeticNotStaked");
+    });
+
+    it("rejects a cosmetic in a slot its type does not map to", async () => {
+      await expectError(equip(equipper, FRAME, { background: {} }), "CosmeticSlotMismatch");
+    });
+
+    it("equips a cosmetic the owner staked to the profile NFT", async () => {
+      await equip(equipper, FRAME, { frame: {} });
+
+      const profile = await program.account.profileState.fetch(profilePda(equipper.publicKey));
+      expect(profile.equipment.frame?.cosmeticMint.toBase58()).to.equal(FRAME.toBase58());
+      expect(profile.equipment.frame?.profileMint.toBase58()).to.equal(PROFILE_MINT.toBase58());
+      expect(profile.equipment.background).to.be.null;
+    });
+
+    it("refuses to clear a slot whose stake record is still live", async () => {
+      await expectError(unequipStale(stakeRecordPda(FRAME)), "EquipmentNotStale");
+      await expectError(unequipStale(stakeRecordPda(LENT)), "StakeRecordMismatch");
+    });
+
+    it("lets anyone clear a slot once its stake record is gone", async () => {
+      // Under a new cosmetics program the equipped stake record no longer exists
+      const replacement = Keypair.generate().publicKey;
+      await setCosmeticsProgram(replacement);
+      await unequipStale(stakeRecordPda(FRAME, replacement));
+
+      const profile = await program.account.profileState.fetch(profilePda(equipper.publicKey));
+      expect(profile.equipment.frame).to.be.null;
+      await setCosmeticsProgram(COSMETICS_PROGRAM);
+    });
+  });
+
+  describe("Reputation and Badges", () => {
+    // Writes are CPI-only; the success path needs a deployed caller program
+    // signing with its "profile_reputation" PDA, so these cover the gates
+    const callerProgram = Keypair.generate().publicKey;
+
+    const setReputationPrograms = (programs: PublicKey[], signer: Keypair | null = null) =>
+      program.methods
+        .setReputationPrograms(programs)
+        .accountsPartial({ admin: signer?.publicKey ?? authority, config: configPda })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    const updateScore = (callerAuthority: Keypair, caller: PublicKey) =>
+      program.methods
+        .updateReputationScore(new anchor.BN(100))
+        .accountsPartial({
+          callerAuthority: callerAuthority.publicKey,
+          callerProgram: caller,
+          config: configPda,
+          profileState: profilePda(owner.publicKey),
+        })
+        .signers([callerAuthority])
+        .rpc();
+
+    after(async () => {
+      await setReputationPrograms([]);
+    });
+
+    it("lets only the admin set the reputation programs", async () => {
+      await expectError(setReputationPrograms([callerProgram], stranger), "Unauthorized");
+      await expectError(
+        setReputationPrograms(Array.from({ length: 6 }, () => Keypair.generate().publicKey)),
+        "InvalidReputationPrograms"
+      );
+
+      await setReputationPrograms([callerProgram]);
+      const config = await program.account.profileConfig.fetch(configPda);
+      expect(config.reputationPrograms.map((p) => p.toBase58())).to.deep.equal([callerProgram.toBase58()]);
+    });
+
+    it("rejects a direct call signed by a wallet", async () => {
+      await expectError(updateScore(stranger, callerProgram), "Unauthorized");
+      await expectError(
+        program.methods
+          .awardBadge("early-adopter")
+          .accountsPartial({
+            callerAuthority: stranger.publicKey,
+            callerProgram,
+            config: configPda,
+            profileState: profilePda(owner.publicKey),
+          })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("rejects a caller program outside the allowlist", async () => {
+      await expectError(updateScore(stranger, program.programId), "Unauthorized");
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.reputationScore.toNumber()).to.equal(0);
+      expect(profile.badges).to.have.length(0);
+    });
+  });
+
+  describe("Layout Migration", () => {
+    // Fixture account from Anchor.toml: a v1 profile with a Twitter handle
+    // and an empty Discord handle
+    const legacyOwner = new PublicKey("2rZXD1Ei7m3JKgcY7465GmbpMjvwAgc1PYNPufXjjBdG");
+    const legacyPda = profilePda(legacyOwner);
+
+    const migrate = () =>
+      program.methods
+        .migrateProfile()
+        .accountsPartial({ payer: authority, profileState: legacyPda })
+        .rpc();
+
+    it("reads a v1 profile before it is migrated", async () => {
+      const summary = await program.methods
+        .getProfileSummary()
+        .accountsPartial({ profileState: legacyPda })
+        .view();
+
+      expect(summary.owner.toBase58()).to.equal(legacyOwner.toBase58());
+      expect(summary.name).to.equal("Legacy");
+      expect(summary.sbtHandle).to.equal("");
+      expect(summary.socialLinks).to.have.length(1);
+    });
+
+    it("migrates a v1 profile to the current layout", async () => {
+      await migrate();
+
+      const profile = await program.account.profileState.fetch(legacyPda);
+      expect(profile.version).to.equal(2);
+      expect(profile.owner.toBase58()).to.equal(legacyOwner.toBase58());
+      expect(profile.sbtHandle).to.equal("");
+      expect(profile.usernameAccount.toBase58()).to.equal(PublicKey.default.toBase58());
+      expect(profile.bio).to.equal("Created before versioning");
+      expect(profile.website).to.equal("https://legacy.example");
+      expect(profile.updateCount).to.equal(3);
+      expect(profile.createdAt.toNumber()).to.equal(1_690_000_000);
+      expect(profile.socialLinks).to.have.length(1);
+      expect(profile.socialLinks[0].platform).to.deep.equal({ twitter: {} });
+      expect(profile.socialLinks[0].handle).to.equal("legacy_tw");
+      expect(profile.socialLinks[0].verifiedBy).to.be.null;
+    });
+
+    it("rejects migrating a profile twice", async () => {
+      await expectError(migrate(), "AlreadyMigrated");
+      await expectError(
+        program.methods
+          .migrateProfile()
+          .accountsPartial({ payer: authority, profileState: profilePda(owner.publicKey) })
+          .rpc(),
+        "AlreadyMigrated"
+      );
+    });
+  });
+
+  describe("Closing Profiles", () => {
+    const closer = Keypair.generate();
+    const privateFieldPda = (wallet: PublicKey, label: string) =>
+      pda(Buffer.from("private_field"), wallet.toBuffer(), Buffer.from(label));
+    const encryptionKeyPda = (wallet: PublicKey) => pda(Buffer.from("encryption_key"), wallet.toBuffer());
+
+    const closeProfile = (wallet: Keypair) =>
+      program.methods
+        .closeProfile()
+        .accountsPartial({
+          owner: wallet.publicKey,
+          profileState: profilePda(wallet.publicKey),
+          tombstone: tombstonePda(wallet.publicKey),
+          encryptionKey: encryptionKeyPda(wallet.publicKey),
+          nftMint: null,
+          nftTokenAccount: null,
+          nftMetadata: null,
+          nftMasterEdition: null,
+          profileAuthority: null,
+          tokenProgram: null,
+          tokenMetadataProgram: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+
+    before(async () => {
+      await airdrop(closer.publicKey);
+      await createHandle(closer, "closer");
+      await createProfile(closer, "closer", "Closer");
+    });
+
+    it("refuses to close a profile that still has private fields", async () => {
+      await program.methods
+        .setPrivateField("email", Array(12).fill(1), Buffer.from("sealed"), [])
+        .accountsPartial({
+          owner: closer.publicKey,
+          profileState: profilePda(closer.publicKey),
+          privateField: privateFieldPda(closer.publicKey, "email"),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([closer])
+        .rpc();
+
+      await expectError(closeProfile(closer), "PrivateFieldsRemain");
+
+      await program.methods
+        .deletePrivateField("email")
+        .accountsPartial({
+          owner: closer.publicKey,
+          profileState: profilePda(closer.publicKey),
+          privateField: privateFieldPda(closer.publicKey, "email"),
+        })
+        .signers([closer])
+        .rpc();
+    });
+
+    it("closes the profile and its encryption key and leaves a tombstone", async () => {
+      await program.methods
+        .setEncryptionKey(Array(32).fill(7))
+        .accountsPartial({
+          owner: closer.publicKey,
+          encryptionKey: encryptionKeyPda(closer.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([closer])
+        .rpc();
+
+      await closeProfile(closer);
+
+      expect(await provider.connection.getAccountInfo(profilePda(closer.publicKey))).to.be.null;
+      expect(await provider.connection.getAccountInfo(encryptionKeyPda(closer.publicKey))).to.be.null;
+      const tombstone = await program.account.profileTombstone.fetch(tombstonePda(closer.publicKey));
+      expect(tombstone.owner.toBase58()).to.equal(closer.publicKey.toBase58());
+      expect(tombstone.closedAt.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("holds a recreated profile to the cooldown", async () => {
+      await expectError(createProfile(closer, "closer", "Closer"), "ProfileCooldownActive");
+    });
+  });
+
+  describe("Social Links", () => {
+    const oracle = Keypair.generate();
+
+    const setLink = (wallet: Keypair, platform: any, handle: string) =>
+      program.methods
+        .setSocialLink(platform, handle)
+        .accountsPartial({ owner: wallet.publicKey, profileState: profilePda(wallet.publicKey) })
+        .signers([wallet])
+        .rpc();
+
+    const verifyLink = (signer: Keypair, platform: any, handle: string, verified: boolean) =>
+      program.methods
+        .verifySocialLink(platform, handle, verified)
+        .accountsPartial({ oracle: signer.publicKey, config: configPda, profileState: profilePda(reader.publicKey) })
+        .signers([signer])
+        .rpc();
+
+    const setLinkOracles = (oracles: PublicKey[], signer: Keypair | null = null) =>
+      program.methods
+        .setLinkOracles(oracles)
+        .accountsPartial({ admin: signer?.publicKey ?? authority, config: configPda })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    const linksOf = async (wallet: Keypair) =>
+      (await program.account.profileState.fetch(profilePda(wallet.publicKey))).socialLinks;
+
+    before(async () => {
+      await airdrop(oracle.publicKey);
+    });
+
+    after(async () => {
+      await setLinkOracles([]);
+    });
+
+    it("adds and replaces social links", async () => {
+      await setLink(reader, { twitter: {} }, "reader_tw");
+      await setLink(reader, { telegram: {} }, "reader_tg");
+      await setLink(reader, { telegram: {} }, "reader_chat");
+
+      const links = await linksOf(reader);
+      expect(links.map((l) => l.handle)).to.deep.equal(["reader_tw", "reader_chat"]);
+      expect(links[1].platform).to.deep.equal({ telegram: {} });
+    });
+
+    it("rejects empty, overlong and unchanged handles", async () => {
+      await expectError(setLink(reader, { discord: {} }, ""), "InvalidSocialHandle");
+      await expectError(setLink(reader, { discord: {} }, "x".repeat(51)), "InvalidSocialHandle");
+      await expectError(setLink(reader, { twitter: {} }, "reader_tw"), "NoFieldsChanged");
+    });
+
+    it("lets only the admin set the link oracles", async () => {
+      await expectError(setLinkOracles([oracle.publicKey], stranger), "Unauthorized");
+      await setLinkOracles([oracle.publicKey]);
+    });
+
+    it("lets only a link oracle verify a link", async () => {
+      await expectError(verifyLink(stranger, { twitter: {} }, "reader_tw", true), "Unauthorized");
+      await verifyLink(oracle, { twitter: {} }, "reader_tw", true);
+
+      const [twitter] = await linksOf(reader);
+      expect(twitter.verifiedBy?.toBase58()).to.equal(oracle.publicKey.toBase58());
+      expect(twitter.verifiedAt.toNumber()).to.be.greaterThan(0);
+    });
+
+    it("rejects a verification for a handle that has since changed", async () => {
+      await expectError(verifyLink(oracle, { twitter: {} }, "someone_else", true), "SocialLinkChanged");
+      await expectError(verifyLink(oracle, { discord: {} }, "reader", true), "SocialLinkNotFound");
+    });
+
+    it("clears the verification when the owner changes the handle", async () => {
+      await setLink(reader, { twitter: {} }, "reader_new");
+
+      const [twitter] = await linksOf(reader);
+      expect(twitter.handle).to.equal("reader_new");
+      expect(twitter.verifiedBy).to.be.null;
+      expect(twitter.verifiedAt.toNumber()).to.equal(0);
+    });
+
+    it("removes a social link", async () => {
+      await program.methods
+        .removeSocialLink({ telegram: {} })
+        .accountsPartial({ owner: reader.publicKey, profileState: profilePda(reader.publicKey) })
+        .signers([reader])
+        .rpc();
+      expect((await linksOf(reader)).map((l) => l.handle)).to.deep.equal(["reader_new"]);
+
+      await expectError(
+        program.methods
+          .removeSocialLink({ telegram: {} })
+          .accountsPartial({ owner: reader.publicKey, profileState: profilePda(reader.publicKey) })
+          .signers([reader])
+          .rpc(),
+        "SocialLinkNotFound"
+      );
+    });
+  });
+
+  describe("Profile NFTs", () => {
+    const TOKEN_METADATA_PROGRAM = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+    const profileAuthority = pda(Buffer.from("profile_authority"));
+    const metadataPda = (mint: PublicKey, ...suffix: Buffer[]) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM.toBuffer(), mint.toBuffer(), ...suffix],
+        TOKEN_METADATA_PROGRAM
+      )[0];
+
+    let mint: PublicKey;
+
+    const mintProfileNft = async (wallet: Keypair) => {
+      const profile = await program.account.profileState.fetch(profilePda(wallet.publicKey));
+      mint = pda(
+        Buffer.from("profile_mint"),
+        profilePda(wallet.publicKey).toBuffer(),
+        profile.createdAt.toArrayLike(Buffer, "le", 8)
+      );
+      return program.methods
+        .mintProfileNft()
+        .accountsPartial({
+          owner: wallet.publicKey,
+          profileState: profilePda(wallet.publicKey),
+          profileAuthority,
+          mint,
+          tokenAccount: getAssociatedTokenAddressSync(mint, wallet.publicKey),
+          nftMetadata: metadataPda(mint),
+          masterEdition: metadataPda(mint, Buffer.from("edition")),
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM,
+          systemProgram: SystemProgram.programId,
+          rent: SYSVAR_RENT_PUBKEY,
+        })
+        .signers([wallet])
+        .rpc();
+    };
+
+    const updateUri = (uri: string, withNft: boolean) =>
+      program.methods
+        .updateProfileFields({ metadataUri: uri, name: null, bio: null, country: null, website: null })
+        .accountsPartial({
+          owner: reader.publicKey,
+          profileState: profilePda(reader.publicKey),
+          nftMetadata: withNft ? metadataPda(mint) : null,
+          profileAuthority: withNft ? profileAuthority : null,
+          tokenMetadataProgram: withNft ? TOKEN_METADATA_PROGRAM : null,
+        })
+        .signers([reader])
+        .rpc();
+
+    it("mints a frozen one-of-one NFT for a bound profile", async () => {
+      await mintProfileNft(reader);
+
+      const profile = await program.account.profileState.fetch(profilePda(reader.publicKey));
+      expect(profile.profileMint?.toBase58()).to.equal(mint.toBase58());
+
+      const token = await getAccount(provider.connection, getAssociatedTokenAddressSync(mint, reader.publicKey));
+      expect(Number(token.amount)).to.equal(1);
+      expect(token.isFrozen).to.be.true;
+
+      const metadata = await provider.connection.getAccountInfo(metadataPda(mint));
+      expect(metadata?.owner.toBase58()).to.equal(TOKEN_METADATA_PROGRAM.toBase58());
+      expect(metadata?.data.includes(Buffer.from("reader"))).to.be.true;
+    });
+
+    it("rejects a second NFT for the same profile", async () => {
+      await expectError(mintProfileNft(reader), "ProfileNftAlreadyMinted");
+    });
+
+    it("blocks transfers of the profile NFT", async () => {
+      const source = getAssociatedTokenAddressSync(mint, reader.publicKey);
+      const destination = await createAssociatedTokenAccount(provider.connection, reader, mint, stranger.publicKey);
+      await expectError(transfer(provider.connection, reader, source, destination, reader, 1), "0x11");
+    });
+
+    it("requires the NFT accounts to change the URI of a profile with an NFT", async () => {
+      await expectError(updateUri("https://neoengine.example/reader-v2.json", false), "MissingProfileNftAccounts");
+    });
+
+    it("keeps the NFT URI in sync with the profile", async () => {
+      await updateUri("https://neoengine.example/reader-v2.json", true);
+
+      const metadata = await provider.connection.getAccountInfo(metadataPda(mint));
+      expect(metadata?.data.includes(Buffer.from("https://neoengine.example/reader-v2.json"))).to.be.true;
+    });
+  });
+
+  describe("Private Fields", () => {
+    // The program only stores ciphertext and wrapped keys, so fixed bytes
+    // stand in for the output of the neoengine-private-fields crate
+    const label = "contact";
+    const privateFieldPda = (wallet: PublicKey, fieldLabel: string) =>
+      pda(Buffer.from("private_field"), wallet.toBuffer(), Buffer.from(fieldLabel));
+    const encryptionKeyPda = (wallet: PublicKey) => pda(Buffer.from("encryption_key"), wallet.toBuffer());
+    const wrappedFor = (recipient: PublicKey) => ({
+      recipient,
+      ephemeralPubkey: Array(32).fill(3),
+      wrappedKey: Array(48).fill(4),
+    });
+
+    const publishKey = (wallet: Keypair) =>
+      program.methods
+        .setEncryptionKey(Array(32).fill(9))
+        .accountsPartial({
+          owner: wallet.publicKey,
+          encryptionKey: encryptionKeyPda(wallet.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([wallet])
+        .rpc();
+
+    const setField = (fieldLabel: string, recipients: PublicKey[], keyAccounts: PublicKey[] = recipients) =>
+      program.methods
+        .setPrivateField(fieldLabel, Array(12).fill(2), Buffer.from("ciphertext"), recipients.map(wrappedFor))
+        .accountsPartial({
+          owner: owner.publicKey,
+          profileState: profilePda(owner.publicKey),
+          privateField: privateFieldPda(owner.publicKey, fieldLabel),
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          keyAccounts.map((wallet) => ({ pubkey: encryptionKeyPda(wallet), isSigner: false, isWritable: false }))
+        )
+        .signers([owner])
+        .rpc();
+
+    const addRecipient = (recipient: PublicKey) =>
+      program.methods
+        .addPrivateFieldRecipient(label, wrappedFor(recipient))
+        .accountsPartial({
+          owner: owner.publicKey,
+          privateField: privateFieldPda(owner.publicKey, label),
+          recipientKey: encryptionKeyPda(recipient),
+        })
+        .signers([owner])
+        .rpc();
+
+    const revokeRecipient = (recipient: PublicKey) =>
+      program.methods
+        .revokePrivateFieldRecipient(label, recipient)
+        .accountsPartial({ owner: owner.publicKey, privateField: privateFieldPda(owner.publicKey, label) })
+        .signers([owner])
+        .rpc();
+
+    const recipientsOf = async () =>
+      (await program.account.privateField.fetch(privateFieldPda(owner.publicKey, label))).recipients.map((r) =>
+        r.recipient.toBase58()
+      );
+
+    it("requires every recipient to have published an encryption key", async () => {
+      await expectError(setField(label, [reader.publicKey], []), "EncryptionKeyNotPublished");
+      await expectError(setField(label, [reader.publicKey]), "EncryptionKeyNotPublished");
+      await expectError(setField("", []), "InvalidPrivateFieldLabel");
+    });
+
+    it("stores a private field wrapped for the owner's recipients", async () => {
+      await publishKey(reader);
+      await setField(label, [reader.publicKey]);
+
+      const field = await program.account.privateField.fetch(privateFieldPda(owner.publicKey, label));
+      expect(field.owner.toBase58()).to.equal(owner.publicKey.toBase58());
+      expect(Buffer.from(field.ciphertext).toString()).to.equal("ciphertext");
+      expect(await recipientsOf()).to.deep.equal([reader.publicKey.toBase58()]);
+
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.privateFieldCount).to.equal(1);
+    });
+
+    it("grants and revokes access per recipient", async () => {
+      await expectError(addRecipient(verifier.publicKey), "AccountNotInitialized");
+      await publishKey(verifier);
+      await addRecipient(verifier.publicKey);
+      await expectError(addRecipient(verifier.publicKey), "FieldRecipientExists");
+
+      await revokeRecipient(reader.publicKey);
+      expect(await recipientsOf()).to.deep.equal([verifier.publicKey.toBase58()]);
+      await expectError(revokeRecipient(reader.publicKey), "FieldRecipientNotFound");
+    });
+
+    it("deletes a private field and frees its slot", async () => {
+      await program.methods
+        .deletePrivateField(label)
+        .accountsPartial({
+          owner: owner.publicKey,
+          profileState: profilePda(owner.publicKey),
+          privateField: privateFieldPda(owner.publicKey, label),
+        })
+        .signers([owner])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(privateFieldPda(owner.publicKey, label))).to.be.null;
+      const profile = await program.account.profileState.fetch(profilePda(owner.publicKey));
+      expect(profile.privateFieldCount).to.equal(0);
+    });
+  });
+});